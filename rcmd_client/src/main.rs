@@ -1,16 +1,29 @@
 use std::{
     fs,
+    io::{self, Write},
     path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime},
 };
 
+use rcmd_data::{
+    parse_duration, Condition, Dependency, JobSpec, OutputRange, OutputStream, OverlapPolicy,
+    Priority, RetryPolicy, ScheduleSpec, StdinMode, Trigger,
+};
 use structopt::StructOpt;
 
-use crate::operations::{delete, list, output, status, submit};
+use crate::operations::{
+    combined_output, create_schedule, delete, delete_schedule, drain, drain_status, follow_output,
+    list, list_schedules, output, output_range, pause_schedule, raw_output, set_priority, status,
+    stop, stream_stdin, submit, submit_workflow, usage, usage_summary, OutputSinks,
+};
 
 mod operations;
 
 const CA_CERT_NAME: &str = "rootCA.crt";
 const CLIENT_IDENTITY_NAME: &str = "clientKeyCert.pem";
+/// time a stop request may take on top of its grace period
+const STOP_TIMEOUT_MARGIN: Duration = Duration::from_secs(30);
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "rcmd-client")]
@@ -25,27 +38,317 @@ struct Opt {
     operation: Operation,
 }
 
+#[derive(Debug, StructOpt)]
+struct ExecOptions {
+    /// set environment variable for the job, can be repeated
+    #[structopt(long = "env", name = "KEY=VALUE", parse(try_from_str = parse_env_var))]
+    env: Vec<(String, String)>,
+    /// don't inherit the server's environment
+    #[structopt(long)]
+    clear_env: bool,
+    /// directory to start the job in
+    #[structopt(long = "cwd", parse(from_os_str))]
+    working_dir: Option<PathBuf>,
+    /// file mode creation mask in octal, e.g. 022
+    #[structopt(long, parse(try_from_str = parse_umask))]
+    umask: Option<u32>,
+    /// stream this client's stdin to the job
+    #[structopt(long)]
+    stdin: bool,
+    /// provide the content of the given file as the job's stdin
+    #[structopt(long, parse(from_os_str), conflicts_with = "stdin")]
+    input: Option<PathBuf>,
+    /// stop the job if it runs longer than this, e.g. 30s or 2h (default set by the server)
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    timeout: Option<Duration>,
+    /// low, normal or high, orders the job among the client's queued jobs (default normal)
+    #[structopt(long)]
+    priority: Option<Priority>,
+    /// start only after the given job finished, can be repeated
+    /// the condition is on-success (default), on-failure or always, e.g. 3:on-failure
+    #[structopt(long = "after", name = "JOB_ID[:CONDITION]", parse(try_from_str = parse_dependency))]
+    dependencies: Vec<Dependency>,
+    /// run the job up to this many times in total while it exits with a non-zero exit code
+    #[structopt(long, conflicts_with = "stdin")]
+    attempts: Option<u32>,
+    /// only retry these exit codes, e.g. 1,75 (default any non-zero one)
+    #[structopt(long, use_delimiter = true, requires = "attempts")]
+    retry_on: Vec<i32>,
+    /// delay before the first retry, doubled for each further one, e.g. 500ms (default 1s)
+    #[structopt(long, parse(try_from_str = parse_duration), requires = "attempts")]
+    backoff: Option<Duration>,
+    /// upper bound of the delay between retries
+    #[structopt(long, parse(try_from_str = parse_duration), requires = "attempts")]
+    max_backoff: Option<Duration>,
+}
+
+impl ExecOptions {
+    fn job_spec(self, command: &str, args: &[&str]) -> JobSpec {
+        let mut spec = JobSpec::new(command, args);
+        if self.stdin {
+            spec.stdin = StdinMode::Pipe;
+        } else if let Some(input) = self.input {
+            let data = fs::read(&input).expect("could not read input file");
+            spec.stdin = StdinMode::Data(data);
+        }
+        spec.env = self.env.into_iter().collect();
+        spec.clear_env = self.clear_env;
+        spec.working_dir = self.working_dir;
+        spec.umask = self.umask;
+        spec.timeout = self.timeout;
+        spec.priority = self.priority.unwrap_or_default();
+        spec.dependencies = self.dependencies;
+        if let Some(attempts) = self.attempts {
+            let mut retry = RetryPolicy::new(attempts);
+            retry.exit_codes = self.retry_on;
+            if let Some(backoff) = self.backoff {
+                retry.backoff = backoff;
+            }
+            retry.max_backoff = self.max_backoff;
+            spec.retry = Some(retry);
+        }
+        spec
+    }
+}
+
+fn parse_env_var(var: &str) -> Result<(String, String), String> {
+    match var.split_once('=') {
+        Some((key, value)) => Ok((key.to_string(), value.to_string())),
+        None => Err(format!("expected KEY=VALUE, got {}", var)),
+    }
+}
+
+fn parse_dependency(dependency: &str) -> Result<Dependency, String> {
+    let (job, condition) = match dependency.split_once(':') {
+        Some((job, condition)) => (job, condition.parse()?),
+        None => (dependency, Condition::OnSuccess),
+    };
+    let job = job
+        .parse()
+        .map_err(|_| format!("invalid job id: {}", job))?;
+    Ok(Dependency { job, condition })
+}
+
+fn parse_time(time: &str) -> Result<SystemTime, String> {
+    humantime::parse_rfc3339_weak(time).map_err(|e| format!("invalid time {}: {}", time, e))
+}
+
+fn parse_umask(umask: &str) -> Result<u32, String> {
+    u32::from_str_radix(umask, 8).map_err(|_| format!("invalid octal umask: {}", umask))
+}
+
+#[derive(Debug, StructOpt)]
+struct OutputOptions {
+    /// print output as it is produced until the job finished, then exit with the job's exit code
+    #[structopt(long, conflicts_with_all = &["stream", "from", "limit", "head", "tail"])]
+    follow: bool,
+    /// print stdout and stderr interleaved in the order they were produced, each line tagged with its stream
+    #[structopt(
+        long,
+        conflicts_with_all = &["follow", "raw", "stdout-file", "stderr-file", "stream", "from", "limit", "head", "tail"]
+    )]
+    combined: bool,
+    /// like --combined, with each line prefixed by the time it was produced
+    #[structopt(
+        long,
+        conflicts_with_all = &["follow", "raw", "stdout-file", "stderr-file", "stream", "from", "limit", "head", "tail"]
+    )]
+    timestamps: bool,
+    /// write stdout and stderr of the job unchanged to this client's stdout and stderr
+    #[structopt(long)]
+    raw: bool,
+    /// write stdout of the job unchanged to the given file
+    #[structopt(long, parse(from_os_str))]
+    stdout_file: Option<PathBuf>,
+    /// write stderr of the job unchanged to the given file
+    #[structopt(long, parse(from_os_str))]
+    stderr_file: Option<PathBuf>,
+    /// only print the given stream (stdout or stderr), default stdout if a range is given
+    #[structopt(long, conflicts_with_all = &["raw", "stdout-file", "stderr-file"])]
+    stream: Option<OutputStream>,
+    /// byte offset to start at, e.g. the next offset printed by a previous call
+    #[structopt(long, conflicts_with_all = &["head", "tail", "raw", "stdout-file", "stderr-file"])]
+    from: Option<u64>,
+    /// maximum number of bytes to print
+    #[structopt(long, conflicts_with_all = &["head", "tail", "raw", "stdout-file", "stderr-file"])]
+    limit: Option<u64>,
+    /// only print the first <head> lines
+    #[structopt(long, conflicts_with_all = &["tail", "raw", "stdout-file", "stderr-file"])]
+    head: Option<usize>,
+    /// only print the last <tail> lines
+    #[structopt(long, conflicts_with_all = &["raw", "stdout-file", "stderr-file"])]
+    tail: Option<usize>,
+    /// print the output of the given attempt of a retried job instead of the current one
+    #[structopt(
+        long,
+        conflicts_with_all = &["follow", "combined", "timestamps", "raw", "stdout-file", "stderr-file", "stream", "from", "limit", "head", "tail"]
+    )]
+    attempt: Option<u32>,
+}
+
+impl OutputOptions {
+    /// selected stream and range, None if the whole output was requested
+    fn range(&self) -> Option<(OutputStream, OutputRange)> {
+        let range = match (self.head, self.tail) {
+            (Some(head), _) => OutputRange::Head(head),
+            (_, Some(tail)) => OutputRange::Tail(tail),
+            _ if self.stream.is_some() || self.from.is_some() || self.limit.is_some() => {
+                OutputRange::Bytes {
+                    from: self.from.unwrap_or(0),
+                    limit: self.limit,
+                }
+            }
+            _ => return None,
+        };
+        Some((self.stream.unwrap_or(OutputStream::Stdout), range))
+    }
+
+    /// true if output should be written unchanged instead of as text
+    fn verbatim(&self) -> bool {
+        self.raw || self.stdout_file.is_some() || self.stderr_file.is_some()
+    }
+
+    fn sinks(&self) -> Result<OutputSinks, String> {
+        let open = |path: &Path| {
+            fs::File::create(path).map_err(|e| format!("could not create {:?}: {}", path, e))
+        };
+        let stdout: Box<dyn Write> = match &self.stdout_file {
+            Some(path) => Box::new(open(path)?),
+            None => Box::new(io::stdout()),
+        };
+        let stderr: Box<dyn Write> = match &self.stderr_file {
+            Some(path) => Box::new(open(path)?),
+            None => Box::new(io::stderr()),
+        };
+        Ok(OutputSinks { stdout, stderr })
+    }
+}
+
+/// exits with the given exit code, or prints the error and exits with 1
+fn exit_with(result: Result<i32, String>) -> ! {
+    match result {
+        Ok(exit_code) => process::exit(exit_code),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
 #[derive(Debug, StructOpt)]
 enum Operation {
     Exec {
+        #[structopt(flatten)]
+        options: ExecOptions,
         #[structopt(name = "COMMAND")]
         command: String,
         #[structopt(name = "ARGUMENTS")]
         args: Vec<String>,
     },
-    List,
+    /// submit jobs depending on each other, described by a JSON file like
+    /// {"jobs": [{"name": "build", "spec": {...}}, {"name": "test", "spec": {...}, "after": [{"job": "build"}]}]}
+    Workflow {
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file: PathBuf,
+    },
+    List {
+        /// list status, pid and timestamps of each job
+        #[structopt(long, short)]
+        detailed: bool,
+    },
     Status {
         #[structopt(name = "JOB_ID")]
         id: u64,
     },
+    /// resources used by a finished job, or by all finished jobs of this client if no id is given
+    Usage {
+        #[structopt(name = "JOB_ID")]
+        id: Option<u64>,
+    },
     Output {
         #[structopt(name = "JOB_ID")]
         id: u64,
+        #[structopt(flatten)]
+        options: OutputOptions,
     },
     Delete {
         #[structopt(name = "JOB_ID")]
         id: u64,
     },
+    /// stop job with a signal, killing it if still running after the grace period
+    Stop {
+        #[structopt(name = "JOB_ID")]
+        id: u64,
+        /// signal to send first, e.g. TERM, INT or HUP (default TERM)
+        #[structopt(long)]
+        signal: Option<String>,
+        /// time to wait before killing the job, e.g. 10s or 500ms
+        #[structopt(long)]
+        grace: Option<String>,
+    },
+    /// send a signal to a running job, e.g. HUP, USR1, STOP or CONT
+    Signal {
+        #[structopt(name = "JOB_ID")]
+        id: u64,
+        #[structopt(name = "SIGNAL")]
+        signal: String,
+    },
+    /// change the priority of a queued job to low, normal or high
+    Priority {
+        #[structopt(name = "JOB_ID")]
+        id: u64,
+        #[structopt(name = "PRIORITY")]
+        priority: Priority,
+    },
+    /// create, list, pause, resume or delete schedules submitting jobs
+    Schedule(ScheduleOperation),
+    /// let the server reject new jobs and exit once all jobs finished (admin clients only)
+    Drain {
+        /// stop the jobs still running after this long, e.g. 30m (default set by the server)
+        #[structopt(long, conflicts_with = "status")]
+        timeout: Option<String>,
+        /// only show whether the server drains and how many jobs remain
+        #[structopt(long)]
+        status: bool,
+    },
+}
+
+// parsed once from the command line, its size does not matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, StructOpt)]
+enum ScheduleOperation {
+    /// submit the job repeatedly by a cron expression or once at the given time
+    Create {
+        /// minute, hour, day of month, month and day of week in UTC, e.g. "*/15 8-18 * * mon-fri"
+        #[structopt(long, required_unless = "at")]
+        cron: Option<String>,
+        /// time in UTC, e.g. 2024-01-01T12:00:00Z or "2024-01-01 12:00:00"
+        #[structopt(long, parse(try_from_str = parse_time), conflicts_with = "cron")]
+        at: Option<SystemTime>,
+        /// skip, queue or allow, what to do if the previous job still runs (default skip)
+        #[structopt(long)]
+        overlap: Option<OverlapPolicy>,
+        #[structopt(flatten)]
+        options: ExecOptions,
+        #[structopt(name = "COMMAND")]
+        command: String,
+        #[structopt(name = "ARGUMENTS")]
+        args: Vec<String>,
+    },
+    List,
+    Pause {
+        #[structopt(name = "SCHEDULE_ID")]
+        id: u64,
+    },
+    Resume {
+        #[structopt(name = "SCHEDULE_ID")]
+        id: u64,
+    },
+    /// delete the schedule, the jobs it submitted are kept
+    Delete {
+        #[structopt(name = "SCHEDULE_ID")]
+        id: u64,
+    },
 }
 
 fn main() {
@@ -64,22 +367,122 @@ fn main() {
     let client_identity =
         reqwest::Identity::from_pem(&client_identity).expect("could not read client key/cert");
 
-    let client = reqwest::blocking::Client::builder()
+    let mut client_builder = reqwest::blocking::Client::builder()
         .add_root_certificate(ca_cert)
         .identity(client_identity)
-        .use_rustls_tls()
-        .build()
-        .expect("could not build http client");
+        .use_rustls_tls();
+    if let Operation::Output {
+        options: OutputOptions { follow: true, .. },
+        ..
+    } = opt.operation
+    {
+        // a followed job may run for arbitrarily long
+        client_builder = client_builder.timeout(None);
+    }
+    if let Operation::Stop { grace, .. } = &opt.operation {
+        // the server answers once the job exited, which may take the whole grace period
+        // without one only the server knows the job's grace period
+        let timeout = match grace.as_deref().map(parse_duration) {
+            Some(Ok(grace)) => grace.checked_add(STOP_TIMEOUT_MARGIN),
+            _ => None,
+        };
+        client_builder = client_builder.timeout(timeout);
+    }
+    let client = client_builder.build().expect("could not build http client");
 
     let output = match opt.operation {
-        Operation::Exec { command, args } => {
+        Operation::Exec {
+            options,
+            command,
+            args,
+        } => {
             let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
-            submit(&client, opt.host_name, &command, &args)
+            let pipe_stdin = options.stdin;
+            let output = submit(
+                &client,
+                opt.host_name.clone(),
+                &options.job_spec(&command, &args),
+            );
+            match output.parse::<u64>() {
+                Ok(id) if pipe_stdin => match stream_stdin(&client, opt.host_name, id) {
+                    Ok(_) => output,
+                    Err(err) => format!("{}\nerror streaming stdin: {}", output, err),
+                },
+                _ => output,
+            }
         }
-        Operation::List => list(&client, opt.host_name),
+        Operation::Workflow { file } => match fs::read(&file) {
+            Ok(workflow) => match serde_json::from_slice(&workflow) {
+                Ok(workflow) => submit_workflow(&client, opt.host_name, &workflow),
+                Err(err) => format!("invalid workflow file: {}", err),
+            },
+            Err(err) => format!("could not read workflow file: {}", err),
+        },
+        Operation::List { detailed } => list(&client, opt.host_name, detailed),
         Operation::Status { id } => status(&client, opt.host_name, id),
-        Operation::Output { id } => output(&client, opt.host_name, id),
+        Operation::Usage { id: Some(id) } => usage(&client, opt.host_name, id),
+        Operation::Usage { id: None } => usage_summary(&client, opt.host_name),
+        Operation::Output { id, options } => {
+            // closures would capture all of opt in edition 2018
+            let url = opt.host_name;
+            if options.follow {
+                exit_with(
+                    options
+                        .sinks()
+                        .and_then(|sinks| follow_output(&client, url, id, sinks)),
+                )
+            } else if options.combined || options.timestamps {
+                combined_output(&client, url, id, options.timestamps)
+            } else if let Some((stream, range)) = options.range() {
+                exit_with(output_range(&client, url, id, stream, range).map(|_| 0))
+            } else if options.verbatim() {
+                exit_with(
+                    options
+                        .sinks()
+                        .and_then(|sinks| raw_output(&client, url, id, sinks))
+                        .map(|_| 0),
+                )
+            } else {
+                output(&client, url, id, options.attempt)
+            }
+        }
         Operation::Delete { id } => delete(&client, opt.host_name, id),
+        Operation::Stop { id, signal, grace } => stop(&client, opt.host_name, id, signal, grace),
+        Operation::Signal { id, signal } => operations::signal(&client, opt.host_name, id, &signal),
+        Operation::Priority { id, priority } => set_priority(&client, opt.host_name, id, priority),
+        Operation::Schedule(ScheduleOperation::Create {
+            cron,
+            at,
+            overlap,
+            options,
+            command,
+            args,
+        }) => {
+            let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+            let trigger = match (cron, at) {
+                (Some(expression), _) => Trigger::Cron(expression),
+                (None, Some(time)) => Trigger::At(time),
+                (None, None) => unreachable!("either --cron or --at is required"),
+            };
+            let spec = ScheduleSpec {
+                job: options.job_spec(&command, &args),
+                trigger,
+                overlap: overlap.unwrap_or_default(),
+            };
+            create_schedule(&client, opt.host_name, &spec)
+        }
+        Operation::Schedule(ScheduleOperation::List) => list_schedules(&client, opt.host_name),
+        Operation::Schedule(ScheduleOperation::Pause { id }) => {
+            pause_schedule(&client, opt.host_name, id, true)
+        }
+        Operation::Schedule(ScheduleOperation::Resume { id }) => {
+            pause_schedule(&client, opt.host_name, id, false)
+        }
+        Operation::Schedule(ScheduleOperation::Delete { id }) => {
+            delete_schedule(&client, opt.host_name, id)
+        }
+        Operation::Drain { status: true, .. } => drain_status(&client, opt.host_name),
+        Operation::Drain { timeout, .. } => drain(&client, opt.host_name, timeout),
     };
 
     println!("{}", output);
@@ -1,32 +1,115 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    time::{Duration, SystemTime},
+};
 
-use rcmd_data::{JobOutput, JobSpec, JobStatus};
+use rcmd_data::{
+    DrainStatus, JobInfo, JobOutput, JobSpec, JobStatus, OutputChunk, OutputLine, OutputRange,
+    OutputStream, Priority, ResourceUsage, ScheduleInfo, ScheduleSpec, Trigger, UsageSummary,
+    Workflow,
+};
 use reqwest::blocking::{Client, Response};
 
 const JOB_NOT_FOUND_MSG: &str = "Job not found";
+const SCHEDULE_NOT_FOUND_MSG: &str = "Schedule not found";
+/// amount of stdin data sent per request, the server accepts up to 1 MiB
+const STDIN_CHUNK_SIZE: usize = 64 * 1024;
 
-pub fn submit(http_client: &Client, url: String, command: &str, args: &[&str]) -> String {
-    let job_spec = JobSpec::new(command, args);
+pub fn submit(http_client: &Client, url: String, job_spec: &JobSpec) -> String {
     let request = http_client
         .post(format!("https://{}:8000/jobs", &url))
-        .json(&job_spec)
+        .json(job_spec)
         .build()
         .expect("unexpected error building the request");
 
     match http_client.execute(request) {
         Ok(response) if response.status().is_success() => response.text().unwrap(),
+        Ok(response) if response.status().as_u16() == 503 => draining_msg(response),
         Ok(response) => unexpected_response_msg(response),
         Err(e) => format!("error executing request: {}", e),
     }
 }
 
-pub fn list(http_client: &Client, url: String) -> String {
+/// submits the jobs of a workflow and lists their ids by name
+pub fn submit_workflow(http_client: &Client, url: String, workflow: &Workflow) -> String {
+    let request = http_client
+        .post(format!("https://{}:8000/workflows", &url))
+        .json(workflow)
+        .build()
+        .expect("unexpected error building the request");
+
+    match http_client.execute(request) {
+        Ok(response) if response.status().is_success() => {
+            let ids: HashMap<String, u64> = response.json().unwrap();
+            let mut ids: Vec<(String, u64)> = ids.into_iter().collect();
+            ids.sort_by_key(|(_, id)| *id);
+            ids.iter()
+                .map(|(name, id)| format!("{}: {}", name, id))
+                .collect::<Vec<String>>()
+                .join("\n")
+        }
+        Ok(response) if response.status().as_u16() == 503 => draining_msg(response),
+        Ok(response) => unexpected_response_msg(response),
+        Err(e) => format!("error executing request: {}", e),
+    }
+}
+
+/// sends this process' stdin to the job's stdin pipe until EOF, then closes the pipe
+pub fn stream_stdin(http_client: &Client, url: String, job_id: u64) -> Result<(), String> {
+    let mut stdin = io::stdin();
+    let mut buf = vec![0; STDIN_CHUNK_SIZE];
+    loop {
+        let n = match stdin.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(format!("error reading stdin: {}", e)),
+        };
+        let request = http_client
+            .post(format!("https://{}:8000/jobs/{}/stdin", &url, job_id))
+            .body(buf[..n].to_vec())
+            .build()
+            .expect("unexpected error building the request");
+        expect_success(http_client.execute(request))?;
+    }
+    let request = http_client
+        .post(format!("https://{}:8000/jobs/{}/stdin/close", &url, job_id))
+        .build()
+        .expect("unexpected error building the request");
+    expect_success(http_client.execute(request))
+}
+
+pub fn list(http_client: &Client, url: String, detailed: bool) -> String {
     let request = http_client
         .get(format!("https://{}:8000/jobs", &url))
+        .query(&[("detailed", detailed)])
         .build()
         .expect("unexpected error building the request");
 
     match http_client.execute(request) {
+        Ok(response) if response.status().is_success() && detailed => {
+            let jobs: HashMap<u64, JobInfo> = response.json().unwrap();
+            let mut jobs: Vec<JobInfo> = jobs.into_values().collect();
+            jobs.sort_by_key(|info| info.id);
+            let mut lines = vec![format!(
+                "{:>5} {:>8} {:<20} {:>12}  {:<30} COMMAND",
+                "ID", "PID", "SUBMITTED", "DURATION", "STATUS"
+            )];
+            lines.extend(jobs.iter().map(|info| {
+                format!(
+                    "{:>5} {:>8} {:<20} {:>12}  {:<30} {} {}",
+                    info.id,
+                    info.pid.map_or("-".to_string(), |pid| pid.to_string()),
+                    format_time(Some(info.submitted_at)),
+                    format_duration(info.duration),
+                    info.status.to_string(),
+                    info.command,
+                    info.arguments.join(" ")
+                )
+            }));
+            lines.join("\n")
+        }
         Ok(response) if response.status().is_success() => {
             let jobs: HashMap<u64, JobSpec> = response.json().unwrap();
             let mut lines: Vec<String> = jobs
@@ -49,18 +132,142 @@ pub fn status(http_client: &Client, url: String, job_id: u64) -> String {
 
     match http_client.execute(request) {
         Ok(response) if response.status().is_success() => {
-            let status: JobStatus = response.json().unwrap();
-            format!("{:?}", status)
+            let info: JobInfo = response.json().unwrap();
+            let mut lines = vec![
+                format!("status:    {}", info.status),
+                format!("command:   {} {}", info.command, info.arguments.join(" ")),
+                format!("client:    {}", info.client),
+                format!(
+                    "pid:       {}",
+                    info.pid.map_or("-".to_string(), |pid| pid.to_string())
+                ),
+                format!("submitted: {}", format_time(Some(info.submitted_at))),
+                format!("started:   {}", format_time(info.started_at)),
+                format!("ended:     {}", format_time(info.ended_at)),
+                format!("duration:  {}", format_duration(info.duration)),
+            ];
+            if let Some(schedule) = info.schedule {
+                lines.push(format!("schedule:  {}", schedule));
+            }
+            // only retried jobs have earlier attempts
+            if !info.attempts.is_empty() {
+                lines.push(format!("attempt:   {}", info.attempt));
+                for attempt in &info.attempts {
+                    lines.push(format!(
+                        "  attempt {}: {}, pid {}, started {}, ended {}",
+                        attempt.number,
+                        attempt.status,
+                        attempt.pid.map_or("-".to_string(), |pid| pid.to_string()),
+                        format_time(attempt.started_at),
+                        format_time(attempt.ended_at)
+                    ));
+                }
+            }
+            lines.join("\n")
         }
         Ok(response) if response.status().as_u16() == 404 => JOB_NOT_FOUND_MSG.to_string(),
+        Ok(response) if response.status().as_u16() == 410 => evicted_msg(response),
         Ok(response) => unexpected_response_msg(response),
         Err(e) => format!("error executing request: {}", e),
     }
 }
 
-pub fn output(http_client: &Client, url: String, job_id: u64) -> String {
+pub fn usage(http_client: &Client, url: String, job_id: u64) -> String {
     let request = http_client
-        .get(format!("https://{}:8000/jobs/{}/output", &url, job_id))
+        .get(format!("https://{}:8000/jobs/{}/status", &url, job_id))
+        .build()
+        .expect("unexpected error building the request");
+
+    match http_client.execute(request) {
+        Ok(response) if response.status().is_success() => {
+            let info: JobInfo = response.json().unwrap();
+            match info.usage {
+                Some(usage) => format_usage(&usage),
+                None if matches!(
+                    info.status,
+                    JobStatus::Waiting { .. }
+                        | JobStatus::Queued { .. }
+                        | JobStatus::Retrying { .. }
+                        | JobStatus::Running
+                        | JobStatus::Stopped
+                ) =>
+                {
+                    "job has not finished yet".to_string()
+                }
+                None => "no resource usage collected for job".to_string(),
+            }
+        }
+        Ok(response) if response.status().as_u16() == 404 => JOB_NOT_FOUND_MSG.to_string(),
+        Ok(response) if response.status().as_u16() == 410 => evicted_msg(response),
+        Ok(response) => unexpected_response_msg(response),
+        Err(e) => format!("error executing request: {}", e),
+    }
+}
+
+pub fn usage_summary(http_client: &Client, url: String) -> String {
+    let request = http_client
+        .get(format!("https://{}:8000/usage", &url))
+        .build()
+        .expect("unexpected error building the request");
+
+    match http_client.execute(request) {
+        Ok(response) if response.status().is_success() => {
+            let summary: UsageSummary = response.json().unwrap();
+            format!(
+                "client:        {}\nfinished jobs: {}\n{}",
+                summary.client,
+                summary.jobs,
+                format_usage(&summary.usage)
+            )
+        }
+        Ok(response) => unexpected_response_msg(response),
+        Err(e) => format!("error executing request: {}", e),
+    }
+}
+
+fn format_usage(usage: &ResourceUsage) -> String {
+    [
+        format!("user time:     {}", format_duration(Some(usage.user_time))),
+        format!(
+            "system time:   {}",
+            format_duration(Some(usage.system_time))
+        ),
+        format!("max rss:       {} KiB", usage.max_rss / 1024),
+        format!(
+            "block io:      {} in, {} out",
+            usage.block_input, usage.block_output
+        ),
+        format!(
+            "ctx switches:  {} voluntary, {} involuntary",
+            usage.voluntary_context_switches, usage.involuntary_context_switches
+        ),
+    ]
+    .join("\n")
+}
+
+/// UTC time with second precision, "-" if not set
+fn format_time(time: Option<SystemTime>) -> String {
+    time.map_or("-".to_string(), |time| {
+        humantime::format_rfc3339_seconds(time).to_string()
+    })
+}
+
+/// duration with millisecond precision, "-" if not set
+fn format_duration(duration: Option<Duration>) -> String {
+    duration.map_or("-".to_string(), |duration| {
+        let millis = Duration::from_millis(duration.as_millis() as u64);
+        humantime::format_duration(millis).to_string()
+    })
+}
+
+/// output of the job's current attempt, or of the given one
+pub fn output(http_client: &Client, url: String, job_id: u64, attempt: Option<u32>) -> String {
+    let path = match attempt {
+        Some(attempt) => format!("jobs/{}/attempts/{}/output", job_id, attempt),
+        None => format!("jobs/{}/output", job_id),
+    };
+    let request = http_client
+        .get(format!("https://{}:8000/{}", &url, path))
         .build()
         .expect("unexpected error building the request");
 
@@ -73,12 +280,240 @@ pub fn output(http_client: &Client, url: String, job_id: u64) -> String {
                 output.stderr()
             )
         }
+        Ok(response) if response.status().as_u16() == 404 && attempt.is_some() => {
+            "Job or attempt not found".to_string()
+        }
         Ok(response) if response.status().as_u16() == 404 => JOB_NOT_FOUND_MSG.to_string(),
+        Ok(response) if response.status().as_u16() == 410 => evicted_msg(response),
         Ok(response) => unexpected_response_msg(response),
         Err(e) => format!("error executing request: {}", e),
     }
 }
 
+/// stdout and stderr interleaved as produced, each line tagged with its stream
+/// and prefixed with the time it was read by the server if <timestamps> is set
+pub fn combined_output(http_client: &Client, url: String, job_id: u64, timestamps: bool) -> String {
+    let request = http_client
+        .get(format!(
+            "https://{}:8000/jobs/{}/output/combined",
+            &url, job_id
+        ))
+        .build()
+        .expect("unexpected error building the request");
+
+    match http_client.execute(request) {
+        Ok(response) if response.status().is_success() => {
+            let lines: Vec<OutputLine> = response.json().unwrap();
+            lines
+                .iter()
+                .map(|line| {
+                    let text = String::from_utf8_lossy(&line.data);
+                    let text = text.strip_suffix('\n').unwrap_or(&text);
+                    let stream = match line.stream {
+                        OutputStream::Stdout => "stdout",
+                        OutputStream::Stderr => "stderr",
+                    };
+                    if timestamps {
+                        let time = humantime::format_rfc3339_millis(line.timestamp);
+                        format!("{} [{}] {}", time, stream, text)
+                    } else {
+                        format!("[{}] {}", stream, text)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        Ok(response) if response.status().as_u16() == 404 => JOB_NOT_FOUND_MSG.to_string(),
+        Ok(response) if response.status().as_u16() == 410 => evicted_msg(response),
+        Ok(response) => unexpected_response_msg(response),
+        Err(e) => format!("error executing request: {}", e),
+    }
+}
+
+/// where output of a job is written to unchanged
+pub struct OutputSinks {
+    pub stdout: Box<dyn Write>,
+    pub stderr: Box<dyn Write>,
+}
+
+impl OutputSinks {
+    fn write(&mut self, stream: OutputStream, data: &[u8]) -> Result<(), String> {
+        let sink = match stream {
+            OutputStream::Stdout => &mut self.stdout,
+            OutputStream::Stderr => &mut self.stderr,
+        };
+        sink.write_all(data)
+            .and_then(|_| sink.flush())
+            .map_err(|e| format!("error writing output: {}", e))
+    }
+}
+
+/// writes the whole output of the job unchanged to the given sinks,
+/// each stream is copied from the responses as they are received,
+/// requesting the rest until the stream's size is reached as the server cuts larger output
+pub fn raw_output(
+    http_client: &Client,
+    url: String,
+    job_id: u64,
+    mut sinks: OutputSinks,
+) -> Result<(), String> {
+    for (stream, sink) in [("stdout", &mut sinks.stdout), ("stderr", &mut sinks.stderr)] {
+        let mut from = 0;
+        loop {
+            let request = http_client
+                .get(format!("https://{}:8000/jobs/{}/output/raw", &url, job_id))
+                .query(&[("stream", stream.to_string()), ("from", from.to_string())])
+                .build()
+                .expect("unexpected error building the request");
+
+            let (next, size) = match http_client.execute(request) {
+                Ok(mut response) if response.status().is_success() => {
+                    let next = header_u64(&response, "Rcmd-Next-Offset");
+                    let size = header_u64(&response, "Rcmd-Size");
+                    response
+                        .copy_to(sink)
+                        .map_err(|e| format!("error reading output: {}", e))
+                        .and_then(|_| {
+                            sink.flush()
+                                .map_err(|e| format!("error writing output: {}", e))
+                        })?;
+                    (next, size)
+                }
+                Ok(response) if response.status().as_u16() == 404 => {
+                    return Err(JOB_NOT_FOUND_MSG.to_string())
+                }
+                Ok(response) if response.status().as_u16() == 410 => {
+                    return Err(evicted_msg(response))
+                }
+                Ok(response) => return Err(unexpected_response_msg(response)),
+                Err(e) => return Err(format!("error executing request: {}", e)),
+            };
+            match (next, size) {
+                (Some(next), Some(size)) if next > from && next < size => from = next,
+                _ => break,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// writes part of one output stream unchanged to stdout, the offset to continue from to stderr
+pub fn output_range(
+    http_client: &Client,
+    url: String,
+    job_id: u64,
+    stream: OutputStream,
+    range: OutputRange,
+) -> Result<(), String> {
+    let stream = match stream {
+        OutputStream::Stdout => "stdout",
+        OutputStream::Stderr => "stderr",
+    };
+    let mut query = vec![("stream", stream.to_string())];
+    match range {
+        OutputRange::Bytes { from, limit } => {
+            query.push(("from", from.to_string()));
+            if let Some(limit) = limit {
+                query.push(("limit", limit.to_string()));
+            }
+        }
+        OutputRange::Head(head) => query.push(("head", head.to_string())),
+        OutputRange::Tail(tail) => query.push(("tail", tail.to_string())),
+    }
+    let request = http_client
+        .get(format!("https://{}:8000/jobs/{}/output", &url, job_id))
+        .query(&query)
+        .build()
+        .expect("unexpected error building the request");
+
+    match http_client.execute(request) {
+        Ok(response) if response.status().is_success() => {
+            let chunk: OutputChunk = response.json().unwrap();
+            let mut stdout = io::stdout();
+            stdout
+                .write_all(&chunk.data)
+                .and_then(|_| stdout.flush())
+                .map_err(|e| format!("error writing output: {}", e))?;
+            eprintln!(
+                "___NEXT_OFFSET___ {} (of {} bytes)",
+                chunk.next_offset, chunk.size
+            );
+            Ok(())
+        }
+        Ok(response) if response.status().as_u16() == 404 => Err(JOB_NOT_FOUND_MSG.to_string()),
+        Ok(response) if response.status().as_u16() == 410 => Err(evicted_msg(response)),
+        Ok(response) => Err(unexpected_response_msg(response)),
+        Err(e) => Err(format!("error executing request: {}", e)),
+    }
+}
+
+/// writes output of the job to the given sinks as it is produced until the job finished
+/// returns the exit code of the job, or 1 if it did not exit with a code
+pub fn follow_output(
+    http_client: &Client,
+    url: String,
+    job_id: u64,
+    mut sinks: OutputSinks,
+) -> Result<i32, String> {
+    let request = http_client
+        .get(format!(
+            "https://{}:8000/jobs/{}/output/follow",
+            &url, job_id
+        ))
+        .build()
+        .expect("unexpected error building the request");
+
+    let response = match http_client.execute(request) {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) if response.status().as_u16() == 404 => {
+            return Err(JOB_NOT_FOUND_MSG.to_string())
+        }
+        Ok(response) if response.status().as_u16() == 410 => return Err(evicted_msg(response)),
+        Ok(response) => return Err(unexpected_response_msg(response)),
+        Err(e) => return Err(format!("error executing request: {}", e)),
+    };
+    // server-sent events: "event:" and "data:" fields, terminated by an empty line
+    let mut event = String::new();
+    let mut data = String::new();
+    for line in BufReader::new(response).lines() {
+        let line = line.map_err(|e| format!("error reading output stream: {}", e))?;
+        if let Some(value) = line.strip_prefix("event:") {
+            event = value.trim_start().to_string();
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push_str(value.trim_start());
+        } else if line.is_empty() && !data.is_empty() {
+            let stream = match event.as_str() {
+                "stdout" => Some(OutputStream::Stdout),
+                "stderr" => Some(OutputStream::Stderr),
+                "exit" => {
+                    let status: JobStatus = serde_json::from_str(&data)
+                        .map_err(|e| format!("invalid job status: {}", e))?;
+                    return Ok(match status {
+                        JobStatus::Completed { exit_code } => exit_code,
+                        JobStatus::Halted {
+                            exit_code: Some(exit_code),
+                            ..
+                        } => exit_code,
+                        // same as timeout(1)
+                        JobStatus::TimedOut { .. } => 124,
+                        _ => 1,
+                    });
+                }
+                _ => None,
+            };
+            if let Some(stream) = stream {
+                let output =
+                    base64::decode(&data).map_err(|e| format!("invalid output data: {}", e))?;
+                sinks.write(stream, &output)?;
+            }
+            event.clear();
+            data.clear();
+        }
+        // anything else is a comment (keep-alive) or an unknown field
+    }
+    Err("job was deleted while following its output".to_string())
+}
+
 pub fn delete(http_client: &Client, url: String, job_id: u64) -> String {
     let request = http_client
         .delete(format!("https://{}:8000/jobs/{}", &url, job_id))
@@ -90,11 +525,253 @@ pub fn delete(http_client: &Client, url: String, job_id: u64) -> String {
             format!("{} deleted", job_id)
         }
         Ok(response) if response.status().as_u16() == 404 => JOB_NOT_FOUND_MSG.to_string(),
+        Ok(response) if response.status().as_u16() == 410 => evicted_msg(response),
         Ok(response) => unexpected_response_msg(response),
         Err(e) => format!("error executing request: {}", e),
     }
 }
 
+pub fn stop(
+    http_client: &Client,
+    url: String,
+    job_id: u64,
+    signal: Option<String>,
+    grace: Option<String>,
+) -> String {
+    let mut query = Vec::new();
+    if let Some(signal) = signal {
+        query.push(("signal", signal));
+    }
+    if let Some(grace) = grace {
+        query.push(("grace", grace));
+    }
+    let request = http_client
+        .post(format!("https://{}:8000/jobs/{}/stop", &url, job_id))
+        .query(&query)
+        .build()
+        .expect("unexpected error building the request");
+
+    match http_client.execute(request) {
+        Ok(response) if response.status().is_success() => {
+            let status: JobStatus = response.json().unwrap();
+            status.to_string()
+        }
+        Ok(response) if response.status().as_u16() == 404 => JOB_NOT_FOUND_MSG.to_string(),
+        Ok(response) if response.status().as_u16() == 410 => evicted_msg(response),
+        Ok(response) => unexpected_response_msg(response),
+        Err(e) => format!("error executing request: {}", e),
+    }
+}
+
+pub fn signal(http_client: &Client, url: String, job_id: u64, signal: &str) -> String {
+    let request = http_client
+        .post(format!("https://{}:8000/jobs/{}/signal", &url, job_id))
+        .query(&[("signal", signal)])
+        .build()
+        .expect("unexpected error building the request");
+
+    match http_client.execute(request) {
+        Ok(response) if response.status().is_success() => {
+            format!("{} sent to {}", signal, job_id)
+        }
+        Ok(response) if response.status().as_u16() == 404 => JOB_NOT_FOUND_MSG.to_string(),
+        Ok(response) if response.status().as_u16() == 410 => evicted_msg(response),
+        Ok(response) => unexpected_response_msg(response),
+        Err(e) => format!("error executing request: {}", e),
+    }
+}
+
+pub fn set_priority(http_client: &Client, url: String, job_id: u64, priority: Priority) -> String {
+    let request = http_client
+        .post(format!("https://{}:8000/jobs/{}/priority", &url, job_id))
+        .query(&[("priority", priority.to_string())])
+        .build()
+        .expect("unexpected error building the request");
+
+    match http_client.execute(request) {
+        Ok(response) if response.status().is_success() => {
+            format!("priority of {} set to {}", job_id, priority)
+        }
+        Ok(response) if response.status().as_u16() == 404 => JOB_NOT_FOUND_MSG.to_string(),
+        Ok(response) if response.status().as_u16() == 410 => evicted_msg(response),
+        Ok(response) => unexpected_response_msg(response),
+        Err(e) => format!("error executing request: {}", e),
+    }
+}
+
+pub fn create_schedule(http_client: &Client, url: String, schedule_spec: &ScheduleSpec) -> String {
+    let request = http_client
+        .post(format!("https://{}:8000/schedules", &url))
+        .json(schedule_spec)
+        .build()
+        .expect("unexpected error building the request");
+
+    match http_client.execute(request) {
+        Ok(response) if response.status().is_success() => response.text().unwrap(),
+        Ok(response) if response.status().as_u16() == 503 => draining_msg(response),
+        Ok(response) => unexpected_response_msg(response),
+        Err(e) => format!("error executing request: {}", e),
+    }
+}
+
+pub fn list_schedules(http_client: &Client, url: String) -> String {
+    let request = http_client
+        .get(format!("https://{}:8000/schedules", &url))
+        .build()
+        .expect("unexpected error building the request");
+
+    match http_client.execute(request) {
+        Ok(response) if response.status().is_success() => {
+            let schedules: HashMap<u64, ScheduleInfo> = response.json().unwrap();
+            let mut schedules: Vec<ScheduleInfo> = schedules.into_values().collect();
+            schedules.sort_by_key(|info| info.id);
+            let mut lines = vec![format!(
+                "{:>5} {:<30} {:<8} {:<20} {:<20} {:>5} {:>7}  COMMAND",
+                "ID", "TRIGGER", "OVERLAP", "NEXT RUN", "LAST RUN", "JOBS", "SKIPPED"
+            )];
+            lines.extend(schedules.iter().map(|info| {
+                let trigger = match &info.spec.trigger {
+                    Trigger::Cron(expression) => format!("cron {}", expression),
+                    Trigger::At(time) => format!("at {}", format_time(Some(*time))),
+                };
+                let next_run = match info.next_run {
+                    _ if info.paused => "paused".to_string(),
+                    next_run => format_time(next_run),
+                };
+                format!(
+                    "{:>5} {:<30} {:<8} {:<20} {:<20} {:>5} {:>7}  {} {}",
+                    info.id,
+                    trigger,
+                    info.spec.overlap.to_string(),
+                    next_run,
+                    format_time(info.last_run),
+                    info.jobs.len(),
+                    info.skipped,
+                    info.spec.job.command,
+                    info.spec.job.arguments.join(" ")
+                )
+            }));
+            lines.join("\n")
+        }
+        Ok(response) => unexpected_response_msg(response),
+        Err(e) => format!("error executing request: {}", e),
+    }
+}
+
+/// pauses the schedule, or resumes it if <paused> is false
+pub fn pause_schedule(http_client: &Client, url: String, schedule_id: u64, paused: bool) -> String {
+    let action = if paused { "pause" } else { "resume" };
+    let request = http_client
+        .post(format!(
+            "https://{}:8000/schedules/{}/{}",
+            &url, schedule_id, action
+        ))
+        .build()
+        .expect("unexpected error building the request");
+
+    match http_client.execute(request) {
+        Ok(response) if response.status().is_success() => {
+            format!("{} {}d", schedule_id, action)
+        }
+        Ok(response) if response.status().as_u16() == 404 => SCHEDULE_NOT_FOUND_MSG.to_string(),
+        Ok(response) => unexpected_response_msg(response),
+        Err(e) => format!("error executing request: {}", e),
+    }
+}
+
+pub fn delete_schedule(http_client: &Client, url: String, schedule_id: u64) -> String {
+    let request = http_client
+        .delete(format!("https://{}:8000/schedules/{}", &url, schedule_id))
+        .build()
+        .expect("unexpected error building the request");
+
+    match http_client.execute(request) {
+        Ok(response) if response.status().is_success() => {
+            format!("{} deleted", schedule_id)
+        }
+        Ok(response) if response.status().as_u16() == 404 => SCHEDULE_NOT_FOUND_MSG.to_string(),
+        Ok(response) => unexpected_response_msg(response),
+        Err(e) => format!("error executing request: {}", e),
+    }
+}
+
+/// starts draining the server, remaining jobs are stopped after <timeout> if given
+pub fn drain(http_client: &Client, url: String, timeout: Option<String>) -> String {
+    let mut request = http_client.post(format!("https://{}:8000/admin/drain", &url));
+    if let Some(timeout) = timeout {
+        request = request.query(&[("timeout", timeout)]);
+    }
+    let request = request
+        .build()
+        .expect("unexpected error building the request");
+
+    match http_client.execute(request) {
+        Ok(response) if response.status().is_success() => {
+            format_drain_status(&response.json().unwrap())
+        }
+        Ok(response) => unexpected_response_msg(response),
+        Err(e) => format!("error executing request: {}", e),
+    }
+}
+
+pub fn drain_status(http_client: &Client, url: String) -> String {
+    let request = http_client
+        .get(format!("https://{}:8000/admin/drain", &url))
+        .build()
+        .expect("unexpected error building the request");
+
+    match http_client.execute(request) {
+        Ok(response) if response.status().is_success() => {
+            format_drain_status(&response.json().unwrap())
+        }
+        Ok(response) => unexpected_response_msg(response),
+        Err(e) => format!("error executing request: {}", e),
+    }
+}
+
+fn format_drain_status(status: &DrainStatus) -> String {
+    format!(
+        "draining:       {}\nremaining jobs: {}\ndeadline:       {}",
+        status.draining,
+        status.remaining_jobs,
+        format_time(status.deadline)
+    )
+}
+
+/// job evicted by the server's retention policy, along with why
+fn evicted_msg(response: Response) -> String {
+    response
+        .text()
+        .unwrap_or_else(|_| "job was evicted".to_string())
+}
+
+/// submission rejected as the server drains, along with when to submit again
+fn draining_msg(response: Response) -> String {
+    let retry_after = response
+        .headers()
+        .get("Retry-After")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("-");
+    format!("server is draining, submit again in {}s", retry_after)
+}
+
+/// numeric value of a header of the response, if it is set
+fn header_u64(response: &Response, name: &str) -> Option<u64> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+fn expect_success(response: reqwest::Result<Response>) -> Result<(), String> {
+    match response {
+        Ok(response) if response.status().is_success() => Ok(()),
+        Ok(response) => Err(unexpected_response_msg(response)),
+        Err(e) => Err(format!("error executing request: {}", e)),
+    }
+}
+
 fn unexpected_response_msg(response: Response) -> String {
     format!(
         "unexpected response (status {}): {:?}",
@@ -1,9 +1,48 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct JobSpec {
     pub command: String,
     pub arguments: Vec<String>,
+    #[serde(default)]
+    pub limits: Option<ResourceLimits>,
+    /// how long a stop request waits for the job to exit before killing it
+    #[serde(default)]
+    pub stop_grace: Option<Duration>,
+    /// how long the job may run before it is stopped with SIGTERM and the stop grace period
+    #[serde(default)]
+    pub timeout: Option<Duration>,
+    /// environment variables set for the job, in addition to the inherited ones
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// start with an empty environment instead of inheriting the server's
+    #[serde(default)]
+    pub clear_env: bool,
+    /// directory the job is started in, defaults to the server's working directory
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+    /// file mode creation mask of the job, defaults to the server's umask
+    #[serde(default)]
+    pub umask: Option<u32>,
+    #[serde(default)]
+    pub stdin: StdinMode,
+    /// order among the queued jobs of the same client
+    #[serde(default)]
+    pub priority: Priority,
+    /// jobs of the same pool that have to finish first, the job is skipped if a condition fails
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+    /// how failed attempts are retried, not at all if None
+    #[serde(default)]
+    pub retry: Option<RetryPolicy>,
 }
 
 impl JobSpec {
@@ -11,43 +50,636 @@ impl JobSpec {
         Self {
             command: command.to_string(),
             arguments: args.iter().map(|a| a.to_string()).collect(),
+            limits: None,
+            stop_grace: None,
+            timeout: None,
+            env: HashMap::new(),
+            clear_env: false,
+            working_dir: None,
+            umask: None,
+            stdin: StdinMode::Null,
+            priority: Priority::Normal,
+            dependencies: Vec::new(),
+            retry: None,
         }
     }
+
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    pub fn with_stop_grace(mut self, grace: Duration) -> Self {
+        self.stop_grace = Some(grace);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_dependency(mut self, job: u64, condition: Condition) -> Self {
+        self.dependencies.push(Dependency { job, condition });
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = Some(retry);
+        self
+    }
 }
 
+/// retries of a job exiting with a non-zero exit code, with exponential backoff
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// attempts in total, including the first one
+    pub max_attempts: u32,
+    /// exit codes that are retried, any non-zero one if empty
+    #[serde(default)]
+    pub exit_codes: Vec<i32>,
+    /// delay before the first retry, doubled for each further one
+    #[serde(default = "RetryPolicy::default_backoff")]
+    pub backoff: Duration,
+    /// upper bound of the delay
+    #[serde(default)]
+    pub max_backoff: Option<Duration>,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            exit_codes: Vec::new(),
+            backoff: Self::default_backoff(),
+            max_backoff: None,
+        }
+    }
+
+    fn default_backoff() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    /// whether an attempt that exited with <exit_code> is retried (if attempts are left)
+    pub fn retries(&self, exit_code: i32) -> bool {
+        exit_code != 0 && (self.exit_codes.is_empty() || self.exit_codes.contains(&exit_code))
+    }
+
+    /// delay after the failure of the given attempt (counting from 1)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        let delay = self.backoff.checked_mul(factor).unwrap_or(Duration::MAX);
+        match self.max_backoff {
+            Some(max_backoff) => delay.min(max_backoff),
+            None => delay,
+        }
+    }
+}
+
+/// job that has to finish before the dependent one can start
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dependency {
+    pub job: u64,
+    #[serde(default)]
+    pub condition: Condition,
+}
+
+/// how a job has to finish for its dependents to start
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Condition {
+    /// exit code 0
+    #[default]
+    OnSuccess,
+    /// any other way of finishing, except being skipped
+    OnFailure,
+    /// finished in any way, including being skipped
+    Always,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(condition: &str) -> Result<Self, Self::Err> {
+        match condition {
+            "on-success" => Ok(Condition::OnSuccess),
+            "on-failure" => Ok(Condition::OnFailure),
+            "always" => Ok(Condition::Always),
+            _ => Err(format!("unknown dependency condition: {}", condition)),
+        }
+    }
+}
+
+/// jobs submitted at once, depending on each other by name
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Workflow {
+    pub jobs: Vec<WorkflowJob>,
+}
+
+/// job of a workflow, <after> lists the jobs of the workflow it depends on
+/// (dependencies on already submitted jobs can be set in the spec)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkflowJob {
+    pub name: String,
+    pub spec: JobSpec,
+    #[serde(default)]
+    pub after: Vec<WorkflowDependency>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkflowDependency {
+    /// name of the job within the workflow
+    pub job: String,
+    #[serde(default)]
+    pub condition: Condition,
+}
+
+/// queued jobs of a client with higher priority start first, equal ones in submission order
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl FromStr for Priority {
+    type Err = String;
+
+    fn from_str(priority: &str) -> Result<Self, Self::Err> {
+        match priority.to_ascii_lowercase().as_str() {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            _ => Err(format!("unknown priority: {}", priority)),
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Priority::Low => write!(f, "low"),
+            Priority::Normal => write!(f, "normal"),
+            Priority::High => write!(f, "high"),
+        }
+    }
+}
+
+/// submits a job repeatedly or once at a given time
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleSpec {
+    pub job: JobSpec,
+    pub trigger: Trigger,
+    #[serde(default)]
+    pub overlap: OverlapPolicy,
+}
+
+/// when a schedule submits its job
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    /// cron expression (minute, hour, day of month, month and day of week), evaluated in UTC
+    Cron(String),
+    /// once at the given time
+    At(SystemTime),
+}
+
+/// what a schedule does if the job it submitted before is still running when it fires
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlapPolicy {
+    /// submits no job this time
+    #[default]
+    Skip,
+    /// submits a job starting once the previous one finished
+    Queue,
+    /// submits a job running concurrently
+    Allow,
+}
+
+impl FromStr for OverlapPolicy {
+    type Err = String;
+
+    fn from_str(overlap: &str) -> Result<Self, Self::Err> {
+        match overlap.to_ascii_lowercase().as_str() {
+            "skip" => Ok(OverlapPolicy::Skip),
+            "queue" => Ok(OverlapPolicy::Queue),
+            "allow" => Ok(OverlapPolicy::Allow),
+            _ => Err(format!("unknown overlap policy: {}", overlap)),
+        }
+    }
+}
+
+impl fmt::Display for OverlapPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverlapPolicy::Skip => write!(f, "skip"),
+            OverlapPolicy::Queue => write!(f, "queue"),
+            OverlapPolicy::Allow => write!(f, "allow"),
+        }
+    }
+}
+
+/// where a job's stdin is connected to
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StdinMode {
+    /// reading stdin returns EOF right away
+    #[default]
+    Null,
+    /// stdin provides the given data, followed by EOF
+    Data(#[serde(with = "base64_bytes")] Vec<u8>),
+    /// stdin is an open pipe that is written to and closed through the job pool
+    Pipe,
+}
+
+/// resource limits applied to a job's cgroup (v2)
+/// unset fields are left at the parent cgroup's defaults
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// relative cpu weight in range 1..=10000 (`cpu.weight`)
+    pub cpu_weight: Option<u64>,
+    /// cpu time in microseconds the job may use per period (`cpu.max`)
+    pub cpu_quota_us: Option<u64>,
+    /// period for cpu_quota_us in microseconds, kernel default is 100000
+    pub cpu_period_us: Option<u64>,
+    /// memory limit in bytes (`memory.max`)
+    pub memory_max: Option<u64>,
+    /// maximum number of processes/threads (`pids.max`)
+    pub pids_max: Option<u64>,
+}
+
+/// standard stream job output was written to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl FromStr for OutputStream {
+    type Err = String;
+
+    fn from_str(stream: &str) -> Result<Self, Self::Err> {
+        match stream {
+            "stdout" => Ok(OutputStream::Stdout),
+            "stderr" => Ok(OutputStream::Stderr),
+            _ => Err(format!("unknown output stream: {}", stream)),
+        }
+    }
+}
+
+/// selects part of an output stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputRange {
+    /// <limit> bytes (or everything) starting at byte offset <from>
+    Bytes { from: u64, limit: Option<u64> },
+    /// the first n lines
+    Head(usize),
+    /// the last n lines
+    Tail(usize),
+}
+
+/// part of an output stream, reading can be resumed at <next_offset>
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputChunk {
+    /// byte offset of data within the stream
+    pub offset: u64,
+    pub next_offset: u64,
+    /// bytes written to the stream so far
+    pub size: u64,
+    #[serde(with = "base64_bytes")]
+    pub data: Vec<u8>,
+}
+
+/// output read from one stream at once, split after line breaks
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputLine {
+    pub stream: OutputStream,
+    /// when the output was read by the server
+    pub timestamp: SystemTime,
+    #[serde(with = "base64_bytes")]
+    pub data: Vec<u8>,
+}
+
+/// output of a job exactly as written by it, which need not be valid UTF-8
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct JobOutput {
-    stdout_lines: Vec<String>,
-    stderr_lines: Vec<String>,
+    #[serde(with = "base64_bytes")]
+    stdout: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    stderr: Vec<u8>,
 }
 
 impl JobOutput {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         Self {
-            stdout_lines: Vec::new(),
-            stderr_lines: Vec::new(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
         }
     }
 
-    pub fn append(&mut self, mut stdout_lines: Vec<String>, mut stderr_lines: Vec<String>) {
-        self.stdout_lines.append(&mut stdout_lines);
-        self.stderr_lines.append(&mut stderr_lines);
+    pub fn append(&mut self, stdout: &[u8], stderr: &[u8]) {
+        self.stdout.extend_from_slice(stdout);
+        self.stderr.extend_from_slice(stderr);
     }
 
+    /// stdout as text, invalid UTF-8 is replaced
     pub fn stdout(&self) -> String {
-        self.stdout_lines.join("")
+        String::from_utf8_lossy(&self.stdout).into_owned()
     }
 
+    /// stderr as text, invalid UTF-8 is replaced
     pub fn stderr(&self) -> String {
-        self.stderr_lines.join("")
+        String::from_utf8_lossy(&self.stderr).into_owned()
+    }
+
+    pub fn stdout_bytes(&self) -> &[u8] {
+        &self.stdout
+    }
+
+    pub fn stderr_bytes(&self) -> &[u8] {
+        &self.stderr
     }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum JobStatus {
+    /// waiting for the given dependencies to finish
+    Waiting {
+        dependencies: Vec<u64>,
+    },
+    /// <attempt> failed, the next one is queued after <retry_in>
+    Retrying {
+        attempt: u32,
+        retry_in: Duration,
+    },
+    /// waiting for a free slot, <position> 1 starts next
+    Queued {
+        position: usize,
+    },
     Running,
-    Completed { exit_code: i32 },
-    Terminated,
-    Error { msg: String },
+    /// paused with SIGSTOP, continues after SIGCONT
+    Stopped,
+    Completed {
+        exit_code: i32,
+    },
+    /// killed by <signal> (name including SIG prefix) that was not part of a stop request
+    /// <by_rcmd> if the signal was sent through rcmd, otherwise it came from elsewhere on the host
+    Terminated {
+        signal: String,
+        core_dumped: bool,
+        by_rcmd: bool,
+    },
+    OomKilled,
+    /// stopped on request by sending <signal>, <escalated> to SIGKILL if still running after <grace>
+    Halted {
+        signal: String,
+        grace: Duration,
+        escalated: bool,
+        exit_code: Option<i32>,
+    },
+    /// stopped after running longer than <timeout>, <escalated> to SIGKILL if still running after the grace period
+    TimedOut {
+        timeout: Duration,
+        escalated: bool,
+    },
+    /// never started because the condition on <dependency> can not be met anymore
+    Skipped {
+        dependency: u64,
+    },
+    /// was not finished when the server stopped, how it ended is unknown
+    Lost {
+        reason: String,
+    },
+    Error {
+        msg: String,
+    },
+}
+
+impl fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobStatus::Waiting { dependencies } => {
+                let jobs: Vec<String> = dependencies.iter().map(u64::to_string).collect();
+                write!(f, "waiting for jobs {}", jobs.join(", "))
+            }
+            JobStatus::Retrying { attempt, retry_in } => {
+                write!(f, "attempt {} failed, retrying in {:?}", attempt, retry_in)
+            }
+            JobStatus::Queued { position } => write!(f, "queued at position {}", position),
+            JobStatus::Running => write!(f, "running"),
+            JobStatus::Stopped => write!(f, "paused"),
+            JobStatus::Completed { exit_code } => write!(f, "exited with code {}", exit_code),
+            JobStatus::Terminated {
+                signal,
+                core_dumped,
+                by_rcmd,
+            } => {
+                write!(f, "killed by {}", signal)?;
+                if *core_dumped {
+                    write!(f, " (core dumped)")?;
+                }
+                if *by_rcmd {
+                    write!(f, " (sent through rcmd)")?;
+                }
+                Ok(())
+            }
+            JobStatus::OomKilled => write!(f, "killed by the OOM killer"),
+            JobStatus::Halted {
+                signal,
+                grace,
+                escalated,
+                exit_code,
+            } => {
+                write!(f, "stopped with {}", signal)?;
+                match (escalated, exit_code) {
+                    (true, _) => write!(f, ", killed after {:?} grace period", grace),
+                    (false, Some(exit_code)) => write!(f, ", exited with code {}", exit_code),
+                    (false, None) => Ok(()),
+                }
+            }
+            JobStatus::TimedOut { timeout, escalated } => {
+                write!(f, "timed out after {:?}", timeout)?;
+                if *escalated {
+                    write!(f, ", killed after grace period")?;
+                }
+                Ok(())
+            }
+            JobStatus::Skipped { dependency } => {
+                write!(f, "skipped, condition on job {} not met", dependency)
+            }
+            JobStatus::Lost { reason } => write!(f, "lost: {}", reason),
+            JobStatus::Error { msg } => write!(f, "error: {}", msg),
+        }
+    }
+}
+
+/// status of a job along with when and where it ran
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobInfo {
+    pub id: u64,
+    pub command: String,
+    pub arguments: Vec<String>,
+    pub status: JobStatus,
+    /// name of the client that submitted the job
+    pub client: String,
+    pub pid: Option<u32>,
+    pub submitted_at: SystemTime,
+    /// None if the job never started
+    pub started_at: Option<SystemTime>,
+    /// None while the job is running
+    pub ended_at: Option<SystemTime>,
+    /// wall-clock time from start to end, or until now while the job is running
+    pub duration: Option<Duration>,
+    /// None until the job finished, or if it could not be collected
+    pub usage: Option<ResourceUsage>,
+    /// number of the current attempt, counting from 1, the fields above are about it
+    pub attempt: u32,
+    /// earlier attempts of a retried job
+    pub attempts: Vec<Attempt>,
+    /// schedule that submitted the job
+    pub schedule: Option<u64>,
+}
+
+/// state of a schedule, along with the jobs it submitted
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleInfo {
+    pub id: u64,
+    pub spec: ScheduleSpec,
+    pub paused: bool,
+    pub created_at: SystemTime,
+    /// None if the schedule does not fire anymore
+    pub next_run: Option<SystemTime>,
+    pub last_run: Option<SystemTime>,
+    /// ids of the submitted jobs, oldest first
+    pub jobs: Vec<u64>,
+    /// number of firings that submitted no job as the previous one was still running
+    pub skipped: u64,
+}
+
+/// finished attempt of a retried job
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attempt {
+    pub number: u32,
+    pub status: JobStatus,
+    pub pid: Option<u32>,
+    pub started_at: Option<SystemTime>,
+    pub ended_at: Option<SystemTime>,
+}
+
+/// resources used by a finished job
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    pub user_time: Duration,
+    pub system_time: Duration,
+    /// maximum resident set size in bytes of the job's process or its largest waited for descendant
+    pub max_rss: u64,
+    /// blocks (of 512 bytes) read from and written to disk
+    pub block_input: u64,
+    pub block_output: u64,
+    pub voluntary_context_switches: u64,
+    pub involuntary_context_switches: u64,
+}
+
+impl ResourceUsage {
+    /// adds up the usage of another job, except for max_rss which is the maximum of both
+    pub fn add(&mut self, other: &ResourceUsage) {
+        self.user_time += other.user_time;
+        self.system_time += other.system_time;
+        self.max_rss = self.max_rss.max(other.max_rss);
+        self.block_input += other.block_input;
+        self.block_output += other.block_output;
+        self.voluntary_context_switches += other.voluntary_context_switches;
+        self.involuntary_context_switches += other.involuntary_context_switches;
+    }
+}
+
+/// resources used by all finished jobs of a client, including deleted ones
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageSummary {
+    pub client: String,
+    /// number of jobs the usage was collected for
+    pub jobs: u64,
+    pub usage: ResourceUsage,
+}
+
+/// progress of draining the server, which rejects new jobs while it drains
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DrainStatus {
+    pub draining: bool,
+    /// jobs of all clients that did not finish yet
+    pub remaining_jobs: usize,
+    /// when the remaining jobs are stopped and the server exits, None if it waits for them
+    pub deadline: Option<SystemTime>,
+}
+
+/// parses durations like "10s", "500ms", "2m" or "1h", a plain number is taken as seconds
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let duration = duration.trim();
+    let split = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (value, unit) = duration.split_at(split);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration: {}", duration))?;
+    let secs = |factor: u64| {
+        value
+            .checked_mul(factor)
+            .map(Duration::from_secs)
+            .ok_or_else(|| "duration too large".to_string())
+    };
+    match unit {
+        "ms" => Ok(Duration::from_millis(value)),
+        "" | "s" => secs(1),
+        "m" => secs(60),
+        "h" => secs(60 * 60),
+        _ => Err(format!("invalid duration unit: {}", unit)),
+    }
+}
+
+/// (de)serializes bytes as base64 string
+mod base64_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        base64::decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::parse_duration;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(Ok(Duration::from_millis(500)), parse_duration("500ms"));
+        assert_eq!(Ok(Duration::from_secs(10)), parse_duration("10"));
+        assert_eq!(Ok(Duration::from_secs(120)), parse_duration(" 2m "));
+        assert_eq!(Ok(Duration::from_secs(3600)), parse_duration("1h"));
+        assert!(parse_duration("1d").is_err());
+        assert!(parse_duration("h").is_err());
+        assert_eq!(
+            Err("duration too large".to_string()),
+            parse_duration("99999999999999999h")
+        );
+        assert!(parse_duration("99999999999999999999s").is_err());
+    }
 }
@@ -0,0 +1,4 @@
+/// supervises a single job on behalf of rcmd_server, see rcmd_lib::shim
+fn main() {
+    rcmd_lib::shim::main();
+}
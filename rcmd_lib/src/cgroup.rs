@@ -0,0 +1,302 @@
+use std::{
+    ffi::{CStr, CString},
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use rcmd_data::ResourceLimits;
+use tokio::time::sleep;
+use tracing::{debug, error, info};
+
+/// a leaf cgroup (v2) holding the processes of a single job
+#[derive(Debug)]
+pub struct Cgroup {
+    path: PathBuf,
+    procs: CString,
+}
+
+impl Cgroup {
+    /// creates cgroup <parent>/<name> and writes the given limits to its controller files
+    /// required controllers are enabled in the parent's subtree_control if not already
+    pub fn create(parent: &Path, name: &str, limits: &ResourceLimits) -> io::Result<Self> {
+        enable_controllers(parent, limits)?;
+        let path = parent.join(name);
+        fs::create_dir(&path)?;
+        let procs = CString::new(path.join("cgroup.procs").as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let cgroup = Self { path, procs };
+        if let Err(err) = cgroup.apply_limits(limits) {
+            // freshly created and still empty, can be removed right away
+            let _ = fs::remove_dir(&cgroup.path);
+            return Err(err);
+        }
+        info!("created cgroup {:?}", cgroup.path);
+        Ok(cgroup)
+    }
+
+    /// cgroup created before, e.g. for a job reattached after a restart of the server
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if !path.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("cgroup {:?} does not exist", path),
+            ));
+        }
+        let procs = CString::new(path.join("cgroup.procs").as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        Ok(Self {
+            path: path.to_owned(),
+            procs,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// path of the cgroup.procs file, to be passed to `join` in a pre_exec hook
+    pub fn procs_path(&self) -> &CStr {
+        &self.procs
+    }
+
+    /// true if the kernel OOM killer killed any process of this cgroup
+    pub fn oom_killed(&self) -> bool {
+        match fs::read_to_string(self.path.join("memory.events")) {
+            Ok(events) => events
+                .lines()
+                .filter_map(|line| line.strip_prefix("oom_kill "))
+                .any(|count| matches!(count.trim().parse::<u64>(), Ok(c) if c > 0)),
+            // memory controller not enabled, no limit could have been hit
+            Err(_) => false,
+        }
+    }
+
+    /// user and system cpu time used by all processes that were in the cgroup
+    pub fn cpu_times(&self) -> Option<(Duration, Duration)> {
+        let stat = fs::read_to_string(self.path.join("cpu.stat")).ok()?;
+        let field = |name: &str| {
+            stat.lines()
+                .filter_map(|line| line.strip_prefix(name))
+                .find_map(|usec| usec.trim().parse().ok())
+                .map(Duration::from_micros)
+        };
+        Some((field("user_usec ")?, field("system_usec ")?))
+    }
+
+    /// sends SIGKILL to every process in the cgroup
+    pub fn kill(&self) {
+        // cgroup.kill is only available since Linux 5.14, fall back to signalling each member
+        if fs::write(self.path.join("cgroup.kill"), "1").is_ok() {
+            return;
+        }
+        for pid in self.pids() {
+            unsafe {
+                libc::kill(pid, libc::SIGKILL);
+            }
+        }
+    }
+
+    /// removes the cgroup, it has to be empty which may take a moment after its processes got killed
+    pub async fn remove(self) {
+        for _ in 0..50 {
+            match fs::remove_dir(&self.path) {
+                Ok(_) => {
+                    info!("removed cgroup {:?}", self.path);
+                    return;
+                }
+                Err(err) if err.raw_os_error() == Some(libc::EBUSY) => {
+                    debug!("cgroup {:?} still populated, retrying", self.path);
+                    sleep(Duration::from_millis(10)).await;
+                }
+                Err(err) => {
+                    error!("could not remove cgroup {:?}: {}", self.path, err);
+                    return;
+                }
+            }
+        }
+        error!("cgroup {:?} still populated, leaving it behind", self.path);
+    }
+
+    fn pids(&self) -> Vec<libc::pid_t> {
+        fs::read_to_string(self.path.join("cgroup.procs"))
+            .map(|procs| procs.lines().filter_map(|l| l.parse().ok()).collect())
+            .unwrap_or_default()
+    }
+
+    fn apply_limits(&self, limits: &ResourceLimits) -> io::Result<()> {
+        if let Some(weight) = limits.cpu_weight {
+            fs::write(self.path.join("cpu.weight"), weight.to_string())?;
+        }
+        if limits.cpu_quota_us.is_some() || limits.cpu_period_us.is_some() {
+            let quota = limits
+                .cpu_quota_us
+                .map_or("max".to_string(), |q| q.to_string());
+            let period = limits.cpu_period_us.unwrap_or(100_000);
+            fs::write(self.path.join("cpu.max"), format!("{} {}", quota, period))?;
+        }
+        if let Some(memory_max) = limits.memory_max {
+            fs::write(self.path.join("memory.max"), memory_max.to_string())?;
+            // without this the job could evade the limit by swapping
+            let _ = fs::write(self.path.join("memory.swap.max"), "0");
+        }
+        if let Some(pids_max) = limits.pids_max {
+            fs::write(self.path.join("pids.max"), pids_max.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// moves the calling process into the cgroup with the given cgroup.procs file
+/// runs between fork and exec, so only async-signal-safe functions may be used
+pub fn join(procs: &CStr) -> io::Result<()> {
+    unsafe {
+        // the file always exists in a real cgroup, O_CREAT lets tests use plain directories instead
+        let fd = libc::open(
+            procs.as_ptr(),
+            libc::O_WRONLY | libc::O_CREAT | libc::O_CLOEXEC,
+            0o644,
+        );
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // "0" refers to the writing process itself
+        let written = libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1);
+        let write_error = io::Error::last_os_error();
+        libc::close(fd);
+        if written < 0 {
+            return Err(write_error);
+        }
+    }
+    Ok(())
+}
+
+fn enable_controllers(parent: &Path, limits: &ResourceLimits) -> io::Result<()> {
+    let mut required = Vec::new();
+    if limits.cpu_weight.is_some()
+        || limits.cpu_quota_us.is_some()
+        || limits.cpu_period_us.is_some()
+    {
+        required.push("cpu");
+    }
+    if limits.memory_max.is_some() {
+        required.push("memory");
+    }
+    if limits.pids_max.is_some() {
+        required.push("pids");
+    }
+    let enabled = fs::read_to_string(parent.join("cgroup.subtree_control"))?;
+    let enabled: Vec<&str> = enabled.split_whitespace().collect();
+    for controller in required {
+        if !enabled.contains(&controller) {
+            debug!("enabling {} controller in {:?}", controller, parent);
+            fs::write(
+                parent.join("cgroup.subtree_control"),
+                format!("+{}", controller),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, path::PathBuf, process, time::Duration};
+
+    use rcmd_data::ResourceLimits;
+
+    use super::Cgroup;
+
+    /// fake cgroup parent with the cpu controller already enabled for its children
+    fn parent(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("rcmd-test-cgroup-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("cgroup.subtree_control"), "cpu io").unwrap();
+        dir
+    }
+
+    // testing that limits are written to the controller files and missing controllers are enabled
+    #[test]
+    fn test_apply_limits() {
+        let parent = parent("limits");
+        let limits = ResourceLimits {
+            cpu_weight: Some(50),
+            cpu_quota_us: Some(20_000),
+            memory_max: Some(64 * 1024 * 1024),
+            pids_max: Some(10),
+            ..Default::default()
+        };
+        let cgroup = Cgroup::create(&parent, "job", &limits).unwrap();
+        let read = |file: &str| fs::read_to_string(cgroup.path().join(file)).unwrap();
+        assert_eq!("50", read("cpu.weight"));
+        assert_eq!("20000 100000", read("cpu.max"));
+        assert_eq!("67108864", read("memory.max"));
+        assert_eq!("0", read("memory.swap.max"));
+        assert_eq!("10", read("pids.max"));
+        // each write to the real file enables one controller, here the last one wins
+        assert_eq!(
+            "+pids",
+            fs::read_to_string(parent.join("cgroup.subtree_control")).unwrap()
+        );
+        assert!(cgroup
+            .procs_path()
+            .to_str()
+            .unwrap()
+            .ends_with("job/cgroup.procs"));
+
+        // an unlimited quota with a custom period, unset limits are not written
+        let limits = ResourceLimits {
+            cpu_period_us: Some(50_000),
+            ..Default::default()
+        };
+        let cgroup = Cgroup::create(&parent, "unlimited", &limits).unwrap();
+        let read = |file: &str| fs::read_to_string(cgroup.path().join(file));
+        assert_eq!("max 50000", read("cpu.max").unwrap());
+        assert!(read("memory.max").is_err() && read("cpu.weight").is_err());
+
+        // an existing cgroup is not created again
+        assert!(Cgroup::create(&parent, "job", &limits).is_err());
+        fs::remove_dir_all(parent).unwrap();
+    }
+
+    // testing that OOM kills are detected from the memory events
+    #[test]
+    fn test_oom_killed() {
+        let parent = parent("oom");
+        fs::create_dir(parent.join("job")).unwrap();
+        let cgroup = Cgroup::open(&parent.join("job")).unwrap();
+        // memory controller not enabled
+        assert!(!cgroup.oom_killed());
+        let events = |oom_kill: u64| {
+            format!(
+                "low 0\nhigh 0\nmax 3\noom 1\noom_kill {}\noom_group_kill 0\n",
+                oom_kill
+            )
+        };
+        fs::write(cgroup.path().join("memory.events"), events(0)).unwrap();
+        assert!(!cgroup.oom_killed());
+        fs::write(cgroup.path().join("memory.events"), events(2)).unwrap();
+        assert!(cgroup.oom_killed());
+        assert!(Cgroup::open(&parent.join("missing")).is_err());
+        fs::remove_dir_all(parent).unwrap();
+    }
+
+    // testing the cpu times read from the cpu stats
+    #[test]
+    fn test_cpu_times() {
+        let parent = parent("cpu");
+        fs::create_dir(parent.join("job")).unwrap();
+        let cgroup = Cgroup::open(&parent.join("job")).unwrap();
+        assert_eq!(None, cgroup.cpu_times());
+        let stat = "usage_usec 3500\nuser_usec 2500\nsystem_usec 1000\nnr_periods 0\n";
+        fs::write(cgroup.path().join("cpu.stat"), stat).unwrap();
+        assert_eq!(
+            Some((Duration::from_micros(2500), Duration::from_micros(1000))),
+            cgroup.cpu_times()
+        );
+        fs::write(cgroup.path().join("cpu.stat"), "usage_usec 3500\n").unwrap();
+        assert_eq!(None, cgroup.cpu_times());
+        fs::remove_dir_all(parent).unwrap();
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const MINUTES_PER_DAY: u64 = 24 * 60;
+/// times further ahead are not searched, so expressions like "0 0 30 2 *" have no next time
+const MAX_SEARCH_DAYS: u64 = 5 * 366;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// cron expression with the fields minute, hour, day of month, month and day of week,
+/// each a comma separated list of `*`, values and ranges with an optional step, e.g. `*/15 8-18 * * mon-fri`
+/// fires at the start of each matching minute in UTC
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CronSchedule {
+    // bit sets of the matching values
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // if both are restricted, a day matches if either its day of month or its day of week does
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let expression = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expression => expression,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "cron expression needs 5 fields, got {}: {}",
+                fields.len(),
+                expression
+            ));
+        }
+        let weekdays = parse_field(fields[4], 0, 7, &WEEKDAYS)?;
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], 0, 59, &[])?,
+            hours: parse_field(fields[1], 0, 23, &[])?,
+            days: parse_field(fields[2], 1, 31, &[])?,
+            months: parse_field(fields[3], 1, 12, &MONTHS)?,
+            // 7 is sunday as well
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            days_restricted: !fields[2].starts_with('*'),
+            weekdays_restricted: !fields[4].starts_with('*'),
+        })
+    }
+}
+
+impl CronSchedule {
+    /// first time after <time> at which the schedule fires, None if there is none in the next years
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let secs = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        // start of the next minute, counted in minutes since the epoch
+        let mut minute = secs / 60 + 1;
+        let limit = minute + MAX_SEARCH_DAYS * MINUTES_PER_DAY;
+        while minute < limit {
+            let day = minute / MINUTES_PER_DAY;
+            let day_start = day * MINUTES_PER_DAY;
+            let hour = (minute - day_start) / 60;
+            if !self.day_matches(day) {
+                minute = day_start + MINUTES_PER_DAY;
+            } else if self.hours & (1 << hour) == 0 {
+                minute = day_start + (hour + 1) * 60;
+            } else if self.minutes & (1 << (minute % 60)) == 0 {
+                minute += 1;
+            } else {
+                return Some(UNIX_EPOCH + Duration::from_secs(minute * 60));
+            }
+        }
+        None
+    }
+
+    /// whether the schedule fires on the given day since the epoch
+    fn day_matches(&self, day: u64) -> bool {
+        let (month, day_of_month) = month_and_day(day);
+        if self.months & (1 << month) == 0 {
+            return false;
+        }
+        // the epoch was a thursday
+        let weekday = (day + 4) % 7;
+        let day_matches = self.days & (1 << day_of_month) != 0;
+        let weekday_matches = self.weekdays & (1 << weekday) != 0;
+        if self.days_restricted && self.weekdays_restricted {
+            day_matches || weekday_matches
+        } else {
+            day_matches && weekday_matches
+        }
+    }
+}
+
+/// bit set of the values in <min>..=<max> matched by a field, <names> can be used instead of values from <min> on
+fn parse_field(field: &str, min: u64, max: u64, names: &[&str]) -> Result<u64, String> {
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<usize>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("invalid step in cron field: {}", part)),
+            },
+            None => (part, 1),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (
+                parse_value(first, min, max, names)?,
+                parse_value(last, min, max, names)?,
+            ),
+            // a single value with a step runs up to the maximum
+            None if part.contains('/') => (parse_value(range, min, max, names)?, max),
+            None => {
+                let value = parse_value(range, min, max, names)?;
+                (value, value)
+            }
+        };
+        if first > last {
+            return Err(format!("invalid range in cron field: {}", part));
+        }
+        for value in (first..=last).step_by(step) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+fn parse_value(value: &str, min: u64, max: u64, names: &[&str]) -> Result<u64, String> {
+    let name = value.to_ascii_lowercase();
+    let parsed = match names.iter().position(|known| *known == name) {
+        Some(index) => index as u64 + min,
+        None => value
+            .parse()
+            .map_err(|_| format!("invalid value in cron field: {}", value))?,
+    };
+    if parsed < min || parsed > max {
+        return Err(format!(
+            "cron value {} out of range {}-{}",
+            parsed, min, max
+        ));
+    }
+    Ok(parsed)
+}
+
+/// month (1-12) and day of month of the given day since the epoch
+fn month_and_day(day: u64) -> (u64, u64) {
+    // days since 0000-03-01, so that leap days are at the end of a year
+    let days = day + 719_468;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // months counted from march
+    let month = (5 * day_of_year + 2) / 153;
+    let day_of_month = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    (month, day_of_month)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use super::CronSchedule;
+
+    /// the given time in UTC
+    fn utc(year: u64, month: u64, day: u64, hour: u64, minute: u64) -> SystemTime {
+        // days since 0000-03-01, as in month_and_day
+        let year = if month <= 2 { year - 1 } else { year };
+        let month = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * month + 2) / 5 + day - 1;
+        let days = year * 365 + year / 4 - year / 100 + year / 400 + day_of_year - 719_468;
+        UNIX_EPOCH + Duration::from_secs(days * 24 * 3600 + hour * 3600 + minute * 60)
+    }
+
+    fn schedule(expression: &str) -> CronSchedule {
+        expression.parse().unwrap()
+    }
+
+    fn bits(values: &[u64]) -> u64 {
+        values.iter().map(|value| 1 << value).sum()
+    }
+
+    // testing ranges, steps, lists and names in the fields
+    #[test]
+    fn test_parse() {
+        let parsed = schedule("1-3,10 */6 1-10/3 jan,MAR-may 7");
+        assert_eq!(bits(&[1, 2, 3, 10]), parsed.minutes);
+        assert_eq!(bits(&[0, 6, 12, 18]), parsed.hours);
+        assert_eq!(bits(&[1, 4, 7, 10]), parsed.days);
+        assert_eq!(bits(&[1, 3, 4, 5]), parsed.months);
+        // 7 is sunday, like 0
+        assert_eq!(bits(&[0]), parsed.weekdays);
+        assert!(parsed.days_restricted && parsed.weekdays_restricted);
+
+        let parsed = schedule("10/20 * * * mon-fri");
+        assert_eq!(bits(&[10, 30, 50]), parsed.minutes);
+        assert_eq!(bits(&(0..24).collect::<Vec<_>>()), parsed.hours);
+        assert_eq!(bits(&[1, 2, 3, 4, 5]), parsed.weekdays);
+        assert!(!parsed.days_restricted && parsed.weekdays_restricted);
+
+        assert_eq!(schedule("0 0 1 1 *"), schedule("@yearly"));
+        assert_eq!(schedule("0 * * * *"), schedule(" @hourly "));
+    }
+
+    // testing that invalid expressions are rejected
+    #[test]
+    fn test_invalid() {
+        for expression in [
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * 32 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "*/x * * * *",
+            "5-1 * * * *",
+            "1-2-3 * * * *",
+            "1,,2 * * * *",
+            "x * * * *",
+            "* * * foo *",
+            "* * * * sunday",
+            "@weekly 1",
+        ] {
+            assert!(
+                expression.parse::<CronSchedule>().is_err(),
+                "{:?} was accepted",
+                expression
+            );
+        }
+    }
+
+    // testing the next time skipping minutes, hours and days that do not match
+    #[test]
+    fn test_next_after() {
+        let every_quarter = schedule("*/15 8-18 * * mon-fri");
+        // a friday
+        let time = utc(2021, 10, 15, 9, 7);
+        assert_eq!(
+            Some(utc(2021, 10, 15, 9, 15)),
+            every_quarter.next_after(time)
+        );
+        // the start of a matching minute is not after itself
+        let time = utc(2021, 10, 15, 9, 15);
+        assert_eq!(
+            Some(utc(2021, 10, 15, 9, 30)),
+            every_quarter.next_after(time)
+        );
+        let time = utc(2021, 10, 15, 18, 45) + Duration::from_secs(30);
+        assert_eq!(
+            Some(utc(2021, 10, 18, 8, 0)),
+            every_quarter.next_after(time)
+        );
+    }
+
+    // testing that a day matches if either the restricted day of month or day of week does
+    #[test]
+    fn test_day_or_weekday() {
+        // friday the 1st, the 13th is a wednesday
+        let either = schedule("0 0 13 * fri");
+        let time = utc(2021, 10, 1, 0, 0);
+        assert_eq!(Some(utc(2021, 10, 8, 0, 0)), either.next_after(time));
+        let time = utc(2021, 10, 8, 0, 0);
+        assert_eq!(Some(utc(2021, 10, 13, 0, 0)), either.next_after(time));
+        let time = utc(2021, 10, 13, 0, 0);
+        assert_eq!(Some(utc(2021, 10, 15, 0, 0)), either.next_after(time));
+
+        // with only one restricted, both have to match
+        let time = utc(2021, 10, 1, 0, 0);
+        let day = schedule("0 0 13 * *");
+        assert_eq!(Some(utc(2021, 10, 13, 0, 0)), day.next_after(time));
+        let weekday = schedule("0 0 * * fri");
+        assert_eq!(Some(utc(2021, 10, 8, 0, 0)), weekday.next_after(time));
+        // a field starting with * does not restrict, so odd days that are fridays
+        let both = schedule("0 0 */2 * fri");
+        assert_eq!(Some(utc(2021, 10, 15, 0, 0)), both.next_after(time));
+    }
+
+    // testing days that do not exist in every month and rolling over into the next month and year
+    #[test]
+    fn test_month_end() {
+        let last = schedule("0 0 31 * *");
+        // april has 30 days
+        let time = utc(2021, 4, 1, 0, 0);
+        assert_eq!(Some(utc(2021, 5, 31, 0, 0)), last.next_after(time));
+        let leap_day = schedule("0 12 29 2 *");
+        let time = utc(2021, 3, 1, 0, 0);
+        assert_eq!(Some(utc(2024, 2, 29, 12, 0)), leap_day.next_after(time));
+        let first = schedule("@monthly");
+        let time = utc(2021, 2, 28, 23, 59);
+        assert_eq!(Some(utc(2021, 3, 1, 0, 0)), first.next_after(time));
+        let time = utc(2021, 12, 31, 23, 59);
+        assert_eq!(Some(utc(2022, 1, 1, 0, 0)), first.next_after(time));
+        let midnight = schedule("@daily");
+        let time = utc(2024, 2, 28, 0, 0);
+        assert_eq!(Some(utc(2024, 2, 29, 0, 0)), midnight.next_after(time));
+        // never exists
+        let never = schedule("0 0 30 2 *");
+        assert_eq!(None, never.next_after(utc(2021, 1, 1, 0, 0)));
+    }
+}
@@ -1,54 +1,154 @@
 use std::{
-    collections::HashMap,
-    process::{ExitStatus, Stdio},
-    sync::{atomic::AtomicU64, Arc},
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    env, mem,
+    os::unix::process::ExitStatusExt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64},
+        Arc, Weak,
+    },
+    time::{Duration, SystemTime},
 };
 
-pub use rcmd_data::{JobOutput, JobSpec, JobStatus};
+pub use rcmd_data::{
+    Attempt, Condition, Dependency, JobInfo, JobOutput, JobSpec, JobStatus, OutputChunk,
+    OutputLine, OutputRange, OutputStream, Priority, ResourceLimits, ResourceUsage, StdinMode,
+    UsageSummary, Workflow,
+};
 use tokio::{
-    io,
-    process::Command,
     sync::{
-        mpsc::{self, UnboundedReceiver},
-        oneshot, Mutex,
+        mpsc::{self, UnboundedSender},
+        oneshot, watch, Mutex,
     },
-    time::Instant,
+    task::JoinHandle,
+    time::sleep,
 };
 use tracing::{error, info, instrument};
 
-use crate::util::{manage_process, receive_all_lines, receive_lines_until};
+use crate::{
+    cgroup::Cgroup,
+    scheduler::RunSlot,
+    shim::{self, Shim, ShimJob},
+    signal, spec,
+    spool::{JobSpool, SpoolLimits, SpoolWriter, READ_LIMIT_MARKER},
+    store::{AttemptRecord, JobRecord, JobStore},
+    util::{
+        self, manage_process, Control, JobProcess, ManagerChannels, ProcessExit, StopRequest,
+        Timeout,
+    },
+};
 
-impl From<&JobState> for JobStatus {
-    fn from(state: &JobState) -> Self {
-        match state {
-            JobState::Running { .. } => JobStatus::Running,
-            JobState::Completed { exit_code } => JobStatus::Completed {
-                exit_code: *exit_code,
-            },
-            JobState::Terminated => JobStatus::Terminated,
-            JobState::Error { msg } => JobStatus::Error {
-                msg: msg.to_string(),
-            },
-        }
-    }
-}
+pub use crate::scheduler::Scheduler;
+
+/// grace period for stopping a job if neither the job spec nor the stop request set one
+pub const DEFAULT_STOP_GRACE: Duration = Duration::from_secs(10);
+
+/// stands in for delays too long to add to the current time
+const FAR_FUTURE: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// how often finished jobs are checked against the retention policy of their pool
+const RETENTION_INTERVAL: Duration = Duration::from_secs(10);
+
+/// number of evicted jobs per pool whose reason is remembered, older ones are then unknown
+const MAX_EVICTED: usize = 10_000;
 
 enum JobState {
+    /// waiting for its dependencies to finish, queued once they did
+    Waiting {
+        // dropping it cancels the wait
+        _cancel_tx: oneshot::Sender<()>,
+        stdin_tx: Option<UnboundedSender<Vec<u8>>>,
+        output_rx: watch::Receiver<()>,
+    },
+    /// waiting to queue the next attempt after the previous one failed
+    Retrying {
+        // dropping it cancels the retry
+        _cancel_tx: oneshot::Sender<()>,
+        // of the next attempt
+        output_rx: watch::Receiver<()>,
+        retry_at: SystemTime,
+    },
+    /// waiting for a free slot
+    Queued {
+        // dropping it cancels the wait
+        _cancel_tx: oneshot::Sender<()>,
+        stdin_tx: Option<UnboundedSender<Vec<u8>>>,
+        output_rx: watch::Receiver<()>,
+    },
     Running {
-        // TODO: unbounded channels can lead to excessive memory usage
-        //       a more advanced implementation would spill to disk
-        stdout_rx: UnboundedReceiver<(String, Instant)>,
-        stderr_rx: UnboundedReceiver<(String, Instant)>,
-        exit_rx: oneshot::Receiver<io::Result<ExitStatus>>,
-        kill_tx: oneshot::Sender<()>,
+        exit_rx: oneshot::Receiver<ProcessExit>,
+        control_tx: UnboundedSender<Control>,
+        // open while the job's stdin is a pipe that was not closed yet
+        stdin_tx: Option<UnboundedSender<Vec<u8>>>,
+        // paused with SIGSTOP through rcmd, only signals sent by rcmd are tracked
+        paused: bool,
+        // changes whenever new output arrived or the process exited, for following the output
+        output_rx: watch::Receiver<()>,
+        // set if the process is run by a shim, which a restarted server can reattach to
+        shim_pid: Option<u32>,
     },
     Completed {
         exit_code: i32,
     },
-    Terminated,
+    Terminated {
+        signal: libc::c_int,
+        core_dumped: bool,
+        by_rcmd: bool,
+    },
+    OomKilled,
+    Halted {
+        signal: libc::c_int,
+        grace: Duration,
+        escalated: bool,
+        exit_code: Option<i32>,
+    },
+    TimedOut {
+        timeout: Duration,
+        escalated: bool,
+    },
+    Skipped {
+        dependency: u64,
+    },
     Error {
         msg: String,
     },
+    /// finished before the server restarted, restored from the store with its final status
+    Restored {
+        status: JobStatus,
+    },
+}
+
+impl JobState {
+    /// receiver for output notifications, closed once the job finished, None if it finished already
+    fn output_rx(&self) -> Option<watch::Receiver<()>> {
+        match self {
+            JobState::Waiting { output_rx, .. }
+            | JobState::Retrying { output_rx, .. }
+            | JobState::Queued { output_rx, .. }
+            | JobState::Running { output_rx, .. } => Some(output_rx.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// whether <condition> on a dependency in <state> is met, None while the dependency did not finish
+fn condition_met(condition: Condition, state: &JobState) -> Option<bool> {
+    match state {
+        JobState::Waiting { .. }
+        | JobState::Retrying { .. }
+        | JobState::Queued { .. }
+        | JobState::Running { .. } => None,
+        JobState::Skipped { .. }
+        | JobState::Restored {
+            status: JobStatus::Skipped { .. },
+        } => Some(condition == Condition::Always),
+        JobState::Completed { exit_code: 0 }
+        | JobState::Restored {
+            status: JobStatus::Completed { exit_code: 0 },
+        } => Some(condition != Condition::OnFailure),
+        _ => Some(condition != Condition::OnSuccess),
+    }
 }
 
 struct Job {
@@ -56,90 +156,491 @@ struct Job {
     pid: Option<u32>,
     spec: JobSpec,
     state: JobState,
-    output: JobOutput,
+    // None if the job failed before its output could be stored
+    spool: Option<Arc<JobSpool>>,
+    submitted_at: SystemTime,
+    // None if the process could not be spawned
+    started_at: Option<SystemTime>,
+    // set once the job is not running anymore
+    ended_at: Option<SystemTime>,
+    // set once the job's process exited
+    usage: Option<ResourceUsage>,
+    // the fields above are about the current attempt, counting from 1
+    attempt: u32,
+    attempts: Vec<PastAttempt>,
+    // set if the job has a retry policy
+    retrier: Option<Retrier>,
+    // set if the job was submitted by a schedule
+    schedule: Option<u64>,
+}
+
+/// finished attempt of a retried job along with its output
+struct PastAttempt {
+    info: Attempt,
+    spool: Option<Arc<JobSpool>>,
+}
+
+/// settings shared by all jobs of a pool
+#[derive(Clone, Debug, Default)]
+pub struct JobPoolConfig {
+    /// cgroup (v2) under which jobs with resource limits get their own leaf cgroup
+    /// the server process needs write access to it
+    pub cgroup_parent: Option<PathBuf>,
+    /// directory for files holding the jobs' output, defaults to rcmd-spool in the temp directory
+    pub spool_dir: Option<PathBuf>,
+    /// maximum bytes of output stored per job, further output is replaced by a truncation marker
+    pub max_job_output: Option<u64>,
+    /// maximum bytes of output stored for all jobs of the pools sharing <spool_usage>
+    pub max_spool_size: Option<u64>,
+    /// bytes currently used by spool files, shared by all pools created with clones of this config
+    pub spool_usage: Arc<AtomicU64>,
+    /// timeout of jobs that did not set one, the maximum timeout if not set
+    pub default_timeout: Option<Duration>,
+    /// jobs with a longer timeout are rejected
+    pub max_timeout: Option<Duration>,
+    /// maximum number of jobs of the pool running at once, further jobs are queued
+    pub max_running_jobs: Option<usize>,
+    /// share of the scheduler's run slots relative to the other pools, defaults to 1
+    pub weight: Option<u32>,
+    /// shares the run slots between all pools created with clones of this config
+    pub scheduler: Scheduler,
+    /// directory for the records of the jobs, if set finished jobs and their output are restored
+    /// when a pool with the same name is created again, e.g. after a restart of the server
+    pub state_dir: Option<PathBuf>,
+    /// rcmd_shim binary supervising each job in a process of its own, only used along with <state_dir>
+    /// the jobs then keep running when the pool is dropped and are reattached once it is restored,
+    /// except jobs with piped stdin, whose input only the pool can write
+    pub shim: Option<PathBuf>,
+    /// how long finished jobs are kept before they are evicted, by default until they are deleted
+    pub retention: Retention,
+}
+
+/// limits on the finished jobs of a pool, beyond which the oldest ones are evicted along with their output
+/// finished jobs that unfinished jobs depend on are kept
+#[derive(Clone, Debug, Default)]
+pub struct Retention {
+    /// time since a job finished
+    pub max_age: Option<Duration>,
+    /// number of finished jobs
+    pub max_jobs: Option<usize>,
+    /// bytes of output of all finished jobs, including earlier attempts
+    pub max_output: Option<u64>,
+}
+
+impl Retention {
+    fn is_set(&self) -> bool {
+        self.max_age.is_some() || self.max_jobs.is_some() || self.max_output.is_some()
+    }
 }
 
 pub struct JobPool {
+    name: String,
+    config: JobPoolConfig,
     // using counter instead of uuid for more convenient usage from client
     // amount of jobs should not be considered private
     next_job_id: AtomicU64,
     jobs: Arc<Mutex<HashMap<u64, Job>>>,
+    launcher: Launcher,
+    // set once shutdown started, jobs submitted afterwards fail
+    shut_down: AtomicBool,
+    // cleared once the pool stops accepting new jobs, e.g. while the server drains
+    accepting: AtomicBool,
+    // why the most recently evicted jobs were evicted, by id
+    evicted: Evicted,
+}
+
+/// reasons of the most recently evicted jobs of a pool by id, at most MAX_EVICTED
+type Evicted = Arc<std::sync::Mutex<BTreeMap<u64, String>>>;
+
+/// join handles of the process managers of a pool, finished ones are dropped as new ones are added
+/// along with each handle a token held by the manager's task, which is gone once it finished
+type Managers = Arc<std::sync::Mutex<Vec<(Weak<()>, JoinHandle<()>)>>>;
+
+/// prepares the launches of a pool's jobs, shared with the tasks retrying jobs
+#[derive(Clone)]
+struct Launcher {
+    pool: String,
+    config: JobPoolConfig,
+    // updated by the process managers, so usage of deleted jobs is kept
+    usage_summary: Arc<std::sync::Mutex<UsageSummary>>,
+    // set if the pool has a state directory
+    store: Option<Arc<JobStore>>,
+    managers: Managers,
+}
+
+/// launches the next attempts of a retried job
+#[derive(Clone)]
+struct Retrier {
+    // weak, as the jobs own their retrier
+    jobs: Weak<Mutex<HashMap<u64, Job>>>,
+    launcher: Launcher,
+}
+
+/// launch of a job along with the spool and the channels it was prepared with
+struct Prepared {
+    launch: Launch,
+    spool: Arc<JobSpool>,
+    stdin_tx: Option<UnboundedSender<Vec<u8>>>,
+    output_rx: watch::Receiver<()>,
+}
+
+/// everything needed to start the process of a job, prepared when the job is submitted
+struct Launch {
+    id: u64,
+    spec: JobSpec,
+    cgroup_parent: Option<PathBuf>,
+    cgroup_name: String,
+    // set if the job is run by a shim, which writes to the spool itself
+    shim: Option<PathBuf>,
+    spool: Arc<JobSpool>,
+    max_job_output: Option<u64>,
+    stdout: SpoolWriter,
+    stderr: SpoolWriter,
+    stdin: Option<mpsc::UnboundedReceiver<Vec<u8>>>,
+    output_changed: watch::Sender<()>,
+    usage_summary: Arc<std::sync::Mutex<UsageSummary>>,
+    managers: Managers,
+}
+
+/// handles of a job's started process
+struct Started {
+    pid: Option<u32>,
+    shim_pid: Option<u32>,
+    exit_rx: oneshot::Receiver<ProcessExit>,
+    control_tx: UnboundedSender<Control>,
+}
+
+/// follows the output of a single job, created with JobPool::follow
+pub struct OutputFollower {
+    id: u64,
+    jobs: Arc<Mutex<HashMap<u64, Job>>>,
+    spool: Option<Arc<JobSpool>>,
+    // None once the job's process manager finished
+    output_rx: Option<watch::Receiver<()>>,
+    // byte offsets up to which each stream was already returned
+    stdout_pos: u64,
+    stderr_pos: u64,
 }
 
 impl JobPool {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        Self {
-            next_job_id: AtomicU64::new(0),
+        Self::with_config("default", JobPoolConfig::default())
+    }
+
+    /// creates a pool, the name has to be unique among pools sharing the same config
+    /// with a state directory, the jobs stored for a pool of the same name are restored,
+    /// those that did not finish are marked lost
+    pub fn with_config(name: &str, config: JobPoolConfig) -> Self {
+        config
+            .scheduler
+            .add_pool(name, config.max_running_jobs, config.weight.unwrap_or(1));
+        let store = config
+            .state_dir
+            .as_ref()
+            .and_then(|dir| match JobStore::open(dir, name) {
+                Ok(store) => Some(Arc::new(store)),
+                Err(err) => {
+                    error!(
+                        "could not open job store of pool {} in {:?}, jobs are not persisted: {}",
+                        name, dir, err
+                    );
+                    None
+                }
+            });
+        let launcher = Launcher {
+            pool: name.to_string(),
+            config: config.clone(),
+            usage_summary: Arc::new(std::sync::Mutex::new(UsageSummary {
+                client: name.to_string(),
+                ..Default::default()
+            })),
+            store,
+            managers: Default::default(),
+        };
+        let (restored, next_job_id) = launcher.restore();
+        let evicted = launcher.store.as_ref().map_or_else(BTreeMap::new, |store| {
+            store.load_evicted().unwrap_or_else(|err| {
+                error!("could not read evicted jobs of pool {}: {}", name, err);
+                BTreeMap::new()
+            })
+        });
+        let pool = Self {
+            name: name.to_string(),
+            config,
+            next_job_id: AtomicU64::new(next_job_id),
             jobs: Arc::new(Mutex::new(HashMap::new())),
+            launcher,
+            shut_down: AtomicBool::new(false),
+            accepting: AtomicBool::new(true),
+            evicted: Arc::new(std::sync::Mutex::new(evicted)),
+        };
+        // reattached jobs are watched like freshly started ones
+        let mut running = Vec::new();
+        for (id, mut job) in restored {
+            if job.state.output_rx().is_some() {
+                if job.spec.retry.is_some() {
+                    job.retrier = Some(Retrier {
+                        jobs: Arc::downgrade(&pool.jobs),
+                        launcher: pool.launcher.clone(),
+                    });
+                }
+                running.push(id);
+            }
+            // just created, nobody else holds the lock
+            pool.jobs.try_lock().unwrap().insert(id, job);
+        }
+        for id in running {
+            tokio::spawn(watch_job(
+                Arc::downgrade(&pool.jobs),
+                pool.launcher.clone(),
+                id,
+            ));
         }
+        if pool.config.retention.is_set() {
+            tokio::spawn(reap_finished(
+                Arc::downgrade(&pool.jobs),
+                pool.evicted.clone(),
+                pool.launcher.clone(),
+            ));
+        }
+        pool
+    }
+
+    /// names of the pools with jobs stored in the state directory of <config>, to restore them eagerly
+    pub fn stored_pools(config: &JobPoolConfig) -> Vec<String> {
+        let dir = match &config.state_dir {
+            Some(dir) => dir,
+            None => return Vec::new(),
+        };
+        JobStore::pools(dir).unwrap_or_else(|err| {
+            error!("could not read state directory {:?}: {}", dir, err);
+            Vec::new()
+        })
     }
 
     /// submit a job for execution
     /// always succeeds with a job id, errors have to be checked with status
-    #[instrument(skip(self))]
     pub async fn submit(&self, command: &str, args: &[&str]) -> u64 {
+        let spec = JobSpec::new(command, args);
+        if let Err(msg) = self.check_accepting() {
+            info!("job pool does not accept jobs, not starting job");
+            let id = self
+                .next_job_id
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let state = JobState::Error { msg };
+            return self
+                .insert_job(id, None, spec, None, state, SystemTime::now(), None)
+                .await;
+        }
+        self.spawn_job(spec, None).await
+    }
+
+    /// submit a job with a full spec for execution
+    /// fails without creating a job if the spec contains invalid values,
+    /// errors when starting the job have to be checked with status
+    #[instrument(skip(self))]
+    pub async fn submit_spec(&self, spec: JobSpec) -> Result<u64, String> {
+        self.check_accepting()?;
+        if let Err(err) = self.validate(&spec).await {
+            info!("rejecting invalid job spec: {}", err);
+            return Err(err);
+        }
+        Ok(self.spawn_job(spec, None).await)
+    }
+
+    /// submit a job on behalf of the schedule with the given id, validated like submit_spec
+    pub(crate) async fn submit_scheduled(
+        &self,
+        spec: JobSpec,
+        schedule: u64,
+    ) -> Result<u64, String> {
+        self.check_accepting()?;
+        self.validate(&spec).await?;
+        Ok(self.spawn_job(spec, Some(schedule)).await)
+    }
+
+    /// rejects new jobs from now on (jobs submitted by command only get an error status),
+    /// e.g. while the server drains, already submitted jobs are still started and retried
+    pub fn stop_accepting(&self) {
+        info!("job pool stops accepting jobs");
+        self.accepting
+            .store(false, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// whether the pool still accepts new jobs, see stop_accepting
+    pub fn accepting(&self) -> bool {
+        self.accepting.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    fn check_accepting(&self) -> Result<(), String> {
+        if self.accepting() {
+            Ok(())
+        } else {
+            Err("job pool does not accept new jobs".to_string())
+        }
+    }
+
+    /// submit the jobs of a workflow, each one after the jobs it depends on
+    /// fails without creating any job if a spec is invalid or the dependencies contain a cycle,
+    /// otherwise returns the ids of the jobs by name
+    #[instrument(skip(self))]
+    pub async fn submit_workflow(
+        &self,
+        workflow: Workflow,
+    ) -> Result<HashMap<String, u64>, String> {
+        self.check_accepting()?;
+        let order = spec::workflow_order(&workflow)?;
+        for job in &workflow.jobs {
+            if let Err(err) = self.validate(&job.spec).await {
+                info!("rejecting workflow with invalid job spec: {}", err);
+                return Err(format!("invalid spec of job {}: {}", job.name, err));
+            }
+        }
+        let mut ids = HashMap::new();
+        for index in order {
+            let job = &workflow.jobs[index];
+            let mut spec = job.spec.clone();
+            for dependency in &job.after {
+                spec.dependencies.push(Dependency {
+                    job: ids[&dependency.job],
+                    condition: dependency.condition,
+                });
+            }
+            let id = self.spawn_job(spec, None).await;
+            ids.insert(job.name.clone(), id);
+        }
+        Ok(ids)
+    }
+
+    /// checks the spec, including that the jobs it depends on exist
+    pub(crate) async fn validate(&self, spec: &JobSpec) -> Result<(), String> {
+        spec::validate(spec, &self.config)?;
+        let jobs = self.jobs.lock().await;
+        match spec
+            .dependencies
+            .iter()
+            .find(|dependency| !jobs.contains_key(&dependency.job))
+        {
+            Some(dependency) => Err(format!("dependency on unknown job {}", dependency.job)),
+            None => Ok(()),
+        }
+    }
+
+    async fn spawn_job(&self, mut spec: JobSpec, schedule: Option<u64>) -> u64 {
         let id = self
             .next_job_id
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let submitted_at = SystemTime::now();
         info!("try to spawn process of job with id {}", id);
-        // spawn process and pipe stdout/stderr
-        let process = Command::new(command)
-            .args(args)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn();
-        let mut pid = None;
-        let state = match process {
-            Ok(process) => {
-                pid = process.id();
-                info!("process spawned, pid: {:?}", pid);
-                // channels for std streams, exit and kill signal
-                let (stdout_tx, stdout_rx) = mpsc::unbounded_channel::<(String, Instant)>();
-                let (stderr_tx, stderr_rx) = mpsc::unbounded_channel::<(String, Instant)>();
-                let (exit_tx, exit_rx) = oneshot::channel::<io::Result<ExitStatus>>();
-                let (kill_tx, kill_rx) = oneshot::channel::<()>();
-                // spawn manager task that updates stream/exit channels and listens for kill signal
-                let _ = tokio::spawn(manage_process(
-                    id, process, stdout_tx, stderr_tx, exit_tx, kill_rx,
-                ));
-                JobState::Running {
-                    stdout_rx,
-                    stderr_rx,
-                    exit_rx,
-                    kill_tx,
-                }
-            }
+        spec.timeout = spec
+            .timeout
+            .or(self.config.default_timeout)
+            .or(self.config.max_timeout);
+        if self.shut_down.load(std::sync::atomic::Ordering::Relaxed) {
+            info!("job pool is shut down, not starting job");
+            let state = JobState::Error {
+                msg: "job pool is shut down".to_string(),
+            };
+            return self
+                .insert_job(id, None, spec, None, state, submitted_at, schedule)
+                .await;
+        }
+        let Prepared {
+            launch,
+            spool,
+            stdin_tx,
+            output_rx,
+        } = match self.launcher.prepare(id, &spec, 1) {
+            Ok(prepared) => prepared,
             Err(err) => {
-                info!("process could not be spawned, error: {:?}", err);
-                JobState::Error {
-                    msg: err.to_string(),
-                }
+                info!("spool files could not be created, error: {}", err);
+                let state = JobState::Error {
+                    msg: format!("could not set up output spool: {}", err),
+                };
+                return self
+                    .insert_job(id, None, spec, None, state, submitted_at, schedule)
+                    .await;
             }
         };
-        let output = JobOutput::new();
-        let spec = JobSpec::new(command, args);
-        let job = Job {
-            id,
-            pid,
-            spec,
-            state,
-            output,
+        if !spec.dependencies.is_empty() {
+            info!("waiting for dependencies");
+            let (cancel_tx, cancel_rx) = oneshot::channel();
+            let state = JobState::Waiting {
+                _cancel_tx: cancel_tx,
+                stdin_tx,
+                output_rx,
+            };
+            self.insert_job(id, None, spec, Some(spool), state, submitted_at, schedule)
+                .await;
+            let launcher = self.launcher.clone();
+            tokio::spawn(await_dependencies(
+                self.jobs.clone(),
+                launcher,
+                launch,
+                cancel_rx,
+            ));
+            return id;
+        }
+        let mut start = self.launcher.enqueue(id, spec.priority);
+        let slot = match start.try_recv() {
+            Ok(slot) => slot,
+            Err(_) => {
+                info!("no free slot, queueing job");
+                let (cancel_tx, cancel_rx) = oneshot::channel();
+                let state = JobState::Queued {
+                    _cancel_tx: cancel_tx,
+                    stdin_tx,
+                    output_rx,
+                };
+                self.insert_job(id, None, spec, Some(spool), state, submitted_at, schedule)
+                    .await;
+                tokio::spawn(start_queued(
+                    self.jobs.clone(),
+                    self.launcher.clone(),
+                    launch,
+                    start,
+                    cancel_rx,
+                ));
+                return id;
+            }
         };
-        self.jobs.lock().await.insert(id, job);
-        id
+        let (pid, state) = match launch.start(slot).await {
+            Ok(started) => (
+                started.pid,
+                JobState::Running {
+                    exit_rx: started.exit_rx,
+                    control_tx: started.control_tx,
+                    stdin_tx,
+                    paused: false,
+                    output_rx,
+                    shim_pid: started.shim_pid,
+                },
+            ),
+            Err(msg) => (None, JobState::Error { msg }),
+        };
+        self.insert_job(id, pid, spec, Some(spool), state, submitted_at, schedule)
+            .await
     }
 
     /// deletes job if exists and returns None
-    /// associated process is guaranteed to have been terminated
+    /// associated process and all processes of its process group are guaranteed to have been terminated
+    /// (descendants that started their own session can only be tracked with resource limits set)
+    /// a waiting or queued job is removed without ever starting, jobs depending on it get skipped
     /// if job ends up in error state, returns Some(error message)
     #[instrument(skip(self))]
     pub async fn delete(&self, id: u64) -> Option<Result<(), String>> {
         info!("try to delete job");
         let mut jobs = self.jobs.lock().await;
         let job = jobs.remove(&id)?;
+        if let Some(store) = &self.launcher.store {
+            if let Err(err) = store.remove(id) {
+                error!("could not remove record of job: {}", err);
+            }
+        }
+        job.discard_output();
         if let JobState::Running { .. } = job.state {
-            let job = self.update_job_state(job, true).await;
+            let job = update_job_state(job, Some(StopRequest::kill())).await;
             if let JobState::Error { msg } = job.state {
                 let msg = format!("deletion resulted in error state: {}", msg);
                 error!("{}", &msg);
@@ -150,30 +651,338 @@ impl JobPool {
         Some(Ok(()))
     }
 
+    /// stops job if exists by sending <signal> (default SIGTERM) to its process group
+    /// kills the group if it did not exit within <grace> (default from job spec or DEFAULT_STOP_GRACE)
+    /// unlike delete the job is kept, returns its final status
+    /// if job was not running anymore, its status is returned unchanged
+    #[instrument(skip(self))]
+    pub async fn stop(
+        &self,
+        id: u64,
+        signal: Option<libc::c_int>,
+        grace: Option<Duration>,
+    ) -> Option<JobStatus> {
+        info!("try to stop job");
+        let mut sent: Option<StopRequest> = None;
+        loop {
+            let mut output_rx = {
+                let mut jobs = self.jobs.lock().await;
+                let job = match (jobs.remove(&id), sent) {
+                    (Some(job), _) => update_job_state(job, None).await,
+                    (None, None) => return None,
+                    // deleted while the stop waited for the job to exit, the delete killed it at the latest
+                    (None, Some(request)) => {
+                        info!("job was deleted while stopping");
+                        return Some(JobStatus::Halted {
+                            signal: signal::name(request.signal),
+                            grace: request.grace,
+                            escalated: false,
+                            exit_code: None,
+                        });
+                    }
+                };
+                let request = StopRequest {
+                    signal: signal.unwrap_or(libc::SIGTERM),
+                    grace: grace.or(job.spec.stop_grace).unwrap_or(DEFAULT_STOP_GRACE),
+                };
+                match &job.state {
+                    JobState::Running {
+                        control_tx,
+                        output_rx,
+                        ..
+                    } => {
+                        if control_tx.send(Control::Stop(request)).is_err() {
+                            info!("stop request channel receiver dropped, process already exited");
+                        }
+                        let output_rx = output_rx.clone();
+                        jobs.insert(id, job);
+                        sent = Some(request);
+                        output_rx
+                    }
+                    _ => {
+                        let job = update_job_state(job, Some(request)).await;
+                        let status = job.status(&jobs);
+                        jobs.insert(id, job);
+                        info!("stopped job");
+                        return Some(status);
+                    }
+                }
+            };
+            // waited for without the lock, other jobs of the pool stay accessible during the grace period
+            // closed once the manager finished, the job's state is then updated in the next iteration
+            while output_rx.changed().await.is_ok() {}
+        }
+    }
+
+    /// stops all jobs like stop with SIGTERM and <grace>, then waits until all process managers finished,
+    /// so no process of the pool's jobs is left running (see delete for the limits of this guarantee)
+    /// jobs run by a shim are left running and removed from the pool, they are reattached once restored
+    /// jobs that did not start yet are halted, jobs submitted afterwards fail right away
+    #[instrument(skip(self))]
+    pub async fn shutdown(&self, grace: Option<Duration>) {
+        info!("shutting down job pool");
+        self.shut_down
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+        let mut jobs = self.jobs.lock().await;
+        let ids: Vec<u64> = jobs.keys().copied().collect();
+        let mut stopping = Vec::new();
+        for id in ids {
+            let job = jobs.remove(&id).unwrap();
+            match &job.state {
+                // dropping the job closes the control channel, upon which its manager detaches
+                JobState::Running {
+                    shim_pid: Some(shim_pid),
+                    ..
+                } => info!("leaving job {} to its shim {}", id, shim_pid),
+                // stopped concurrently, so shutdown takes at most one grace period
+                JobState::Waiting { .. }
+                | JobState::Retrying { .. }
+                | JobState::Queued { .. }
+                | JobState::Running { .. } => {
+                    let request = StopRequest {
+                        signal: libc::SIGTERM,
+                        grace: grace.or(job.spec.stop_grace).unwrap_or(DEFAULT_STOP_GRACE),
+                    };
+                    stopping.push(tokio::spawn(update_job_state(job, Some(request))));
+                }
+                _ => {
+                    jobs.insert(id, job);
+                }
+            }
+        }
+        for handle in stopping {
+            match handle.await {
+                Ok(job) => {
+                    self.launcher.save(&job, &jobs);
+                    jobs.insert(job.id, job);
+                }
+                Err(err) => error!("unexpected error when stopping job: {}", err),
+            }
+        }
+        drop(jobs);
+        let managers = mem::take(&mut *self.launcher.managers.lock().unwrap());
+        for (_, manager) in managers {
+            if let Err(err) = manager.await {
+                error!("unexpected error when joining process manager: {}", err);
+            }
+        }
+        info!("job pool shut down");
+    }
+
+    /// sends signal to the process group of a running job if job exists
+    /// SIGSTOP/SIGCONT pause and resume the job, a paused job has status Stopped
+    #[instrument(skip(self))]
+    pub async fn signal(&self, id: u64, signal: libc::c_int) -> Option<Result<(), String>> {
+        info!("try to signal job");
+        let mut jobs = self.jobs.lock().await;
+        let job = jobs.remove(&id)?;
+        let mut job = update_job_state(job, None).await;
+        let result = match &mut job.state {
+            JobState::Running {
+                control_tx, paused, ..
+            } => {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                let control = Control::Signal {
+                    signal,
+                    reply: reply_tx,
+                };
+                // if the manager already finished, the reply sender is dropped along with the command
+                let _ = control_tx.send(control);
+                match reply_rx.await {
+                    Ok(Ok(_)) => {
+                        match signal {
+                            libc::SIGSTOP => *paused = true,
+                            libc::SIGCONT => *paused = false,
+                            _ => {}
+                        }
+                        Ok(())
+                    }
+                    Ok(Err(err)) => Err(format!("could not send signal: {}", err)),
+                    Err(_) => Err("job is not running".to_string()),
+                }
+            }
+            _ => Err("job is not running".to_string()),
+        };
+        jobs.insert(id, job);
+        info!("signal result: {:?}", result);
+        Some(result)
+    }
+
+    /// queues data to be written to the stdin pipe of a running job if job exists
+    #[instrument(skip(self, data))]
+    pub async fn write_stdin(&self, id: u64, data: Vec<u8>) -> Option<Result<(), String>> {
+        info!("try to write {} bytes to stdin", data.len());
+        let jobs = self.jobs.lock().await;
+        let job = jobs.get(&id)?;
+        let result = match &job.state {
+            JobState::Waiting {
+                stdin_tx: Some(stdin_tx),
+                ..
+            }
+            | JobState::Queued {
+                stdin_tx: Some(stdin_tx),
+                ..
+            }
+            | JobState::Running {
+                stdin_tx: Some(stdin_tx),
+                ..
+            } => stdin_tx
+                .send(data)
+                .map_err(|_| "job is not reading stdin anymore".to_string()),
+            JobState::Waiting { .. } | JobState::Queued { .. } | JobState::Running { .. } => {
+                Err("stdin of job is not an open pipe".to_string())
+            }
+            _ => Err("job is not running".to_string()),
+        };
+        Some(result)
+    }
+
+    /// closes the stdin pipe of a running job if job exists, the job reads EOF after all queued data
+    #[instrument(skip(self))]
+    pub async fn close_stdin(&self, id: u64) -> Option<Result<(), String>> {
+        info!("try to close stdin");
+        let mut jobs = self.jobs.lock().await;
+        let job = jobs.get_mut(&id)?;
+        let result = match &mut job.state {
+            JobState::Waiting { stdin_tx, .. }
+            | JobState::Queued { stdin_tx, .. }
+            | JobState::Running { stdin_tx, .. } => match stdin_tx.take() {
+                Some(_) => Ok(()),
+                None => Err("stdin of job is not an open pipe".to_string()),
+            },
+            _ => Err("job is not running".to_string()),
+        };
+        Some(result)
+    }
+
+    /// changes the priority of a job if exists, only possible while it is waiting or queued
+    #[instrument(skip(self))]
+    pub async fn set_priority(&self, id: u64, priority: Priority) -> Option<Result<(), String>> {
+        info!("try to set priority");
+        let mut jobs = self.jobs.lock().await;
+        let job = jobs.get_mut(&id)?;
+        let result = match job.state {
+            // the scheduler may have handed out a slot to the job already
+            JobState::Queued { .. }
+                if self.config.scheduler.set_priority(&self.name, id, priority) =>
+            {
+                job.spec.priority = priority;
+                Ok(())
+            }
+            // queued with the priority of the spec once its dependencies finished
+            JobState::Waiting { .. } => {
+                job.spec.priority = priority;
+                Ok(())
+            }
+            _ => Err("job is not queued".to_string()),
+        };
+        Some(result)
+    }
+
     /// gets job status if job exists
     #[instrument(skip(self))]
     pub async fn status(&self, id: u64) -> Option<JobStatus> {
         info!("try to get status");
         let mut jobs = self.jobs.lock().await;
         let job = jobs.remove(&id)?;
-        let job = self.update_job_state(job, false).await;
-        let status = Some(JobStatus::from(&job.state));
+        let job = update_job_state(job, None).await;
+        let status = Some(job.status(&jobs));
         jobs.insert(id, job);
         info!("returning status");
         status
     }
 
-    /// gets job output if job exists
+    /// gets job status along with its pid and timestamps if job exists
     #[instrument(skip(self))]
-    pub async fn output(&self, id: u64) -> Option<JobOutput> {
-        info!("try to get output");
+    pub async fn info(&self, id: u64) -> Option<JobInfo> {
+        info!("try to get info");
         let mut jobs = self.jobs.lock().await;
         let job = jobs.remove(&id)?;
-        let job = self.update_job_state(job, false).await;
-        let output = Some(job.output.clone());
+        let job = update_job_state(job, None).await;
+        let info = job.info(&self.name, &jobs);
         jobs.insert(id, job);
+        info!("returning info");
+        Some(info)
+    }
+
+    /// gets job output if job exists
+    #[instrument(skip(self))]
+    pub async fn output(&self, id: u64) -> Option<JobOutput> {
+        info!("try to get output");
+        let spool = self.spool(id).await?;
         info!("got output");
-        output
+        Some(read_output(spool))
+    }
+
+    /// gets the output of the given attempt of a retried job if job exists
+    #[instrument(skip(self))]
+    pub async fn attempt_output(&self, id: u64, attempt: u32) -> Option<Result<JobOutput, String>> {
+        info!("try to get output of attempt");
+        let spool = {
+            let jobs = self.jobs.lock().await;
+            let job = jobs.get(&id)?;
+            match job.attempts.iter().find(|past| past.info.number == attempt) {
+                Some(past) => Ok(past.spool.clone()),
+                None if attempt == job.attempt => Ok(job.spool.clone()),
+                None => Err(format!("job has no attempt {}", attempt)),
+            }
+        };
+        Some(spool.map(read_output))
+    }
+
+    /// gets part of a job's stdout or stderr if job exists
+    #[instrument(skip(self))]
+    pub async fn output_range(
+        &self,
+        id: u64,
+        stream: OutputStream,
+        range: OutputRange,
+    ) -> Option<Result<OutputChunk, String>> {
+        info!("try to get output range");
+        let result = match self.spool(id).await? {
+            Some(spool) => spool
+                .chunk(stream, range)
+                .map_err(|err| format!("could not read output: {}", err)),
+            None => Ok(OutputChunk {
+                offset: 0,
+                next_offset: 0,
+                size: 0,
+                data: Vec::new(),
+            }),
+        };
+        info!("got output range");
+        Some(result)
+    }
+
+    /// gets output of both streams in the order it was read if job exists
+    #[instrument(skip(self))]
+    pub async fn combined_output(&self, id: u64) -> Option<Result<Vec<OutputLine>, String>> {
+        info!("try to get combined output");
+        let result = match self.spool(id).await? {
+            Some(spool) => spool
+                .combined()
+                .map_err(|err| format!("could not read output: {}", err)),
+            None => Ok(Vec::new()),
+        };
+        info!("got combined output");
+        Some(result)
+    }
+
+    /// follows job output if job exists, starting with the output produced so far
+    /// any number of followers can follow the same job
+    #[instrument(skip(self))]
+    pub async fn follow(&self, id: u64) -> Option<OutputFollower> {
+        info!("start following output");
+        let jobs = self.jobs.lock().await;
+        let job = jobs.get(&id)?;
+        Some(OutputFollower {
+            id,
+            jobs: self.jobs.clone(),
+            spool: job.spool.clone(),
+            output_rx: job.state.output_rx(),
+            stdout_pos: 0,
+            stderr_pos: 0,
+        })
     }
 
     /// get a mapping of all jobs and their specs
@@ -186,235 +995,2520 @@ impl JobPool {
             .collect()
     }
 
-    /// update job's state and output
-    /// if kill is true, send kill signal to job's process and collect all outstanding output
-    /// returns updated job
-    async fn update_job_state(&self, mut job: Job, kill: bool) -> Job {
-        job.state = match job.state {
-            JobState::Running {
-                mut stdout_rx,
-                mut stderr_rx,
-                mut exit_rx,
-                kill_tx,
-            } => {
-                if kill {
-                    info!(
-                        "send kill signal for job {}'s process with pid {:?}",
-                        job.id, job.pid
-                    );
-                    if kill_tx.send(()).is_err() {
-                        info!("kill signal channel receiver dropped, process already exited");
-                    }
-                    match exit_rx.await {
-                        Ok(exit_result) => {
-                            let (state, output) =
-                                finish_job(exit_result, job.output, stdout_rx, stderr_rx).await;
-                            job.output = output;
-                            state
-                        }
-                        // TODO: handle error instead of panic
-                        // this should never happen, manager task should never complete without sending
-                        // could either return job error state or add extra "internal error" state to return here
-                        Err(_err) => {
-                            panic!("exit channel sender unexpectedly dropped without sending")
-                        }
-                    }
-                } else {
-                    match exit_rx.try_recv() {
-                        Ok(exit_result) => {
-                            let (state, output) =
-                                finish_job(exit_result, job.output, stdout_rx, stderr_rx).await;
-                            job.output = output;
-                            state
-                        }
-                        _ => {
-                            let now = Instant::now();
-                            let stdout_lines = receive_lines_until(&mut stdout_rx, &now).await;
-                            let stderr_lines = receive_lines_until(&mut stderr_rx, &now).await;
-                            job.output.append(stdout_lines, stderr_lines);
-                            JobState::Running {
-                                stdout_rx,
-                                stderr_rx,
-                                exit_rx,
-                                kill_tx,
-                            }
-                        }
-                    }
-                }
-            }
-            x => x,
-        };
-        job
-    }
-}
-
-/// reads all remaining lines from stdout/stderr channels and returns full job output
-async fn finish_job(
-    exit_status: io::Result<ExitStatus>,
-    mut job_output: JobOutput,
-    mut stdout_rx: UnboundedReceiver<(String, Instant)>,
-    mut stderr_rx: UnboundedReceiver<(String, Instant)>,
-) -> (JobState, JobOutput) {
-    let stdout_lines = receive_all_lines(&mut stdout_rx).await;
-    let stderr_lines = receive_all_lines(&mut stderr_rx).await;
-    job_output.append(stdout_lines, stderr_lines);
-    match exit_status {
-        Ok(exit_status) if exit_status.code().is_some() => {
-            let exit_code = exit_status.code().unwrap();
-            (JobState::Completed { exit_code }, job_output)
-        }
-        Ok(_exit_status) => (JobState::Terminated, job_output),
-        Err(io_err) => {
-            let error_state = JobState::Error {
-                msg: format!(
-                    "unexpected io error when waiting for job process {:?}",
-                    io_err
-                ),
-            };
-            (error_state, job_output)
+    /// get a mapping of all jobs and their status, pid and timestamps
+    #[instrument(skip_all)]
+    pub async fn list_info(&self) -> HashMap<u64, JobInfo> {
+        info!("get a detailed list of jobs");
+        let mut jobs = self.jobs.lock().await;
+        let ids: Vec<u64> = jobs.keys().copied().collect();
+        let mut infos = HashMap::new();
+        for id in ids {
+            let job = update_job_state(jobs.remove(&id).unwrap(), None).await;
+            infos.insert(id, job.info(&self.name, &jobs));
+            jobs.insert(id, job);
         }
+        infos
     }
-}
-
-#[cfg(test)]
-mod test {
-    use std::{sync::Once, time::Duration};
-
-    use lazy_static::lazy_static;
 
-    use rcmd_data::{JobSpec, JobStatus};
-    use tokio::{runtime::Runtime, time::sleep};
+    /// evicts the finished jobs beyond the pool's retention policy right away, returns how many
+    /// this also happens periodically in the background
+    pub async fn evict_finished(&self) -> usize {
+        evict_finished(&self.jobs, &self.evicted, &self.launcher).await
+    }
 
-    use super::JobPool;
+    /// why job <id> was evicted, None if it was not or too many jobs were evicted since
+    pub fn evicted(&self, id: u64) -> Option<String> {
+        self.evicted.lock().unwrap().get(&id).cloned()
+    }
 
-    lazy_static! {
-        static ref RUNTIME: Runtime = Runtime::new().unwrap();
+    /// number of jobs that did not finish yet, including waiting, queued and retried ones
+    pub async fn unfinished_jobs(&self) -> usize {
+        let mut jobs = self.jobs.lock().await;
+        let ids: Vec<u64> = jobs.keys().copied().collect();
+        let mut unfinished = 0;
+        for id in ids {
+            let job = update_job_state(jobs.remove(&id).unwrap(), None).await;
+            if job.state.output_rx().is_some() {
+                unfinished += 1;
+            }
+            jobs.insert(id, job);
+        }
+        unfinished
     }
-    static INIT: Once = Once::new();
 
-    pub fn setup() {
-        INIT.call_once(|| {
-            tracing_subscriber::fmt::init();
-        });
+    /// resources used by all jobs of the pool that finished so far, including deleted ones
+    pub fn usage_summary(&self) -> UsageSummary {
+        self.launcher.usage_summary.lock().unwrap().clone()
+    }
+
+    /// a job that is not running is inserted as ended right away
+    #[allow(clippy::too_many_arguments)]
+    async fn insert_job(
+        &self,
+        id: u64,
+        pid: Option<u32>,
+        spec: JobSpec,
+        spool: Option<Arc<JobSpool>>,
+        state: JobState,
+        submitted_at: SystemTime,
+        schedule: Option<u64>,
+    ) -> u64 {
+        let now = SystemTime::now();
+        let (started_at, ended_at) = match state {
+            JobState::Waiting { .. } | JobState::Queued { .. } => (None, None),
+            JobState::Running { .. } => (Some(now), None),
+            _ => (None, Some(now)),
+        };
+        let mut job = Job {
+            id,
+            pid,
+            spec,
+            state,
+            spool,
+            submitted_at,
+            started_at,
+            ended_at,
+            usage: None,
+            attempt: 1,
+            attempts: Vec::new(),
+            retrier: None,
+            schedule,
+        };
+        let retried = job.spec.retry.is_some();
+        if retried {
+            job.retrier = Some(Retrier {
+                jobs: Arc::downgrade(&self.jobs),
+                launcher: self.launcher.clone(),
+            });
+        }
+        let mut jobs = self.jobs.lock().await;
+        // started while the pool was shutting down, stopped here as shutdown may have missed it
+        if self.shut_down.load(std::sync::atomic::Ordering::Relaxed)
+            && job.state.output_rx().is_some()
+        {
+            job = update_job_state(job, Some(StopRequest::kill())).await;
+        }
+        if let Some(store) = &self.launcher.store {
+            // saved while holding the lock, so a lower id never overwrites a higher one
+            let next_id = self.next_job_id.load(std::sync::atomic::Ordering::Relaxed);
+            if let Err(err) = store.save_next_id(next_id) {
+                error!("could not save next job id: {}", err);
+            }
+        }
+        self.launcher.save(&job, &jobs);
+        jobs.insert(id, job);
+        if retried || self.launcher.store.is_some() {
+            tokio::spawn(watch_job(
+                Arc::downgrade(&self.jobs),
+                self.launcher.clone(),
+                id,
+            ));
+        }
+        id
+    }
+
+    /// gets the spool of a job if job exists, the spool is read without holding the lock
+    async fn spool(&self, id: u64) -> Option<Option<Arc<JobSpool>>> {
+        let jobs = self.jobs.lock().await;
+        jobs.get(&id).map(|job| job.spool.clone())
+    }
+}
+
+impl Job {
+    /// spools of all attempts of the job
+    fn spools(&self) -> impl Iterator<Item = &Arc<JobSpool>> {
+        let past_spools = self.attempts.iter().filter_map(|past| past.spool.as_ref());
+        self.spool.iter().chain(past_spools)
+    }
+
+    /// bytes of output of all attempts of the job
+    fn output_size(&self) -> u64 {
+        self.spools()
+            .map(|spool| spool.size(OutputStream::Stdout) + spool.size(OutputStream::Stderr))
+            .sum()
+    }
+
+    /// lets the spool files of all attempts be removed once the last reader dropped them
+    fn discard_output(&self) {
+        for spool in self.spools() {
+            spool.keep_files(false);
+        }
+    }
+
+    /// status of the job, <jobs> are the other jobs of its pool, used for the position in the queue
+    fn status(&self, jobs: &HashMap<u64, Job>) -> JobStatus {
+        match &self.state {
+            JobState::Waiting { .. } => {
+                let dependencies = self
+                    .spec
+                    .dependencies
+                    .iter()
+                    .map(|dependency| dependency.job)
+                    .filter(|id| match jobs.get(id) {
+                        Some(job) => job.state.output_rx().is_some(),
+                        None => false,
+                    })
+                    .collect();
+                JobStatus::Waiting { dependencies }
+            }
+            JobState::Retrying { retry_at, .. } => JobStatus::Retrying {
+                attempt: self.attempt - 1,
+                retry_in: retry_at
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default(),
+            },
+            JobState::Queued { .. } => {
+                // same order as the scheduler uses within a pool
+                let key = |job: &Job| (Reverse(job.spec.priority), job.id);
+                let ahead = jobs
+                    .values()
+                    .filter(|job| matches!(job.state, JobState::Queued { .. }))
+                    .filter(|job| key(job) < key(self))
+                    .count();
+                JobStatus::Queued {
+                    position: ahead + 1,
+                }
+            }
+            JobState::Running { paused: true, .. } => JobStatus::Stopped,
+            JobState::Running { .. } => JobStatus::Running,
+            JobState::Completed { exit_code } => JobStatus::Completed {
+                exit_code: *exit_code,
+            },
+            JobState::Terminated {
+                signal,
+                core_dumped,
+                by_rcmd,
+            } => JobStatus::Terminated {
+                signal: signal::name(*signal),
+                core_dumped: *core_dumped,
+                by_rcmd: *by_rcmd,
+            },
+            JobState::OomKilled => JobStatus::OomKilled,
+            JobState::Halted {
+                signal,
+                grace,
+                escalated,
+                exit_code,
+            } => JobStatus::Halted {
+                signal: signal::name(*signal),
+                grace: *grace,
+                escalated: *escalated,
+                exit_code: *exit_code,
+            },
+            JobState::TimedOut { timeout, escalated } => JobStatus::TimedOut {
+                timeout: *timeout,
+                escalated: *escalated,
+            },
+            JobState::Skipped { dependency } => JobStatus::Skipped {
+                dependency: *dependency,
+            },
+            JobState::Error { msg } => JobStatus::Error {
+                msg: msg.to_string(),
+            },
+            JobState::Restored { status } => status.clone(),
+        }
+    }
+
+    /// record to restore the job from, <jobs> are the other jobs of its pool
+    fn record(&self, jobs: &HashMap<u64, Job>) -> JobRecord {
+        let spool_name =
+            |spool: &Option<Arc<JobSpool>>| spool.as_ref().map(|spool| spool.name().to_string());
+        JobRecord {
+            id: self.id,
+            spec: self.spec.clone(),
+            status: self.status(jobs),
+            pid: self.pid,
+            submitted_at: self.submitted_at,
+            started_at: self.started_at,
+            ended_at: self.ended_at,
+            usage: self.usage.clone(),
+            spool: spool_name(&self.spool),
+            attempt: self.attempt,
+            attempts: self
+                .attempts
+                .iter()
+                .map(|past| AttemptRecord {
+                    info: past.info.clone(),
+                    spool: spool_name(&past.spool),
+                })
+                .collect(),
+            schedule: self.schedule,
+            shim_pid: match self.state {
+                JobState::Running { shim_pid, .. } => shim_pid,
+                _ => None,
+            },
+        }
+    }
+
+    fn info(&self, client: &str, jobs: &HashMap<u64, Job>) -> JobInfo {
+        let duration = self.started_at.map(|started_at| {
+            let ended_at = self.ended_at.unwrap_or_else(SystemTime::now);
+            ended_at.duration_since(started_at).unwrap_or_default()
+        });
+        JobInfo {
+            id: self.id,
+            command: self.spec.command.clone(),
+            arguments: self.spec.arguments.clone(),
+            status: self.status(jobs),
+            client: client.to_string(),
+            pid: self.pid,
+            submitted_at: self.submitted_at,
+            started_at: self.started_at,
+            ended_at: self.ended_at,
+            duration,
+            usage: self.usage.clone(),
+            attempt: self.attempt,
+            attempts: self
+                .attempts
+                .iter()
+                .map(|attempt| attempt.info.clone())
+                .collect(),
+            schedule: self.schedule,
+        }
+    }
+}
+
+impl Launcher {
+    /// creates the spool and the stdin and output channels for <attempt> of a job
+    /// stdin and output channels exist from the start, so a queued job can be fed and followed
+    fn prepare(&self, id: u64, spec: &JobSpec, attempt: u32) -> Result<Prepared, String> {
+        // later attempts keep the output of the earlier ones
+        let name = match attempt {
+            1 => format!("{}-job-{}", self.sanitized_name(), id),
+            _ => format!("{}-job-{}-attempt-{}", self.sanitized_name(), id, attempt),
+        };
+        let (spool, stdout, stderr) = self.create_spool(&name)?;
+        let (stdin_tx, stdin_rx) = match &spec.stdin {
+            StdinMode::Null => (None, None),
+            StdinMode::Data(data) => {
+                // sender is dropped right away, so stdin is closed after the data
+                let (stdin_tx, stdin_rx) = mpsc::unbounded_channel::<Vec<u8>>();
+                let _ = stdin_tx.send(data.clone());
+                (None, Some(stdin_rx))
+            }
+            StdinMode::Pipe => {
+                let (stdin_tx, stdin_rx) = mpsc::unbounded_channel::<Vec<u8>>();
+                (Some(stdin_tx), Some(stdin_rx))
+            }
+        };
+        let (output_tx, output_rx) = watch::channel(());
+        // input of a pipe can only be written by the pool, which may not outlive the job
+        let shim = match (&self.store, &spec.stdin) {
+            (Some(_), StdinMode::Null | StdinMode::Data(_)) => self.config.shim.clone(),
+            _ => None,
+        };
+        let launch = Launch {
+            id,
+            spec: spec.clone(),
+            cgroup_parent: self.config.cgroup_parent.clone(),
+            cgroup_name: name,
+            shim,
+            spool: spool.clone(),
+            max_job_output: self.config.max_job_output,
+            stdout,
+            stderr,
+            stdin: stdin_rx,
+            output_changed: output_tx,
+            usage_summary: self.usage_summary.clone(),
+            managers: self.managers.clone(),
+        };
+        Ok(Prepared {
+            launch,
+            spool,
+            stdin_tx,
+            output_rx,
+        })
+    }
+
+    fn create_spool(
+        &self,
+        name: &str,
+    ) -> Result<(Arc<JobSpool>, SpoolWriter, SpoolWriter), String> {
+        let spool = JobSpool::create(&self.spool_dir(), name, self.spool_limits())
+            .map_err(|e| e.to_string())?;
+        // the output of persisted jobs is restored along with them
+        if self.store.is_some() {
+            spool.keep_files(true);
+        }
+        let stdout = spool
+            .writer(OutputStream::Stdout)
+            .map_err(|e| e.to_string())?;
+        let stderr = spool
+            .writer(OutputStream::Stderr)
+            .map_err(|e| e.to_string())?;
+        Ok((spool, stdout, stderr))
+    }
+
+    /// opens the spool of a restored job, None if its files are gone
+    fn open_spool(&self, name: &str) -> Option<Arc<JobSpool>> {
+        match JobSpool::open(&self.spool_dir(), name, self.spool_limits()) {
+            Ok(spool) => Some(spool),
+            Err(err) => {
+                error!("could not open spool {}: {}", name, err);
+                None
+            }
+        }
+    }
+
+    fn spool_dir(&self) -> PathBuf {
+        match &self.config.spool_dir {
+            Some(dir) => dir.clone(),
+            None => env::temp_dir().join("rcmd-spool"),
+        }
+    }
+
+    fn spool_limits(&self) -> SpoolLimits {
+        SpoolLimits {
+            max_job_output: self.config.max_job_output,
+            max_spool_size: self.config.max_spool_size,
+            usage: self.config.spool_usage.clone(),
+        }
+    }
+
+    /// writes the record of a job if the pool is persisted, <jobs> are the other jobs of the pool
+    fn save(&self, job: &Job, jobs: &HashMap<u64, Job>) {
+        if let Some(store) = &self.store {
+            if let Err(err) = store.save(&job.record(jobs)) {
+                error!("could not save record of job {}: {}", job.id, err);
+            }
+        }
+    }
+
+    /// jobs of the pool restored from its store along with the id of the next job
+    /// jobs that did not finish are lost, as their processes are not tracked anymore
+    fn restore(&self) -> (HashMap<u64, Job>, u64) {
+        let mut jobs = HashMap::new();
+        let store = match &self.store {
+            Some(store) => store,
+            None => return (jobs, 0),
+        };
+        let mut next_id = store.next_id().unwrap_or_else(|err| {
+            error!("could not read next job id of pool {}: {}", self.pool, err);
+            0
+        });
+        let records = store.load().unwrap_or_else(|err| {
+            error!("could not read job records of pool {}: {}", self.pool, err);
+            Vec::new()
+        });
+        for mut record in records {
+            next_id = next_id.max(record.id + 1);
+            let open = |name: &Option<String>| name.as_ref().and_then(|name| self.open_spool(name));
+            let spool = open(&record.spool);
+            // a job run by a shim may still be running, or have finished while nobody watched
+            let reattached = match (&record.status, record.shim_pid, &spool) {
+                (JobStatus::Running | JobStatus::Stopped, Some(shim_pid), Some(spool)) => {
+                    match self.reattach(&record, shim_pid, spool.clone()) {
+                        Ok(state) => Some(state),
+                        Err(err) => {
+                            error!("could not reattach to job {}: {}", record.id, err);
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            };
+            let state = match reattached {
+                Some(state) => {
+                    info!("reattached to job {} of pool {}", record.id, self.pool);
+                    state
+                }
+                None => {
+                    if let Some(reason) = lost_reason(&record.status) {
+                        info!(
+                            "job {} of pool {} was lost: {}",
+                            record.id, self.pool, reason
+                        );
+                        record.status = JobStatus::Lost {
+                            reason: reason.to_string(),
+                        };
+                        // the job ended at the latest now
+                        record.ended_at = Some(SystemTime::now());
+                        if let Err(err) = store.save(&record) {
+                            error!("could not save record of job {}: {}", record.id, err);
+                        }
+                    }
+                    JobState::Restored {
+                        status: record.status,
+                    }
+                }
+            };
+            let job = Job {
+                id: record.id,
+                pid: record.pid,
+                state,
+                spool,
+                spec: record.spec,
+                submitted_at: record.submitted_at,
+                started_at: record.started_at,
+                ended_at: record.ended_at,
+                usage: record.usage,
+                attempt: record.attempt,
+                attempts: record
+                    .attempts
+                    .into_iter()
+                    .map(|past| PastAttempt {
+                        spool: open(&past.spool),
+                        info: past.info,
+                    })
+                    .collect(),
+                retrier: None,
+                schedule: record.schedule,
+            };
+            jobs.insert(job.id, job);
+        }
+        info!("restored {} jobs of pool {}", jobs.len(), self.pool);
+        (jobs, next_id)
+    }
+
+    /// reattaches to the process of a job run by the shim with <shim_pid>, started by an earlier pool
+    fn reattach(
+        &self,
+        record: &JobRecord,
+        shim_pid: u32,
+        spool: Arc<JobSpool>,
+    ) -> Result<JobState, String> {
+        let job =
+            shim::read_job(&spool).map_err(|err| format!("could not read job file: {}", err))?;
+        let cgroup = match &job.cgroup {
+            Some(path) => Some(Cgroup::open(path).map_err(|e| e.to_string())?),
+            None => None,
+        };
+        let process = JobProcess::Shim {
+            shim: Shim::reattach(shim_pid, spool),
+            pid: record.pid,
+        };
+        // the timeout counts from the original start
+        let started_at = record.started_at.unwrap_or_else(SystemTime::now);
+        let (output_tx, output_rx) = watch::channel(());
+        // running already, so it takes a slot regardless of the limits
+        let slot = self.config.scheduler.claim(&self.pool);
+        let (exit_rx, control_tx) = spawn_manager(
+            record.id,
+            process,
+            cgroup,
+            time_limit(&record.spec, started_at),
+            output_tx,
+            self.usage_summary.clone(),
+            slot,
+            &self.managers,
+        );
+        Ok(JobState::Running {
+            exit_rx,
+            control_tx,
+            stdin_tx: None,
+            paused: record.status == JobStatus::Stopped,
+            output_rx,
+            shim_pid: Some(shim_pid),
+        })
+    }
+
+    /// queues a job in the scheduler of the pool
+    fn enqueue(&self, id: u64, priority: Priority) -> oneshot::Receiver<RunSlot> {
+        self.config.scheduler.enqueue(&self.pool, id, priority)
+    }
+
+    /// pool name usable as part of file names
+    fn sanitized_name(&self) -> String {
+        self.pool
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect()
+    }
+}
+
+impl Launch {
+    /// creates the job's cgroup if needed and spawns its process (or its shim) along with the manager task
+    /// the given run slot is held until the manager finished
+    async fn start(self, slot: RunSlot) -> Result<Started, String> {
+        let spec = &self.spec;
+        let cgroup = match &spec.limits {
+            Some(limits) => match self.create_cgroup(limits) {
+                Ok(cgroup) => Some(cgroup),
+                Err(err) => {
+                    info!("cgroup could not be created, error: {}", err);
+                    return Err(format!("could not set up resource limits: {}", err));
+                }
+            },
+            None => None,
+        };
+        let started = match &self.shim {
+            Some(path) => {
+                let job = ShimJob {
+                    spec: spec.clone(),
+                    spool_dir: self.spool.dir().to_owned(),
+                    spool_name: self.spool.name().to_string(),
+                    max_job_output: self.max_job_output,
+                    cgroup: cgroup.as_ref().map(|cgroup| cgroup.path().to_owned()),
+                };
+                Shim::start(path, &job, self.spool.clone())
+                    .await
+                    .map(|(shim, pid)| (pid, Some(shim.pid()), JobProcess::Shim { shim, pid }))
+            }
+            None => {
+                let cgroup_procs = cgroup.as_ref().map(|cgroup| cgroup.procs_path().to_owned());
+                match util::spawn_job_process(spec, cgroup_procs) {
+                    Ok(process) => Ok((
+                        Some(process.pid),
+                        None,
+                        JobProcess::Child {
+                            process,
+                            stdin: self.stdin,
+                            stdout: self.stdout,
+                            stderr: self.stderr,
+                        },
+                    )),
+                    Err(err) => Err(err.to_string()),
+                }
+            }
+        };
+        let (pid, shim_pid, process) = match started {
+            Ok(started) => started,
+            Err(err) => {
+                info!("process could not be spawned, error: {:?}", err);
+                if let Some(cgroup) = cgroup {
+                    cgroup.remove().await;
+                }
+                return Err(err);
+            }
+        };
+        info!("process spawned, pid: {:?}", pid);
+        let time_limit = time_limit(spec, SystemTime::now());
+        let (exit_rx, control_tx) = spawn_manager(
+            self.id,
+            process,
+            cgroup,
+            time_limit,
+            self.output_changed,
+            self.usage_summary,
+            slot,
+            &self.managers,
+        );
+        Ok(Started {
+            pid,
+            shim_pid,
+            exit_rx,
+            control_tx,
+        })
+    }
+
+    fn create_cgroup(&self, limits: &ResourceLimits) -> Result<Cgroup, String> {
+        let parent = self
+            .cgroup_parent
+            .as_ref()
+            .ok_or("no cgroup parent configured for job pool")?;
+        // pools share the parent cgroup, so the pool name is part of the leaf's name
+        Cgroup::create(parent, &self.cgroup_name, limits).map_err(|e| e.to_string())
+    }
+}
+
+/// timeout of a job started at <started_at>, stopping it with SIGTERM
+fn time_limit(spec: &JobSpec, started_at: SystemTime) -> Option<Timeout> {
+    spec.timeout.map(|after| Timeout {
+        after,
+        stop: StopRequest {
+            signal: libc::SIGTERM,
+            grace: spec.stop_grace.unwrap_or(DEFAULT_STOP_GRACE),
+        },
+        started_at,
+    })
+}
+
+/// spawns the task managing the process of job <id>, which holds <slot> until it finished
+/// its join handle is added to <managers>, returns the channels for its exit and for control commands
+#[allow(clippy::too_many_arguments)]
+fn spawn_manager(
+    id: u64,
+    process: JobProcess,
+    cgroup: Option<Cgroup>,
+    time_limit: Option<Timeout>,
+    output_changed: watch::Sender<()>,
+    usage_summary: Arc<std::sync::Mutex<UsageSummary>>,
+    slot: RunSlot,
+    managers: &Managers,
+) -> (oneshot::Receiver<ProcessExit>, UnboundedSender<Control>) {
+    let (exit_tx, exit_rx) = oneshot::channel::<ProcessExit>();
+    let (control_tx, control_rx) = mpsc::unbounded_channel::<Control>();
+    // the manager writes output, sends the exit and listens for control commands
+    let channels = ManagerChannels {
+        exit: exit_tx,
+        control: control_rx,
+        output_changed,
+        usage_summary,
+    };
+    let token = Arc::new(());
+    let running = Arc::downgrade(&token);
+    let handle = tokio::spawn(async move {
+        manage_process(id, process, cgroup, time_limit, channels).await;
+        drop(slot);
+        drop(token);
+    });
+    let mut managers = managers.lock().unwrap();
+    managers.retain(|(running, _)| running.strong_count() > 0);
+    managers.push((running, handle));
+    (exit_rx, control_tx)
+}
+
+/// waits for a run slot, then starts the queued job unless it was cancelled in the meantime
+async fn start_queued(
+    jobs: Arc<Mutex<HashMap<u64, Job>>>,
+    launcher: Launcher,
+    launch: Launch,
+    start: oneshot::Receiver<RunSlot>,
+    cancelled: oneshot::Receiver<()>,
+) {
+    let id = launch.id;
+    let slot = tokio::select! {
+        Ok(slot) = start => slot,
+        _ = cancelled => {
+            info!("queued job {} was cancelled", id);
+            return;
+        }
+    };
+    // the job may have been deleted or stopped while acquiring the lock
+    match jobs.lock().await.get(&id) {
+        Some(Job {
+            state: JobState::Queued { .. },
+            ..
+        }) => {}
+        _ => return,
+    }
+    // starting may take a while with a shim, the job stays queued without holding the lock meanwhile
+    info!("starting queued job {}", id);
+    let started = launch.start(slot).await;
+    let mut jobs = jobs.lock().await;
+    let queued = match jobs.get_mut(&id).map(|job| &mut job.state) {
+        Some(JobState::Queued {
+            stdin_tx,
+            output_rx,
+            ..
+        }) => Some((stdin_tx.take(), output_rx.clone())),
+        _ => None,
+    };
+    let (stdin_tx, output_rx) = match queued {
+        Some(queued) => queued,
+        None => {
+            if let Ok(started) = started {
+                info!(
+                    "job {} was stopped or deleted while starting, killing it",
+                    id
+                );
+                // dropping the control channel alone would leave a shim's job running
+                let _ = started.control_tx.send(Control::Stop(StopRequest {
+                    signal: libc::SIGKILL,
+                    grace: Duration::ZERO,
+                }));
+            }
+            return;
+        }
+    };
+    let job = jobs.get_mut(&id).unwrap();
+    match started {
+        Ok(started) => {
+            job.pid = started.pid;
+            job.started_at = Some(SystemTime::now());
+            job.state = JobState::Running {
+                exit_rx: started.exit_rx,
+                control_tx: started.control_tx,
+                stdin_tx,
+                paused: false,
+                output_rx,
+                shim_pid: started.shim_pid,
+            };
+        }
+        Err(msg) => {
+            job.ended_at = Some(SystemTime::now());
+            job.state = JobState::Error { msg };
+        }
+    }
+    launcher.save(&jobs[&id], &jobs);
+}
+
+/// why a job whose last record has <status> was lost, None if it finished before
+fn lost_reason(status: &JobStatus) -> Option<&'static str> {
+    match status {
+        JobStatus::Waiting { .. } | JobStatus::Queued { .. } => {
+            Some("the server stopped before the job started")
+        }
+        JobStatus::Retrying { .. } => {
+            Some("the server stopped while the job was waiting to be retried")
+        }
+        JobStatus::Running | JobStatus::Stopped => {
+            Some("the server stopped while the job was running, its processes may still be running")
+        }
+        _ => None,
+    }
+}
+
+/// both streams of a spool, empty if there is none, each cut after MAX_READ_SIZE bytes
+fn read_output(spool: Option<Arc<JobSpool>>) -> JobOutput {
+    let mut output = JobOutput::new();
+    if let Some(spool) = spool {
+        let read = |stream| match spool.read_from(stream, 0) {
+            Ok((mut data, next)) => {
+                if next < spool.size(stream) {
+                    data.extend_from_slice(READ_LIMIT_MARKER.as_bytes());
+                }
+                data
+            }
+            Err(err) => {
+                error!("could not read {:?} spool file: {}", stream, err);
+                Vec::new()
+            }
+        };
+        output.append(&read(OutputStream::Stdout), &read(OutputStream::Stderr));
+    }
+    output
+}
+
+/// updates the state of a job whenever an attempt ended, so it is retried and its record saved
+/// without being observed, returns once the job finished or got deleted
+async fn watch_job(jobs: Weak<Mutex<HashMap<u64, Job>>>, launcher: Launcher, id: u64) {
+    loop {
+        let mut output_rx = {
+            let jobs = match jobs.upgrade() {
+                Some(jobs) => jobs,
+                None => return,
+            };
+            let mut jobs = jobs.lock().await;
+            let job = match jobs.remove(&id) {
+                Some(job) => job,
+                None => return,
+            };
+            let job = update_job_state(job, None).await;
+            let output_rx = job.state.output_rx();
+            launcher.save(&job, &jobs);
+            jobs.insert(id, job);
+            match output_rx {
+                Some(output_rx) => output_rx,
+                None => return,
+            }
+        };
+        // closed once the manager of the current attempt finished
+        while output_rx.changed().await.is_ok() {}
+    }
+}
+
+/// evicts finished jobs according to the retention policy of the pool until it was dropped
+async fn reap_finished(jobs: Weak<Mutex<HashMap<u64, Job>>>, evicted: Evicted, launcher: Launcher) {
+    loop {
+        sleep(RETENTION_INTERVAL).await;
+        let jobs = match jobs.upgrade() {
+            Some(jobs) => jobs,
+            None => return,
+        };
+        evict_finished(&jobs, &evicted, &launcher).await;
+    }
+}
+
+/// removes the oldest finished jobs while any limit of the retention policy is exceeded,
+/// records why in <evicted> and returns how many were evicted
+async fn evict_finished(
+    jobs: &Mutex<HashMap<u64, Job>>,
+    evicted: &std::sync::Mutex<BTreeMap<u64, String>>,
+    launcher: &Launcher,
+) -> usize {
+    let retention = &launcher.config.retention;
+    let mut jobs = jobs.lock().await;
+    let ids: Vec<u64> = jobs.keys().copied().collect();
+    for id in ids {
+        let job = update_job_state(jobs.remove(&id).unwrap(), None).await;
+        jobs.insert(id, job);
+    }
+    // evicting them would skip the jobs waiting for them
+    let needed: HashSet<u64> = jobs
+        .values()
+        .filter(|job| job.state.output_rx().is_some())
+        .flat_map(|job| {
+            job.spec
+                .dependencies
+                .iter()
+                .map(|dependency| dependency.job)
+        })
+        .collect();
+    let mut finished: Vec<(SystemTime, u64, u64)> = jobs
+        .values()
+        .filter(|job| job.state.output_rx().is_none() && !needed.contains(&job.id))
+        .map(|job| {
+            let ended_at = job.ended_at.unwrap_or(job.submitted_at);
+            (ended_at, job.id, job.output_size())
+        })
+        .collect();
+    finished.sort();
+    let now = SystemTime::now();
+    let mut remaining = finished.len();
+    let mut output: u64 = finished.iter().map(|(_, _, size)| size).sum();
+    let mut evictions = Vec::new();
+    // oldest first, once one is kept so are all younger ones
+    for (ended_at, id, size) in finished {
+        let age = now.duration_since(ended_at).unwrap_or_default();
+        let reason = match retention {
+            Retention {
+                max_age: Some(max_age),
+                ..
+            } if age > *max_age => format!("finished more than {}s ago", max_age.as_secs()),
+            Retention {
+                max_jobs: Some(max_jobs),
+                ..
+            } if remaining > *max_jobs => format!("more than {} finished jobs", max_jobs),
+            Retention {
+                max_output: Some(max_output),
+                ..
+            } if output > *max_output => {
+                format!("finished jobs exceeded {} bytes of output", max_output)
+            }
+            _ => break,
+        };
+        remaining -= 1;
+        output -= size;
+        evictions.push((id, reason));
+    }
+    if evictions.is_empty() {
+        return 0;
+    }
+    // saved before the records are removed, so an evicted job is never just unknown after a crash
+    let saved = {
+        let mut evicted = evicted.lock().unwrap();
+        for (id, reason) in &evictions {
+            info!("evicting job {}: {}", id, reason);
+            evicted.insert(*id, reason.clone());
+        }
+        // dropping the lowest ids forgets about the oldest jobs
+        while evicted.len() > MAX_EVICTED {
+            let oldest = *evicted.keys().next().unwrap();
+            evicted.remove(&oldest);
+        }
+        evicted.clone()
+    };
+    if let Some(store) = &launcher.store {
+        if let Err(err) = store.save_evicted(&saved) {
+            error!("could not save evicted jobs: {}", err);
+        }
+    }
+    for (id, _) in &evictions {
+        let job = jobs.remove(id).unwrap();
+        if let Some(store) = &launcher.store {
+            if let Err(err) = store.remove(*id) {
+                error!("could not remove record of job {}: {}", id, err);
+            }
+        }
+        job.discard_output();
+    }
+    evictions.len()
+}
+
+/// queues the next attempt of a retried job after <delay>, unless it was cancelled in the meantime
+async fn retry_after(
+    jobs: Arc<Mutex<HashMap<u64, Job>>>,
+    launcher: Launcher,
+    prepared: Prepared,
+    delay: Duration,
+    mut cancelled: oneshot::Receiver<()>,
+) {
+    let id = prepared.launch.id;
+    tokio::select! {
+        _ = sleep(delay) => {},
+        _ = &mut cancelled => {
+            info!("retry of job {} was cancelled", id);
+            return;
+        }
+    }
+    let start = {
+        let mut jobs = jobs.lock().await;
+        // the job may have been deleted or stopped in the meantime
+        let mut job = match jobs.remove(&id) {
+            Some(job) => job,
+            None => return,
+        };
+        job.state = match job.state {
+            JobState::Retrying {
+                _cancel_tx,
+                output_rx,
+                ..
+            } => JobState::Queued {
+                _cancel_tx,
+                stdin_tx: prepared.stdin_tx,
+                output_rx,
+            },
+            state => {
+                job.state = state;
+                jobs.insert(id, job);
+                return;
+            }
+        };
+        info!("queueing attempt {} of job {}", job.attempt, id);
+        // queued while holding the lock, so the priority can not change in between
+        let start = launcher.enqueue(id, job.spec.priority);
+        launcher.save(&job, &jobs);
+        jobs.insert(id, job);
+        start
+    };
+    start_queued(jobs, launcher, prepared.launch, start, cancelled).await;
+}
+
+/// state of the dependencies of a job
+enum Dependencies {
+    Met,
+    /// the condition on the given job can not be met anymore
+    Failed(u64),
+    /// output notification receivers of the jobs that did not finish yet
+    Pending(Vec<watch::Receiver<()>>),
+}
+
+async fn check_dependencies(
+    jobs: &mut HashMap<u64, Job>,
+    dependencies: &[Dependency],
+) -> Dependencies {
+    let mut pending = Vec::new();
+    for dependency in dependencies {
+        // a deleted job never finishes in a way that meets a condition
+        let job = match jobs.remove(&dependency.job) {
+            Some(job) => update_job_state(job, None).await,
+            None => return Dependencies::Failed(dependency.job),
+        };
+        let met = condition_met(dependency.condition, &job.state);
+        if let Some(output_rx) = job.state.output_rx() {
+            pending.push(output_rx);
+        }
+        jobs.insert(dependency.job, job);
+        if met == Some(false) {
+            return Dependencies::Failed(dependency.job);
+        }
+    }
+    if pending.is_empty() {
+        Dependencies::Met
+    } else {
+        Dependencies::Pending(pending)
+    }
+}
+
+/// resolves once any of the jobs the given output notification receivers belong to finished
+async fn any_finished(receivers: Vec<watch::Receiver<()>>) {
+    let (finished_tx, mut finished_rx) = mpsc::channel::<()>(1);
+    for mut output_rx in receivers {
+        let finished_tx = finished_tx.clone();
+        tokio::spawn(async move {
+            // closed once the job finished
+            while output_rx.changed().await.is_ok() {}
+            let _ = finished_tx.try_send(());
+        });
+    }
+    drop(finished_tx);
+    finished_rx.recv().await;
+}
+
+/// waits for the dependencies of a job, then queues and starts it, unless it was cancelled
+/// or a dependency finished in a way that skips the job
+async fn await_dependencies(
+    jobs: Arc<Mutex<HashMap<u64, Job>>>,
+    launcher: Launcher,
+    launch: Launch,
+    mut cancelled: oneshot::Receiver<()>,
+) {
+    let id = launch.id;
+    let dependencies = launch.spec.dependencies.clone();
+    let start = loop {
+        let pending = {
+            let mut jobs = jobs.lock().await;
+            let checked = check_dependencies(&mut jobs, &dependencies).await;
+            // the job may have been deleted or stopped in the meantime
+            let mut job = match jobs.remove(&id) {
+                Some(job) => job,
+                None => return,
+            };
+            let (cancel_tx, stdin_tx, output_rx) = match job.state {
+                JobState::Waiting {
+                    _cancel_tx,
+                    stdin_tx,
+                    output_rx,
+                } => (_cancel_tx, stdin_tx, output_rx),
+                state => {
+                    job.state = state;
+                    jobs.insert(id, job);
+                    return;
+                }
+            };
+            match checked {
+                Dependencies::Met => {
+                    info!("dependencies of job {} met, queueing it", id);
+                    job.state = JobState::Queued {
+                        _cancel_tx: cancel_tx,
+                        stdin_tx,
+                        output_rx,
+                    };
+                    // queued while holding the lock, so the priority can not change in between
+                    let start = launcher.enqueue(id, job.spec.priority);
+                    launcher.save(&job, &jobs);
+                    jobs.insert(id, job);
+                    break start;
+                }
+                Dependencies::Failed(dependency) => {
+                    info!(
+                        "skipping job {}, condition on job {} not met",
+                        id, dependency
+                    );
+                    job.ended_at = Some(SystemTime::now());
+                    job.state = JobState::Skipped { dependency };
+                    launcher.save(&job, &jobs);
+                    jobs.insert(id, job);
+                    return;
+                }
+                Dependencies::Pending(pending) => {
+                    job.state = JobState::Waiting {
+                        _cancel_tx: cancel_tx,
+                        stdin_tx,
+                        output_rx,
+                    };
+                    jobs.insert(id, job);
+                    pending
+                }
+            }
+        };
+        tokio::select! {
+            _ = any_finished(pending) => {},
+            _ = &mut cancelled => {
+                info!("waiting job {} was cancelled", id);
+                return;
+            }
+        }
+    };
+    start_queued(jobs, launcher, launch, start, cancelled).await;
+}
+
+impl OutputFollower {
+    /// waits for output that was not returned yet, returns it per stream, stdout before stderr
+    /// returns None once the job finished (or got deleted) and all of its output was returned
+    pub async fn next(&mut self) -> Option<Vec<(OutputStream, Vec<u8>)>> {
+        loop {
+            let (output_rx, current_spool) = {
+                let mut jobs = self.jobs.lock().await;
+                let job = jobs.remove(&self.id)?;
+                let job = update_job_state(job, None).await;
+                let current = (job.state.output_rx(), job.spool.clone());
+                jobs.insert(self.id, job);
+                current
+            };
+            let running = output_rx.is_some();
+            // all output is written before the job finishes, so nothing is missed after it finished
+            let spool = self.spool.clone()?;
+            let mut output = Vec::new();
+            for (stream, pos) in [
+                (OutputStream::Stdout, &mut self.stdout_pos),
+                (OutputStream::Stderr, &mut self.stderr_pos),
+            ] {
+                match spool.read_from(stream, *pos) {
+                    Ok((data, next_pos)) => {
+                        *pos = next_pos;
+                        if !data.is_empty() {
+                            output.push((stream, data));
+                        }
+                    }
+                    Err(err) => {
+                        error!("could not read {:?} spool file: {}", stream, err);
+                        return None;
+                    }
+                }
+            }
+            if !output.is_empty() {
+                return Some(output);
+            }
+            // a retried job continues with the output of its next attempt
+            if let Some(current_spool) = current_spool {
+                if !Arc::ptr_eq(&spool, &current_spool) {
+                    self.spool = Some(current_spool);
+                    self.output_rx = output_rx;
+                    self.stdout_pos = 0;
+                    self.stderr_pos = 0;
+                    continue;
+                }
+            }
+            if !running {
+                return None;
+            }
+            // the manager sends the exit before closing the channel,
+            // so once closed the next update finishes the job
+            if let Some(output_rx) = &mut self.output_rx {
+                if output_rx.changed().await.is_err() {
+                    self.output_rx = None;
+                }
+            }
+        }
+    }
+
+    /// gets status of the followed job if it still exists
+    pub async fn status(&self) -> Option<JobStatus> {
+        let mut jobs = self.jobs.lock().await;
+        let job = jobs.remove(&self.id)?;
+        let job = update_job_state(job, None).await;
+        let status = job.status(&jobs);
+        jobs.insert(self.id, job);
+        Some(status)
+    }
+}
+
+/// update job's state
+/// if stop request is given, send it to job's process manager and wait for the process to exit
+/// returns updated job
+async fn update_job_state(mut job: Job, stop: Option<StopRequest>) -> Job {
+    let mut attempt_finished = false;
+    job.state = match job.state {
+        JobState::Running {
+            mut exit_rx,
+            control_tx,
+            stdin_tx,
+            paused,
+            output_rx,
+            shim_pid,
+        } => {
+            if let Some(request) = stop {
+                info!(
+                    "send stop request for job {}'s process with pid {:?}",
+                    job.id, job.pid
+                );
+                if control_tx.send(Control::Stop(request)).is_err() {
+                    info!("stop request channel receiver dropped, process already exited");
+                }
+                match exit_rx.await {
+                    Ok(exit_result) => {
+                        job.ended_at = Some(exit_result.ended_at);
+                        job.usage = exit_result.usage.clone();
+                        finish_job(exit_result)
+                    }
+                    // TODO: handle error instead of panic
+                    // this should never happen, manager task should never complete without sending
+                    // could either return job error state or add extra "internal error" state to return here
+                    Err(_err) => {
+                        panic!("exit channel sender unexpectedly dropped without sending")
+                    }
+                }
+            } else {
+                match exit_rx.try_recv() {
+                    Ok(exit_result) => {
+                        job.ended_at = Some(exit_result.ended_at);
+                        job.usage = exit_result.usage.clone();
+                        attempt_finished = true;
+                        finish_job(exit_result)
+                    }
+                    _ => JobState::Running {
+                        exit_rx,
+                        control_tx,
+                        stdin_tx,
+                        paused,
+                        output_rx,
+                        shim_pid,
+                    },
+                }
+            }
+        }
+        JobState::Waiting { .. } | JobState::Retrying { .. } | JobState::Queued { .. }
+            if stop.is_some() =>
+        {
+            info!("cancel job {} that did not start yet", job.id);
+            let request = stop.unwrap();
+            job.ended_at = Some(SystemTime::now());
+            JobState::Halted {
+                signal: request.signal,
+                grace: request.grace,
+                escalated: false,
+                exit_code: None,
+            }
+        }
+        x => x,
+    };
+    // decided along with the exit, so nobody sees a retried attempt as the final state
+    if attempt_finished {
+        retry_attempt(&mut job);
+    }
+    job
+}
+
+/// sets a job whose current attempt just finished to Retrying if its retry policy retries it
+/// the next attempt is then queued after the backoff
+fn retry_attempt(job: &mut Job) {
+    let (exit_code, delay) = match (&job.spec.retry, &job.state) {
+        (Some(policy), JobState::Completed { exit_code })
+            if policy.retries(*exit_code) && job.attempt < policy.max_attempts =>
+        {
+            (*exit_code, policy.delay(job.attempt))
+        }
+        _ => return,
+    };
+    let retrier = match &job.retrier {
+        Some(retrier) => retrier.clone(),
+        None => return,
+    };
+    let jobs = match retrier.jobs.upgrade() {
+        Some(jobs) => jobs,
+        None => return,
+    };
+    let prepared = match retrier.launcher.prepare(job.id, &job.spec, job.attempt + 1) {
+        Ok(prepared) => prepared,
+        Err(err) => {
+            error!("could not prepare next attempt of job {}: {}", job.id, err);
+            return;
+        }
+    };
+    info!(
+        "attempt {} of job {} exited with {}, retrying in {:?}",
+        job.attempt, job.id, exit_code, delay
+    );
+    let past = PastAttempt {
+        info: Attempt {
+            number: job.attempt,
+            status: JobStatus::Completed { exit_code },
+            pid: job.pid.take(),
+            started_at: job.started_at.take(),
+            ended_at: job.ended_at.take(),
+        },
+        spool: job.spool.replace(prepared.spool.clone()),
+    };
+    job.attempts.push(past);
+    job.attempt += 1;
+    job.usage = None;
+    let output_rx = prepared.output_rx.clone();
+    let (cancel_tx, cancel_rx) = oneshot::channel();
+    tokio::spawn(retry_after(
+        jobs,
+        retrier.launcher,
+        prepared,
+        delay,
+        cancel_rx,
+    ));
+    job.state = JobState::Retrying {
+        _cancel_tx: cancel_tx,
+        output_rx,
+        // a saturated backoff lies beyond any representable time, such an attempt is never due
+        retry_at: SystemTime::now()
+            .checked_add(delay)
+            .unwrap_or_else(|| SystemTime::now() + FAR_FUTURE),
+    };
+}
+
+/// final state of a job according to how its process exited
+fn finish_job(exit: ProcessExit) -> JobState {
+    match exit.status {
+        Ok(_exit_status) if exit.timed_out.is_some() => JobState::TimedOut {
+            timeout: exit.timed_out.unwrap(),
+            escalated: exit.escalated,
+        },
+        Ok(exit_status) if exit.stop.is_some() => {
+            let request = exit.stop.unwrap();
+            JobState::Halted {
+                signal: request.signal,
+                grace: request.grace,
+                escalated: exit.escalated,
+                exit_code: exit_status.code(),
+            }
+        }
+        // also if a wrapper like a shell exited with a code after its child got killed
+        Ok(_exit_status) if exit.oom_killed => JobState::OomKilled,
+        Ok(exit_status) if exit_status.code().is_some() => {
+            let exit_code = exit_status.code().unwrap();
+            JobState::Completed { exit_code }
+        }
+        Ok(exit_status) => {
+            // a process that was waited for and has no exit code was killed by a signal
+            let signal = exit_status.signal().unwrap_or_default();
+            JobState::Terminated {
+                signal,
+                core_dumped: exit_status.core_dumped(),
+                by_rcmd: exit.signalled.contains(&signal),
+            }
+        }
+        Err(io_err) => JobState::Error {
+            msg: format!(
+                "unexpected io error when waiting for job process {:?}",
+                io_err
+            ),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env, fs, process,
+        sync::{atomic::Ordering, Once},
+        time::Duration,
+    };
+
+    use lazy_static::lazy_static;
+
+    use rcmd_data::{
+        Condition, JobSpec, JobStatus, OutputChunk, OutputRange, OutputStream, Priority,
+        ResourceLimits, RetryPolicy, StdinMode, Workflow, WorkflowDependency, WorkflowJob,
+    };
+    use tokio::{
+        runtime::Runtime,
+        time::{sleep, timeout},
+    };
+
+    use super::{JobPool, JobPoolConfig, Retention, Scheduler};
+
+    lazy_static! {
+        static ref RUNTIME: Runtime = Runtime::new().unwrap();
+    }
+    static INIT: Once = Once::new();
+
+    pub fn setup() {
+        INIT.call_once(|| {
+            tracing_subscriber::fmt::init();
+        });
     }
 
     // testing output of oneshot echo command
     #[test]
-    fn test_output_echo() {
+    fn test_output_echo() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let id = pool.submit("echo", &["hi"]).await;
+            sleep(Duration::from_millis(100)).await;
+            let output = pool.output(id).await;
+            assert!(output.is_some());
+            let output = output.unwrap();
+            assert_eq!("hi\n".to_string(), output.stdout())
+        });
+    }
+
+    // testing status of oneshot ls command
+    #[test]
+    fn test_status_ls() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let id = pool.submit("ls", &[]).await;
+            sleep(Duration::from_millis(100)).await;
+            let status = pool.status(id).await;
+            assert!(status.is_some());
+            let status = status.unwrap();
+            if let JobStatus::Completed { exit_code } = status {
+                assert_eq!(0, exit_code)
+            } else {
+                panic!("unexpected job status: {:?}", status);
+            }
+        });
+    }
+
+    // testing status of sleep job before and after delete
+    #[test]
+    fn test_status_sleep_deleted() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let id = pool.submit("sleep", &["5"]).await;
+            let status = pool.status(id).await;
+            assert!(status.is_some());
+            let status = status.unwrap();
+            assert_eq!(JobStatus::Running, status);
+            let delete_response = pool.delete(id).await;
+            assert_eq!(Some(Ok(())), delete_response);
+            let status = pool.status(id).await;
+            assert!(status.is_none());
+        });
+    }
+
+    // testing output of echo loop
+    #[test]
+    fn test_output_repeated_echo() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let id = pool
+                .submit("bash", &["-c", "while true; do echo hi; sleep 1; done"])
+                .await;
+            sleep(Duration::from_millis(100)).await;
+
+            let status = pool.status(id).await;
+            assert!(status.is_some());
+            let status = status.unwrap();
+            assert_eq!(JobStatus::Running, status);
+            let output = pool.output(id).await.unwrap();
+            assert_eq!("hi\n", &output.stdout());
+            sleep(Duration::from_millis(1000)).await;
+
+            let output = pool.output(id).await.unwrap();
+            assert_eq!("hi\nhi\n", &output.stdout());
+            let delete_response = pool.delete(id).await;
+            assert_eq!(Some(Ok(())), delete_response);
+            let status = pool.status(id).await;
+            assert!(status.is_none());
+        });
+    }
+
+    // testing invalid command
+    #[test]
+    fn test_invalid_command() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let id = pool.submit("abcdfg", &[]).await;
+
+            let status = pool.status(id).await;
+            assert!(status.is_some());
+            let status = status.unwrap();
+            match status {
+                JobStatus::Error { msg: _ } => {}
+                s => panic!("expected error job status, got: {:?}", s),
+            }
+        });
+    }
+
+    // testing listing of jobs
+    #[test]
+    fn test_list_jobs() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let first = pool.submit("abcdfg", &[]).await;
+            let second = pool.submit("ls", &[]).await;
+            let third = pool.submit("echo", &["hi"]).await;
+            let delete_response = pool.delete(second).await;
+            let listed = pool.list().await;
+            assert_eq!(Some(Ok(())), delete_response);
+            assert!(listed.contains_key(&first));
+            assert!(listed.contains_key(&third));
+            assert_eq!(2, listed.len());
+            let first_spec = JobSpec::new("abcdfg", &[]);
+            let third_spec = JobSpec::new("echo", &["hi"]);
+            assert_eq!(&first_spec, listed.get(&first).unwrap());
+            assert_eq!(&third_spec, listed.get(&third).unwrap());
+        });
+    }
+
+    // testing the count of unfinished jobs, queued ones included
+    #[test]
+    fn test_unfinished_jobs() {
+        setup();
+        let config = JobPoolConfig {
+            max_running_jobs: Some(1),
+            ..Default::default()
+        };
+        let pool = JobPool::with_config("unfinished", config);
+        RUNTIME.block_on(async {
+            pool.submit("abcdfg", &[]).await;
+            pool.submit("sleep", &["0.3"]).await;
+            pool.submit("sleep", &["0.3"]).await;
+            assert_eq!(2, pool.unfinished_jobs().await);
+            sleep(Duration::from_millis(450)).await;
+            assert_eq!(1, pool.unfinished_jobs().await);
+            sleep(Duration::from_millis(400)).await;
+            assert_eq!(0, pool.unfinished_jobs().await);
+        });
+    }
+
+    // testing pid and timestamps of running, finished and failed jobs
+    #[test]
+    fn test_job_info() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let id = pool.submit("sleep", &["0.2"]).await;
+            let failed = pool.submit("abcdfg", &[]).await;
+
+            let info = pool.info(id).await.unwrap();
+            assert_eq!(JobStatus::Running, info.status);
+            assert_eq!("default", info.client);
+            assert_eq!("sleep", info.command);
+            assert!(info.pid.is_some());
+            assert!(info.started_at.unwrap() >= info.submitted_at);
+            assert_eq!(None, info.ended_at);
+            assert!(info.duration.is_some());
+
+            sleep(Duration::from_millis(400)).await;
+            let info = pool.info(id).await.unwrap();
+            assert_eq!(JobStatus::Completed { exit_code: 0 }, info.status);
+            let ended_at = info.ended_at.unwrap();
+            let duration = info.duration.unwrap();
+            assert!(duration >= Duration::from_millis(200));
+            assert_eq!(ended_at, info.started_at.unwrap() + duration);
+            // the end time does not move anymore
+            assert_eq!(info, pool.info(id).await.unwrap());
+
+            let info = pool.info(failed).await.unwrap();
+            assert_eq!(None, info.pid);
+            assert_eq!(None, info.started_at);
+            assert!(info.ended_at.is_some());
+            assert_eq!(None, info.duration);
+
+            let listed = pool.list_info().await;
+            assert_eq!(2, listed.len());
+            assert_eq!(Some(&info), listed.get(&failed));
+            assert!(pool.info(failed + 1).await.is_none());
+        });
+    }
+
+    // testing resource usage of finished jobs and the summary including deleted jobs
+    #[test]
+    fn test_resource_usage() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let script = "i=0; while [ $i -lt 20000 ]; do i=$((i+1)); done";
+            let busy = pool.submit("sh", &["-c", script]).await;
+            let sleeping = pool.submit("sleep", &["5"]).await;
+            assert_eq!(None, pool.info(sleeping).await.unwrap().usage);
+
+            sleep(Duration::from_millis(1000)).await;
+            let info = pool.info(busy).await.unwrap();
+            assert_eq!(JobStatus::Completed { exit_code: 0 }, info.status);
+            let usage = info.usage.unwrap();
+            assert!(usage.user_time + usage.system_time > Duration::ZERO);
+            assert!(usage.max_rss > 0);
+            let summary = pool.usage_summary();
+            assert_eq!(("default", 1), (summary.client.as_str(), summary.jobs));
+            assert_eq!(usage, summary.usage);
+
+            assert_eq!(Some(Ok(())), pool.delete(sleeping).await);
+            assert_eq!(Some(Ok(())), pool.delete(busy).await);
+            let summary = pool.usage_summary();
+            assert_eq!(2, summary.jobs);
+            assert!(summary.usage.user_time >= usage.user_time);
+        });
+    }
+
+    // testing timeouts set by the job and by the pool's default and maximum
+    #[test]
+    fn test_timeout() {
+        setup();
+        let config = JobPoolConfig {
+            default_timeout: Some(Duration::from_millis(100)),
+            max_timeout: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let pool = JobPool::with_config("timeout", config);
+        RUNTIME.block_on(async {
+            let spec = JobSpec::new("sh", &["-c", "trap '' TERM; exec sleep 5"])
+                .with_timeout(Duration::from_millis(200))
+                .with_stop_grace(Duration::from_millis(100));
+            let escalated = pool.submit_spec(spec).await.unwrap();
+            let defaulted = pool.submit("sleep", &["5"]).await;
+            let spec = JobSpec::new("sleep", &["5"]).with_timeout(Duration::from_secs(2));
+            assert!(pool.submit_spec(spec).await.is_err());
+
+            assert_eq!(Some(JobStatus::Running), pool.status(escalated).await);
+            sleep(Duration::from_millis(600)).await;
+            let expected = JobStatus::TimedOut {
+                timeout: Duration::from_millis(200),
+                escalated: true,
+            };
+            assert_eq!(Some(expected), pool.status(escalated).await);
+            let expected = JobStatus::TimedOut {
+                timeout: Duration::from_millis(100),
+                escalated: false,
+            };
+            assert_eq!(Some(expected), pool.status(defaulted).await);
+            let listed = pool.list().await;
+            assert_eq!(Some(Duration::from_millis(100)), listed[&defaulted].timeout);
+
+            // timeouts beyond any representable time are rejected, or never reached if set by the pool
+            let spec = JobSpec::new("sleep", &["5"]).with_timeout(Duration::MAX);
+            assert!(JobPool::new().submit_spec(spec).await.is_err());
+            let config = JobPoolConfig {
+                default_timeout: Some(Duration::MAX),
+                ..Default::default()
+            };
+            let unlimited = JobPool::with_config("unlimited", config);
+            let id = unlimited.submit("sleep", &["5"]).await;
+            sleep(Duration::from_millis(100)).await;
+            assert_eq!(Some(JobStatus::Running), unlimited.status(id).await);
+            match unlimited.stop(id, None, None).await {
+                Some(JobStatus::Halted { .. }) => {}
+                status => panic!("expected halted job, got: {:?}", status),
+            }
+        });
+    }
+
+    // testing jobs queued by the pool's and by the shared limit of running jobs
+    #[test]
+    fn test_queue() {
+        setup();
+        let config = JobPoolConfig {
+            scheduler: Scheduler::new(Some(2)),
+            ..Default::default()
+        };
+        let limited = JobPool::with_config(
+            "limited",
+            JobPoolConfig {
+                max_running_jobs: Some(1),
+                ..config.clone()
+            },
+        );
+        let shared = JobPool::with_config("shared", config);
+        RUNTIME.block_on(async {
+            let running = limited.submit("sleep", &["5"]).await;
+            let cancelled = limited.submit("echo", &["cancelled"]).await;
+            let queued = limited.submit("echo", &["queued"]).await;
+            assert_eq!(Some(JobStatus::Running), limited.status(running).await);
+            let expected = JobStatus::Queued { position: 2 };
+            assert_eq!(Some(expected), limited.status(queued).await);
+
+            assert_eq!(Some(Ok(())), limited.delete(cancelled).await);
+            let expected = JobStatus::Queued { position: 1 };
+            assert_eq!(Some(expected), limited.status(queued).await);
+
+            // second slot of the shared limit
+            let other = shared.submit("sleep", &["5"]).await;
+            let waiting = shared.submit("echo", &["waiting"]).await;
+            assert_eq!(Some(JobStatus::Running), shared.status(other).await);
+            let expected = JobStatus::Queued { position: 1 };
+            assert_eq!(Some(expected), shared.status(waiting).await);
+
+            assert_eq!(Some(Ok(())), limited.delete(running).await);
+            sleep(Duration::from_millis(300)).await;
+            let completed = Some(JobStatus::Completed { exit_code: 0 });
+            assert_eq!(completed, limited.status(queued).await);
+            assert_eq!(completed, shared.status(waiting).await);
+            let output = limited.output(queued).await.unwrap();
+            assert_eq!("queued\n", &output.stdout());
+            assert_eq!(Some(Ok(())), shared.delete(other).await);
+        });
+    }
+
+    // testing that queued jobs start by priority and slots are shared fairly between pools
+    #[test]
+    fn test_scheduling() {
+        setup();
+        let config = JobPoolConfig {
+            scheduler: Scheduler::new(Some(1)),
+            ..Default::default()
+        };
+        let heavy = JobPool::with_config("heavy", config.clone());
+        let light = JobPool::with_config("light", config);
+        RUNTIME.block_on(async {
+            let blocking = heavy.submit("sleep", &["5"]).await;
+            let low = heavy.submit("echo", &["low"]).await;
+            let raised = heavy.submit("echo", &["raised"]).await;
+            let spec = JobSpec::new("echo", &["high"]).with_priority(Priority::High);
+            let high = heavy.submit_spec(spec).await.unwrap();
+            let expected = JobStatus::Queued { position: 1 };
+            assert_eq!(Some(expected), heavy.status(high).await);
+            assert_eq!(
+                Some(Ok(())),
+                heavy.set_priority(raised, Priority::High).await
+            );
+            let expected = JobStatus::Queued { position: 2 };
+            assert_eq!(Some(expected), heavy.status(high).await);
+            let expected = JobStatus::Queued { position: 3 };
+            assert_eq!(Some(expected), heavy.status(low).await);
+
+            // the light pool gets the next slot, although its job was queued last
+            let other = light.submit("sleep", &["5"]).await;
+            assert_eq!(Some(Ok(())), heavy.delete(blocking).await);
+            sleep(Duration::from_millis(100)).await;
+            assert_eq!(Some(JobStatus::Running), light.status(other).await);
+            let expected = JobStatus::Queued { position: 1 };
+            assert_eq!(Some(expected), heavy.status(raised).await);
+
+            assert_eq!(Some(Ok(())), light.delete(other).await);
+            sleep(Duration::from_millis(300)).await;
+            let infos = heavy.list_info().await;
+            let completed = Some(JobStatus::Completed { exit_code: 0 });
+            for id in &[raised, high, low] {
+                assert_eq!(completed, Some(infos[id].status.clone()));
+            }
+            assert!(infos[&raised].started_at <= infos[&high].started_at);
+            assert!(infos[&high].started_at <= infos[&low].started_at);
+            assert!(heavy
+                .set_priority(low, Priority::Low)
+                .await
+                .unwrap()
+                .is_err());
+        });
+    }
+
+    // testing that jobs start or get skipped according to the conditions on their dependencies
+    #[test]
+    fn test_dependencies() {
         setup();
         let pool = JobPool::new();
         RUNTIME.block_on(async {
-            let id = pool.submit("echo", &["hi"]).await;
+            let build = pool.submit("sleep", &["0.2"]).await;
+            let spec = JobSpec::new("echo", &["test"]).with_dependency(build, Condition::OnSuccess);
+            let test = pool.submit_spec(spec).await.unwrap();
+            let spec = JobSpec::new("echo", &["fix"]).with_dependency(build, Condition::OnFailure);
+            let fix = pool.submit_spec(spec).await.unwrap();
+            let spec = JobSpec::new("echo", &["report"]).with_dependency(fix, Condition::Always);
+            let report = pool.submit_spec(spec).await.unwrap();
+            let spec = JobSpec::new("echo", &["unknown"]).with_dependency(42, Condition::Always);
+            assert!(pool.submit_spec(spec).await.is_err());
+
+            let expected = JobStatus::Waiting {
+                dependencies: vec![build],
+            };
+            assert_eq!(Some(expected), pool.status(test).await);
+            sleep(Duration::from_millis(500)).await;
+            let completed = Some(JobStatus::Completed { exit_code: 0 });
+            assert_eq!(completed, pool.status(test).await);
+            let output = pool.output(test).await.unwrap();
+            assert_eq!("test\n", &output.stdout());
+            let expected = JobStatus::Skipped { dependency: build };
+            assert_eq!(Some(expected), pool.status(fix).await);
+            assert_eq!(completed, pool.status(report).await);
+
+            // deleting a dependency skips the jobs waiting for it
+            let blocking = pool.submit("sleep", &["5"]).await;
+            let spec =
+                JobSpec::new("echo", &["never"]).with_dependency(blocking, Condition::Always);
+            let never = pool.submit_spec(spec).await.unwrap();
+            assert_eq!(Some(Ok(())), pool.delete(blocking).await);
             sleep(Duration::from_millis(100)).await;
-            let output = pool.output(id).await;
-            assert!(output.is_some());
-            let output = output.unwrap();
-            assert_eq!("hi\n".to_string(), output.stdout())
+            let expected = JobStatus::Skipped {
+                dependency: blocking,
+            };
+            assert_eq!(Some(expected), pool.status(never).await);
         });
     }
 
-    // testing status of oneshot ls command
+    // testing the submission of jobs depending on each other by name
     #[test]
-    fn test_status_ls() {
+    fn test_workflow() {
         setup();
         let pool = JobPool::new();
         RUNTIME.block_on(async {
-            let id = pool.submit("ls", &[]).await;
+            let step = |name: &str, command: &str, after: &[&str]| WorkflowJob {
+                name: name.to_string(),
+                spec: JobSpec::new(command, &[]),
+                after: after
+                    .iter()
+                    .map(|job| WorkflowDependency {
+                        job: job.to_string(),
+                        condition: Condition::OnSuccess,
+                    })
+                    .collect(),
+            };
+            let cyclic = Workflow {
+                jobs: vec![step("a", "true", &["b"]), step("b", "true", &["a"])],
+            };
+            assert!(pool.submit_workflow(cyclic).await.is_err());
+            let unknown = Workflow {
+                jobs: vec![step("a", "true", &["c"])],
+            };
+            assert!(pool.submit_workflow(unknown).await.is_err());
+            assert!(pool.list().await.is_empty());
+
+            // listed before the job it depends on
+            let workflow = Workflow {
+                jobs: vec![
+                    step("package", "true", &["test"]),
+                    step("build", "true", &[]),
+                    step("test", "false", &["build"]),
+                ],
+            };
+            let ids = pool.submit_workflow(workflow).await.unwrap();
+            assert!(ids["build"] < ids["test"] && ids["test"] < ids["package"]);
+            sleep(Duration::from_millis(300)).await;
+            let completed = Some(JobStatus::Completed { exit_code: 0 });
+            assert_eq!(completed, pool.status(ids["build"]).await);
+            let failed = Some(JobStatus::Completed { exit_code: 1 });
+            assert_eq!(failed, pool.status(ids["test"]).await);
+            let skipped = Some(JobStatus::Skipped {
+                dependency: ids["test"],
+            });
+            assert_eq!(skipped, pool.status(ids["package"]).await);
+        });
+    }
+
+    // testing resource limits without a configured cgroup parent
+    // testing retries of failed attempts and the kept history of the attempts
+    #[test]
+    fn test_retries() {
+        setup();
+        let pool = JobPool::new();
+        let counter = env::temp_dir().join(format!("rcmd-retries-{}", process::id()));
+        let _ = fs::remove_file(&counter);
+        RUNTIME.block_on(async {
+            // fails in the first two attempts
+            let script = format!(
+                "n=$(($(cat {0} 2>/dev/null || echo 0) + 1)); echo $n > {0}; echo attempt $n; [ $n -ge 3 ]",
+                counter.display()
+            );
+            let mut retry = RetryPolicy::new(3);
+            retry.backoff = Duration::from_millis(200);
+            let spec = JobSpec::new("sh", &["-c", &script]).with_retry(retry);
+            let id = pool.submit_spec(spec).await.unwrap();
             sleep(Duration::from_millis(100)).await;
-            let status = pool.status(id).await;
-            assert!(status.is_some());
-            let status = status.unwrap();
-            if let JobStatus::Completed { exit_code } = status {
-                assert_eq!(0, exit_code)
-            } else {
-                panic!("unexpected job status: {:?}", status);
+            match pool.status(id).await {
+                Some(JobStatus::Retrying { attempt: 1, .. }) => {}
+                status => panic!("unexpected status {:?}", status),
+            }
+            sleep(Duration::from_millis(800)).await;
+            let completed = Some(JobStatus::Completed { exit_code: 0 });
+            assert_eq!(completed, pool.status(id).await);
+            let info = pool.info(id).await.unwrap();
+            assert_eq!(3, info.attempt);
+            assert_eq!(vec![1, 2], info.attempts.iter().map(|a| a.number).collect::<Vec<_>>());
+            for attempt in &info.attempts {
+                assert_eq!(JobStatus::Completed { exit_code: 1 }, attempt.status);
+                assert!(attempt.pid.is_some() && attempt.ended_at.is_some());
+            }
+            let output = pool.attempt_output(id, 1).await.unwrap().unwrap();
+            assert_eq!("attempt 1\n", &output.stdout());
+            let output = pool.attempt_output(id, 3).await.unwrap().unwrap();
+            assert_eq!("attempt 3\n", &output.stdout());
+            assert_eq!("attempt 3\n", &pool.output(id).await.unwrap().stdout());
+            assert!(pool.attempt_output(id, 4).await.unwrap().is_err());
+
+            // exit codes that are not listed are not retried
+            let mut retry = RetryPolicy::new(3);
+            retry.exit_codes = vec![75];
+            let spec = JobSpec::new("sh", &["-c", "exit 1"]).with_retry(retry);
+            let id = pool.submit_spec(spec).await.unwrap();
+            sleep(Duration::from_millis(100)).await;
+            assert_eq!(Some(JobStatus::Completed { exit_code: 1 }), pool.status(id).await);
+            assert!(pool.info(id).await.unwrap().attempts.is_empty());
+
+            // a backoff too long to add to the current time keeps the job retrying
+            let mut retry = RetryPolicy::new(2);
+            retry.backoff = Duration::MAX;
+            let spec = JobSpec::new("false", &[]).with_retry(retry);
+            let id = pool.submit_spec(spec).await.unwrap();
+            sleep(Duration::from_millis(100)).await;
+            match pool.status(id).await {
+                Some(JobStatus::Retrying { attempt: 1, .. }) => {}
+                status => panic!("unexpected status {:?}", status),
             }
+            assert_eq!(Some(Ok(())), pool.delete(id).await);
+
+            let spec = JobSpec::new("cat", &[]).with_retry(RetryPolicy::new(2));
+            let spec = JobSpec {
+                stdin: StdinMode::Pipe,
+                ..spec
+            };
+            assert!(pool.submit_spec(spec).await.is_err());
         });
+        let _ = fs::remove_file(&counter);
     }
 
-    // testing status of sleep job before and after delete
     #[test]
-    fn test_status_sleep_deleted() {
+    fn test_limits_without_cgroup_parent() {
         setup();
         let pool = JobPool::new();
         RUNTIME.block_on(async {
-            let id = pool.submit("sleep", &["5"]).await;
-            let status = pool.status(id).await;
-            assert!(status.is_some());
-            let status = status.unwrap();
-            assert_eq!(JobStatus::Running, status);
+            let limits = ResourceLimits {
+                memory_max: Some(64 * 1024 * 1024),
+                ..Default::default()
+            };
+            let spec = JobSpec::new("echo", &["hi"]).with_limits(limits);
+            let submitted = pool.submit_spec(spec).await;
+            match submitted {
+                Err(msg) => assert!(msg.contains("resource limits")),
+                s => panic!("expected submission error, got: {:?}", s),
+            }
+            assert!(pool.list().await.is_empty());
+        });
+    }
+
+    // testing that an OOM kill in the job's cgroup is reported even if the job exited with a code
+    #[test]
+    fn test_oom_killed() {
+        setup();
+        // fake cgroup parent, the job itself records the OOM kill in its cgroup's memory events
+        let parent = env::temp_dir().join(format!("rcmd-test-oom-{}", process::id()));
+        fs::create_dir_all(&parent).unwrap();
+        fs::write(parent.join("cgroup.subtree_control"), "memory").unwrap();
+        let config = JobPoolConfig {
+            cgroup_parent: Some(parent.clone()),
+            ..Default::default()
+        };
+        let pool = JobPool::with_config("oom", config);
+        RUNTIME.block_on(async {
+            let limits = ResourceLimits {
+                memory_max: Some(64 * 1024 * 1024),
+                ..Default::default()
+            };
+            let script = format!(
+                "echo 'oom_kill 1' > {}/oom-job-0/memory.events; exit 137",
+                parent.display()
+            );
+            let spec = JobSpec::new("sh", &["-c", &script]).with_limits(limits.clone());
+            let oom = pool.submit_spec(spec).await.unwrap();
+            sleep(Duration::from_millis(300)).await;
+            assert_eq!(Some(JobStatus::OomKilled), pool.status(oom).await);
+
+            // exit codes of jobs in a cgroup without OOM kills are kept
+            let spec = JobSpec::new("sh", &["-c", "exit 137"]).with_limits(limits);
+            let completed = pool.submit_spec(spec).await.unwrap();
+            sleep(Duration::from_millis(300)).await;
+            assert_eq!(
+                Some(JobStatus::Completed { exit_code: 137 }),
+                pool.status(completed).await
+            );
+        });
+        fs::remove_dir_all(parent).unwrap();
+    }
+
+    // testing that deleting a job also kills processes it spawned in the background
+    #[test]
+    fn test_delete_kills_process_group() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let id = pool
+                .submit("bash", &["-c", "sleep 100 & echo $!; wait"])
+                .await;
+            sleep(Duration::from_millis(100)).await;
+            let output = pool.output(id).await.unwrap();
+            let background_pid: i32 = output.stdout().trim().parse().unwrap();
+            assert_eq!(0, unsafe { libc::kill(background_pid, 0) });
+
             let delete_response = pool.delete(id).await;
             assert_eq!(Some(Ok(())), delete_response);
-            let status = pool.status(id).await;
-            assert!(status.is_none());
+            assert_eq!(-1, unsafe { libc::kill(background_pid, 0) });
         });
     }
 
-    // testing output of echo loop
+    // testing graceful stop of job that exits on SIGTERM
     #[test]
-    fn test_output_repeated_echo() {
+    fn test_stop_graceful() {
         setup();
         let pool = JobPool::new();
         RUNTIME.block_on(async {
             let id = pool
-                .submit("bash", &["-c", "while true; do echo hi; sleep 1; done"])
+                .submit(
+                    "bash",
+                    &[
+                        "-c",
+                        "trap 'echo bye; exit 3' TERM; while true; do sleep 0.1; done",
+                    ],
+                )
                 .await;
             sleep(Duration::from_millis(100)).await;
+            let status = pool.stop(id, None, Some(Duration::from_secs(5))).await;
+            let expected = JobStatus::Halted {
+                signal: "SIGTERM".to_string(),
+                grace: Duration::from_secs(5),
+                escalated: false,
+                exit_code: Some(3),
+            };
+            assert_eq!(Some(expected.clone()), status);
+            // job is kept after stopping
+            assert_eq!(Some(expected), pool.status(id).await);
+            assert_eq!("bye\n", pool.output(id).await.unwrap().stdout());
+        });
+    }
 
-            let status = pool.status(id).await;
-            assert!(status.is_some());
-            let status = status.unwrap();
-            assert_eq!(JobStatus::Running, status);
-            let output = pool.output(id).await.unwrap();
-            assert_eq!("hi\n", &output.stdout());
-            sleep(Duration::from_millis(1000)).await;
+    // testing stop of job that ignores SIGTERM
+    #[test]
+    fn test_stop_escalated() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let spec = JobSpec::new("bash", &["-c", "trap '' TERM; sleep 100"])
+                .with_stop_grace(Duration::from_millis(200));
+            let id = pool.submit_spec(spec).await.unwrap();
+            sleep(Duration::from_millis(100)).await;
+            // the pool is not locked while the stop waits out the grace period
+            let (status, stopping) = tokio::join!(pool.stop(id, None, None), async {
+                sleep(Duration::from_millis(50)).await;
+                timeout(Duration::from_millis(50), pool.status(id)).await
+            });
+            assert_eq!(Ok(Some(JobStatus::Running)), stopping);
+            let expected = JobStatus::Halted {
+                signal: "SIGTERM".to_string(),
+                grace: Duration::from_millis(200),
+                escalated: true,
+                exit_code: None,
+            };
+            assert_eq!(Some(expected), status);
+        });
+    }
+
+    // testing that a stop still reports the job as stopped if it got deleted while stopping
+    #[test]
+    fn test_stop_deleted() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let spec = JobSpec::new("bash", &["-c", "trap '' TERM; sleep 100"])
+                .with_stop_grace(Duration::from_secs(1));
+            let id = pool.submit_spec(spec).await.unwrap();
+            sleep(Duration::from_millis(100)).await;
+            let (status, deleted) = tokio::join!(pool.stop(id, None, None), async {
+                sleep(Duration::from_millis(100)).await;
+                pool.delete(id).await
+            });
+            assert_eq!(Some(Ok(())), deleted);
+            let expected = JobStatus::Halted {
+                signal: "SIGTERM".to_string(),
+                grace: Duration::from_secs(1),
+                escalated: false,
+                exit_code: None,
+            };
+            assert_eq!(Some(expected), status);
+            assert_eq!(None, pool.stop(id, None, None).await);
+        });
+    }
+
+    // testing that shutdown stops all jobs and leaves none of their processes behind
+    #[test]
+    fn test_shutdown() {
+        setup();
+        let config = JobPoolConfig {
+            max_running_jobs: Some(2),
+            ..Default::default()
+        };
+        let pool = JobPool::with_config("shutdown", config);
+        RUNTIME.block_on(async {
+            let background = pool
+                .submit("bash", &["-c", "sleep 100 & echo $!; wait"])
+                .await;
+            let spec = JobSpec::new("bash", &["-c", "trap '' TERM; sleep 100"]);
+            let stubborn = pool.submit_spec(spec).await.unwrap();
+            let queued = pool.submit("sleep", &["100"]).await;
+            sleep(Duration::from_millis(100)).await;
+            let output = pool.output(background).await.unwrap();
+            let background_pid: i32 = output.stdout().trim().parse().unwrap();
+            let mut pids = vec![background_pid];
+            for id in [background, stubborn] {
+                pids.push(pool.info(id).await.unwrap().pid.unwrap() as i32);
+            }
+            for pid in &pids {
+                assert_eq!(0, unsafe { libc::kill(*pid, 0) });
+            }
+
+            pool.shutdown(Some(Duration::from_millis(200))).await;
+            // reaped as well, a zombie could still be signalled
+            for pid in &pids {
+                assert_eq!(-1, unsafe { libc::kill(*pid, 0) });
+            }
+            let halted = |escalated| JobStatus::Halted {
+                signal: "SIGTERM".to_string(),
+                grace: Duration::from_millis(200),
+                escalated,
+                exit_code: None,
+            };
+            assert_eq!(Some(halted(false)), pool.status(background).await);
+            assert_eq!(Some(halted(true)), pool.status(stubborn).await);
+            assert_eq!(Some(halted(false)), pool.status(queued).await);
+            assert!(pool.info(queued).await.unwrap().pid.is_none());
+
+            let id = pool.submit("sleep", &["100"]).await;
+            let expected = JobStatus::Error {
+                msg: "job pool is shut down".to_string(),
+            };
+            assert_eq!(Some(expected), pool.status(id).await);
+        });
+    }
+
+    // testing that a pool that stopped accepting jobs rejects new ones through every entry point
+    #[test]
+    fn test_stop_accepting() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let running = pool.submit("sleep", &["0.2"]).await;
+            pool.stop_accepting();
+            assert!(!pool.accepting());
+            let id = pool.submit("echo", &["rejected"]).await;
+            let expected = JobStatus::Error {
+                msg: "job pool does not accept new jobs".to_string(),
+            };
+            assert_eq!(Some(expected), pool.status(id).await);
+            assert!(pool.info(id).await.unwrap().pid.is_none());
+            assert!(pool
+                .submit_spec(JobSpec::new("echo", &["rejected"]))
+                .await
+                .is_err());
+            let workflow = Workflow {
+                jobs: vec![WorkflowJob {
+                    name: "a".to_string(),
+                    spec: JobSpec::new("echo", &["rejected"]),
+                    after: Vec::new(),
+                }],
+            };
+            assert!(pool.submit_workflow(workflow).await.is_err());
+            assert_eq!(2, pool.list().await.len());
+            // jobs submitted before still run to the end
+            sleep(Duration::from_millis(400)).await;
+            assert_eq!(
+                Some(JobStatus::Completed { exit_code: 0 }),
+                pool.status(running).await
+            );
+        });
+    }
+
+    // testing pausing and resuming a job with signals
+    #[test]
+    fn test_signal_pause_resume() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let id = pool.submit("sleep", &["5"]).await;
+            assert_eq!(Some(Ok(())), pool.signal(id, libc::SIGSTOP).await);
+            assert_eq!(Some(JobStatus::Stopped), pool.status(id).await);
+            assert_eq!(Some(Ok(())), pool.signal(id, libc::SIGCONT).await);
+            assert_eq!(Some(JobStatus::Running), pool.status(id).await);
+            assert_eq!(Some(Ok(())), pool.signal(id, libc::SIGINT).await);
+            sleep(Duration::from_millis(100)).await;
+            let expected = JobStatus::Terminated {
+                signal: "SIGINT".to_string(),
+                core_dumped: false,
+                by_rcmd: true,
+            };
+            assert_eq!(Some(expected), pool.status(id).await);
+            assert!(pool.signal(id, libc::SIGINT).await.unwrap().is_err());
+        });
+    }
+
+    // testing a job killed by a signal not sent through rcmd
+    #[test]
+    fn test_killed_by_signal() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let id = pool.submit("sh", &["-c", "kill -USR1 $$"]).await;
+            sleep(Duration::from_millis(100)).await;
+            let expected = JobStatus::Terminated {
+                signal: "SIGUSR1".to_string(),
+                core_dumped: false,
+                by_rcmd: false,
+            };
+            assert_eq!(Some(expected), pool.status(id).await);
+        });
+    }
 
+    // testing environment, working directory and umask of a job
+    #[test]
+    fn test_env_working_dir_umask() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let mut spec = JobSpec::new("sh", &["-c", "echo $GREETING $HOME; pwd; umask"]);
+            spec.env.insert("GREETING".to_string(), "hi".to_string());
+            spec.clear_env = true;
+            spec.working_dir = Some("/".into());
+            spec.umask = Some(0o027);
+            let id = pool.submit_spec(spec).await.unwrap();
+            sleep(Duration::from_millis(100)).await;
             let output = pool.output(id).await.unwrap();
-            assert_eq!("hi\nhi\n", &output.stdout());
-            let delete_response = pool.delete(id).await;
-            assert_eq!(Some(Ok(())), delete_response);
-            let status = pool.status(id).await;
-            assert!(status.is_none());
+            assert_eq!("hi\n/\n0027\n", output.stdout());
         });
     }
 
-    // testing invalid command
+    // testing rejection of invalid spec values
     #[test]
-    fn test_invalid_command() {
+    fn test_invalid_spec() {
         setup();
         let pool = JobPool::new();
         RUNTIME.block_on(async {
-            let id = pool.submit("abcdfg", &[]).await;
+            let mut spec = JobSpec::new("ls", &[]);
+            spec.env.insert("A=B".to_string(), "hi".to_string());
+            assert!(pool.submit_spec(spec).await.is_err());
+            let mut spec = JobSpec::new("ls", &[]);
+            spec.working_dir = Some("/does/not/exist".into());
+            assert!(pool.submit_spec(spec).await.is_err());
+            let mut spec = JobSpec::new("ls", &[]);
+            spec.umask = Some(0o1000);
+            assert!(pool.submit_spec(spec).await.is_err());
+            assert!(pool.list().await.is_empty());
+        });
+    }
 
-            let status = pool.status(id).await;
-            assert!(status.is_some());
-            let status = status.unwrap();
-            match status {
-                JobStatus::Error { msg: _ } => {}
-                s => panic!("expected error job status, got: {:?}", s),
+    // testing stdin modes
+    #[test]
+    fn test_stdin() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let null_id = pool.submit("cat", &[]).await;
+            let mut spec = JobSpec::new("cat", &[]);
+            spec.stdin = StdinMode::Data(b"data\n".to_vec());
+            let data_id = pool.submit_spec(spec).await.unwrap();
+            let mut spec = JobSpec::new("cat", &[]);
+            spec.stdin = StdinMode::Pipe;
+            let pipe_id = pool.submit_spec(spec).await.unwrap();
+            assert!(pool
+                .write_stdin(null_id, b"x".to_vec())
+                .await
+                .unwrap()
+                .is_err());
+            assert_eq!(
+                Some(Ok(())),
+                pool.write_stdin(pipe_id, b"pi".to_vec()).await
+            );
+            assert_eq!(
+                Some(Ok(())),
+                pool.write_stdin(pipe_id, b"pe\n".to_vec()).await
+            );
+            sleep(Duration::from_millis(100)).await;
+            assert_eq!(Some(JobStatus::Running), pool.status(pipe_id).await);
+            assert_eq!(Some(Ok(())), pool.close_stdin(pipe_id).await);
+            sleep(Duration::from_millis(100)).await;
+
+            let completed = Some(JobStatus::Completed { exit_code: 0 });
+            assert_eq!(completed, pool.status(null_id).await);
+            assert_eq!(completed, pool.status(data_id).await);
+            assert_eq!(completed, pool.status(pipe_id).await);
+            assert_eq!("", pool.output(null_id).await.unwrap().stdout());
+            assert_eq!("data\n", pool.output(data_id).await.unwrap().stdout());
+            assert_eq!("pipe\n", pool.output(pipe_id).await.unwrap().stdout());
+        });
+    }
+
+    // testing two followers of a job, one joining after some output was produced
+    #[test]
+    fn test_follow_output() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let script = "echo one; sleep 0.2; echo two >&2; sleep 0.2; echo three; exit 3";
+            let id = pool.submit("sh", &["-c", script]).await;
+            let follow = |delay| {
+                let pool = &pool;
+                async move {
+                    sleep(delay).await;
+                    let mut follower = pool.follow(id).await.unwrap();
+                    let mut output = Vec::new();
+                    while let Some(mut new_output) = follower.next().await {
+                        output.append(&mut new_output);
+                    }
+                    (output, follower.status().await)
+                }
+            };
+            // the late follower joins after the first line was written
+            let (early, late) =
+                tokio::join!(follow(Duration::ZERO), follow(Duration::from_millis(100)));
+            let expected = vec![
+                (OutputStream::Stdout, b"one\n".to_vec()),
+                (OutputStream::Stderr, b"two\n".to_vec()),
+                (OutputStream::Stdout, b"three\n".to_vec()),
+            ];
+            let completed = Some(JobStatus::Completed { exit_code: 3 });
+            assert_eq!((expected.clone(), completed.clone()), early);
+            assert_eq!((expected, completed), late);
+            assert!(pool.follow(id + 1).await.is_none());
+        });
+    }
+
+    // testing byte ranges, head and tail of output
+    #[test]
+    fn test_output_range() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let id = pool.submit("printf", &["a\\nbb\\nccc\\nü"]).await;
+            sleep(Duration::from_millis(100)).await;
+            let range = |from, limit| OutputRange::Bytes { from, limit };
+            let chunk = |offset, next_offset, data: &[u8]| {
+                Some(Ok(OutputChunk {
+                    offset,
+                    next_offset,
+                    size: 11,
+                    data: data.to_vec(),
+                }))
+            };
+            let stdout = OutputStream::Stdout;
+            assert_eq!(
+                chunk(0, 11, "a\nbb\nccc\nü".as_bytes()),
+                pool.output_range(id, stdout, range(0, None)).await
+            );
+            assert_eq!(
+                chunk(2, 7, b"bb\ncc"),
+                pool.output_range(id, stdout, range(2, Some(5))).await
+            );
+            // ranges are exact, even if they split a character
+            assert_eq!(
+                chunk(10, 11, b"\xbc"),
+                pool.output_range(id, stdout, range(10, Some(1))).await
+            );
+            assert_eq!(
+                chunk(11, 11, b""),
+                pool.output_range(id, stdout, range(20, None)).await
+            );
+            assert_eq!(
+                chunk(0, 5, b"a\nbb\n"),
+                pool.output_range(id, stdout, OutputRange::Head(2)).await
+            );
+            assert_eq!(
+                chunk(5, 11, "ccc\nü".as_bytes()),
+                pool.output_range(id, stdout, OutputRange::Tail(2)).await
+            );
+            let stderr = pool.output_range(id, OutputStream::Stderr, OutputRange::Tail(2));
+            assert_eq!(0, stderr.await.unwrap().unwrap().size);
+            assert!(pool
+                .output_range(id + 1, stdout, range(0, None))
+                .await
+                .is_none());
+        });
+    }
+
+    // testing the output cap and removal of spool files on delete
+    #[test]
+    fn test_spool_truncation() {
+        setup();
+        let dir = env::temp_dir().join(format!("rcmd-test-spool-{}", process::id()));
+        let config = JobPoolConfig {
+            spool_dir: Some(dir.clone()),
+            max_job_output: Some(10),
+            ..Default::default()
+        };
+        let pool = JobPool::with_config("spool", config.clone());
+        RUNTIME.block_on(async {
+            let script = "echo 12345; sleep 0.1; echo 67890";
+            let id = pool.submit("sh", &["-c", script]).await;
+            sleep(Duration::from_millis(300)).await;
+            let output = pool.output(id).await.unwrap();
+            assert_eq!("12345\n###OUTPUT TRUNCATED###\n", output.stdout());
+            assert_eq!(29, config.spool_usage.load(Ordering::Relaxed));
+            // data and index file for each stream
+            assert_eq!(4, fs::read_dir(&dir).unwrap().count());
+            assert_eq!(Some(Ok(())), pool.delete(id).await);
+            assert_eq!(0, fs::read_dir(&dir).unwrap().count());
+            assert_eq!(0, config.spool_usage.load(Ordering::Relaxed));
+        });
+        fs::remove_dir(dir).unwrap();
+    }
+
+    // testing that the oldest finished jobs are evicted beyond the retention limits
+    #[test]
+    fn test_retention() {
+        setup();
+        let dir = env::temp_dir().join(format!("rcmd-test-retention-{}", process::id()));
+        let config = JobPoolConfig {
+            spool_dir: Some(dir.join("spool")),
+            state_dir: Some(dir.join("state")),
+            retention: Retention {
+                max_jobs: Some(3),
+                max_output: Some(6),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        RUNTIME.block_on(async {
+            // the background reaper is spawned along with the pool
+            let pool = JobPool::with_config("retention", config.clone());
+            let running = pool.submit("sleep", &["100"]).await;
+            let first = pool.submit("echo", &["one"]).await;
+            sleep(Duration::from_millis(50)).await;
+            let waiting = pool
+                .submit_spec(JobSpec::new("echo", &[]).with_dependency(running, Condition::Always))
+                .await
+                .unwrap();
+            let spec = JobSpec::new("echo", &["two"]).with_dependency(first, Condition::Always);
+            let second = pool.submit_spec(spec).await.unwrap();
+            sleep(Duration::from_millis(50)).await;
+            let third = pool.submit("echo", &["three"]).await;
+            let fourth = pool.submit("true", &[]).await;
+            sleep(Duration::from_millis(100)).await;
+
+            // too many finished jobs, then too much output
+            assert_eq!(2, pool.evict_finished().await);
+            assert_eq!(None, pool.status(first).await);
+            assert_eq!(
+                Some("more than 3 finished jobs".to_string()),
+                pool.evicted(first)
+            );
+            assert_eq!(None, pool.status(second).await);
+            assert_eq!(
+                Some("finished jobs exceeded 6 bytes of output".to_string()),
+                pool.evicted(second)
+            );
+            assert!(pool.status(third).await.is_some());
+            assert!(pool.status(fourth).await.is_some());
+            assert_eq!(Some(JobStatus::Running), pool.status(running).await);
+            assert!(pool.status(waiting).await.is_some());
+            assert_eq!(0, pool.evict_finished().await);
+
+            // deleted jobs are not evicted
+            pool.delete(third).await;
+            assert_eq!(None, pool.evicted(third));
+
+            // the reasons are kept across restarts
+            pool.delete(running).await;
+            drop(pool);
+            let pool = JobPool::with_config("retention", config);
+            assert_eq!(None, pool.status(first).await);
+            assert_eq!(
+                Some("more than 3 finished jobs".to_string()),
+                pool.evicted(first)
+            );
+        });
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // testing that finished jobs and their output survive a restart, unfinished ones are lost
+    #[test]
+    fn test_persistence() {
+        setup();
+        let dir = env::temp_dir().join(format!("rcmd-test-state-{}", process::id()));
+        let config = JobPoolConfig {
+            spool_dir: Some(dir.join("spool")),
+            state_dir: Some(dir.join("state")),
+            ..Default::default()
+        };
+        RUNTIME.block_on(async {
+            let pool = JobPool::with_config("persisted pool", config.clone());
+            let echo = pool.submit("echo", &["hi"]).await;
+            let sleep_id = pool.submit("sleep", &["1"]).await;
+            let deleted = pool.submit("echo", &["deleted"]).await;
+            sleep(Duration::from_millis(100)).await;
+            assert_eq!(Some(Ok(())), pool.delete(deleted).await);
+            drop(pool);
+            assert_eq!(
+                vec!["persisted pool".to_string()],
+                JobPool::stored_pools(&config)
+            );
+
+            let pool = JobPool::with_config("persisted pool", config.clone());
+            assert_eq!(
+                Some(JobStatus::Completed { exit_code: 0 }),
+                pool.status(echo).await
+            );
+            assert_eq!("hi\n", pool.output(echo).await.unwrap().stdout());
+            match pool.status(sleep_id).await {
+                Some(JobStatus::Lost { .. }) => {}
+                status => panic!("expected lost job, got: {:?}", status),
             }
+            assert!(pool.status(deleted).await.is_none());
+            assert_eq!(deleted + 1, pool.submit("true", &[]).await);
+            assert_eq!(Some(Ok(())), pool.delete(echo).await);
+            drop(pool);
+
+            let pool = JobPool::with_config("persisted pool", config.clone());
+            assert!(pool.status(echo).await.is_none());
+            assert_eq!(2, pool.list().await.len());
         });
+        fs::remove_dir_all(dir).unwrap();
     }
 
-    // testing listing of jobs
+    // testing that output is kept byte by byte, including invalid UTF-8 and a partial last line
     #[test]
-    fn test_list_jobs() {
+    fn test_binary_output() {
         setup();
         let pool = JobPool::new();
         RUNTIME.block_on(async {
-            let first = pool.submit("abcdfg", &[]).await;
-            let second = pool.submit("ls", &[]).await;
-            let third = pool.submit("echo", &["hi"]).await;
-            let delete_response = pool.delete(second).await;
-            let listed = pool.list().await;
-            assert_eq!(Some(Ok(())), delete_response);
-            assert!(listed.contains_key(&first));
-            assert!(listed.contains_key(&third));
-            assert_eq!(2, listed.len());
-            let first_spec = JobSpec::new("abcdfg", &[]);
-            let third_spec = JobSpec::new("echo", &["hi"]);
-            assert_eq!(&first_spec, listed.get(&first).unwrap());
-            assert_eq!(&third_spec, listed.get(&third).unwrap());
+            let id = pool.submit("printf", &["\\377\\000ab\\ncd"]).await;
+            sleep(Duration::from_millis(100)).await;
+            let output = pool.output(id).await.unwrap();
+            assert_eq!(b"\xff\x00ab\ncd", output.stdout_bytes());
+            assert_eq!("\u{FFFD}\u{0}ab\ncd", output.stdout());
+        });
+    }
+
+    // testing that the combined output keeps the order of stdout and stderr lines
+    #[test]
+    fn test_combined_output() {
+        setup();
+        let pool = JobPool::new();
+        RUNTIME.block_on(async {
+            let script = "echo out1; sleep 0.05; echo err1 >&2; sleep 0.05; printf 'out2\\nout3'";
+            let id = pool.submit("sh", &["-c", script]).await;
+            sleep(Duration::from_millis(300)).await;
+            let lines = pool.combined_output(id).await.unwrap().unwrap();
+            let expected = vec![
+                (OutputStream::Stdout, b"out1\n".to_vec()),
+                (OutputStream::Stderr, b"err1\n".to_vec()),
+                (OutputStream::Stdout, b"out2\n".to_vec()),
+                (OutputStream::Stdout, b"out3".to_vec()),
+            ];
+            let actual: Vec<_> = lines
+                .iter()
+                .map(|line| (line.stream, line.data.clone()))
+                .collect();
+            assert_eq!(expected, actual);
+            assert!(lines
+                .windows(2)
+                .all(|pair| pair[0].timestamp <= pair[1].timestamp));
         });
     }
 }
@@ -1,2 +1,11 @@
+mod cgroup;
+mod cron;
 pub mod job_pool;
+pub mod schedule_pool;
+mod scheduler;
+pub mod shim;
+pub mod signal;
+mod spec;
+mod spool;
+mod store;
 mod util;
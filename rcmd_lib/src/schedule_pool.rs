@@ -0,0 +1,458 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    time::SystemTime,
+};
+
+use rcmd_data::{Condition, Dependency, JobStatus, StdinMode};
+pub use rcmd_data::{OverlapPolicy, ScheduleInfo, ScheduleSpec, Trigger};
+use tokio::{
+    sync::{watch, Mutex},
+    time::sleep,
+};
+use tracing::{error, info, instrument};
+
+use crate::{cron::CronSchedule, job_pool::JobPool};
+
+/// schedules of a client, submitting their jobs to the client's job pool when they fire
+pub struct SchedulePool {
+    job_pool: Arc<JobPool>,
+    next_schedule_id: AtomicU64,
+    schedules: Arc<Mutex<HashMap<u64, Schedule>>>,
+}
+
+struct Schedule {
+    id: u64,
+    spec: ScheduleSpec,
+    // None for schedules firing once
+    cron: Option<CronSchedule>,
+    created_at: SystemTime,
+    next_run: Option<SystemTime>,
+    last_run: Option<SystemTime>,
+    jobs: Vec<u64>,
+    skipped: u64,
+    // whether the schedule is paused, dropping it ends the task firing the schedule
+    paused_tx: watch::Sender<bool>,
+}
+
+impl SchedulePool {
+    pub fn new(job_pool: Arc<JobPool>) -> Self {
+        SchedulePool {
+            job_pool,
+            next_schedule_id: AtomicU64::new(0),
+            schedules: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// create a schedule, fails if its trigger or its job spec contains invalid values
+    #[instrument(skip(self))]
+    pub async fn create(&self, spec: ScheduleSpec) -> Result<u64, String> {
+        let (cron, next_run) = match &spec.trigger {
+            Trigger::Cron(expression) => {
+                let cron: CronSchedule = expression.parse()?;
+                let next_run = cron.next_after(SystemTime::now());
+                if next_run.is_none() {
+                    return Err(format!("cron expression never matches: {}", expression));
+                }
+                (Some(cron), next_run)
+            }
+            // a time in the past fires right away
+            Trigger::At(time) => (None, Some(*time)),
+        };
+        if spec.job.stdin == StdinMode::Pipe {
+            return Err("jobs of schedules can not read from a pipe".to_string());
+        }
+        self.job_pool.validate(&spec.job).await?;
+        let id = self.next_schedule_id.fetch_add(1, Ordering::Relaxed);
+        info!("create schedule with id {}, next run at {:?}", id, next_run);
+        let (paused_tx, paused_rx) = watch::channel(false);
+        let schedule = Schedule {
+            id,
+            spec,
+            cron,
+            created_at: SystemTime::now(),
+            next_run,
+            last_run: None,
+            jobs: Vec::new(),
+            skipped: 0,
+            paused_tx,
+        };
+        self.schedules.lock().await.insert(id, schedule);
+        tokio::spawn(run_schedule(
+            Arc::downgrade(&self.schedules),
+            self.job_pool.clone(),
+            id,
+            paused_rx,
+        ));
+        Ok(id)
+    }
+
+    /// gets the state of a schedule if it exists
+    pub async fn info(&self, id: u64) -> Option<ScheduleInfo> {
+        let schedules = self.schedules.lock().await;
+        schedules.get(&id).map(Schedule::info)
+    }
+
+    /// get a mapping of all schedules and their state
+    pub async fn list(&self) -> HashMap<u64, ScheduleInfo> {
+        let schedules = self.schedules.lock().await;
+        schedules
+            .iter()
+            .map(|(id, schedule)| (*id, schedule.info()))
+            .collect()
+    }
+
+    /// stops the schedule from firing until it is resumed, if it exists
+    #[instrument(skip(self))]
+    pub async fn pause(&self, id: u64) -> Option<()> {
+        info!("pause schedule");
+        let mut schedules = self.schedules.lock().await;
+        let schedule = schedules.get_mut(&id)?;
+        let _ = schedule.paused_tx.send(true);
+        Some(())
+    }
+
+    /// lets a paused schedule fire again, if it exists
+    /// firings missed while it was paused are not made up for
+    #[instrument(skip(self))]
+    pub async fn resume(&self, id: u64) -> Option<()> {
+        info!("resume schedule");
+        let mut schedules = self.schedules.lock().await;
+        let schedule = schedules.get_mut(&id)?;
+        if let Some(cron) = &schedule.cron {
+            schedule.next_run = cron.next_after(SystemTime::now());
+        }
+        let _ = schedule.paused_tx.send(false);
+        Some(())
+    }
+
+    /// deletes the schedule if it exists, the jobs it submitted are kept
+    #[instrument(skip(self))]
+    pub async fn delete(&self, id: u64) -> Option<()> {
+        info!("delete schedule");
+        let mut schedules = self.schedules.lock().await;
+        schedules.remove(&id).map(|_| ())
+    }
+}
+
+impl Schedule {
+    fn info(&self) -> ScheduleInfo {
+        ScheduleInfo {
+            id: self.id,
+            spec: self.spec.clone(),
+            paused: *self.paused_tx.borrow(),
+            created_at: self.created_at,
+            next_run: self.next_run,
+            last_run: self.last_run,
+            jobs: self.jobs.clone(),
+            skipped: self.skipped,
+        }
+    }
+}
+
+/// fires the schedule each time its next run is due, waits while it is paused
+/// returns once the schedule does not fire anymore or got deleted
+async fn run_schedule(
+    schedules: Weak<Mutex<HashMap<u64, Schedule>>>,
+    job_pool: Arc<JobPool>,
+    id: u64,
+    mut paused_rx: watch::Receiver<bool>,
+) {
+    loop {
+        while *paused_rx.borrow() {
+            if paused_rx.changed().await.is_err() {
+                return;
+            }
+        }
+        let next_run = {
+            let schedules = match schedules.upgrade() {
+                Some(schedules) => schedules,
+                None => return,
+            };
+            let schedules = schedules.lock().await;
+            match schedules.get(&id).and_then(|schedule| schedule.next_run) {
+                Some(next_run) => next_run,
+                None => return,
+            }
+        };
+        let delay = next_run
+            .duration_since(SystemTime::now())
+            .unwrap_or_default();
+        tokio::select! {
+            _ = sleep(delay) => {},
+            changed = paused_rx.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                continue;
+            }
+        }
+        match schedules.upgrade() {
+            Some(schedules) => fire(&schedules, &job_pool, id).await,
+            None => return,
+        }
+    }
+}
+
+/// submits the job of a schedule according to its overlap policy and sets its next run
+/// the schedules are not locked while the job pool is asked, so they can be read and changed meanwhile
+async fn fire(schedules: &Mutex<HashMap<u64, Schedule>>, job_pool: &JobPool, id: u64) {
+    let (last_job, mut spec, overlap) = {
+        let mut schedules = schedules.lock().await;
+        let schedule = match schedules.get_mut(&id) {
+            Some(schedule) => schedule,
+            None => return,
+        };
+        let now = SystemTime::now();
+        schedule.last_run = Some(now);
+        schedule.next_run = schedule.cron.as_ref().and_then(|cron| cron.next_after(now));
+        (
+            schedule.jobs.last().copied(),
+            schedule.spec.job.clone(),
+            schedule.spec.overlap,
+        )
+    };
+    let previous = match last_job {
+        Some(job) if is_active(job_pool.status(job).await) => Some(job),
+        _ => None,
+    };
+    if !job_pool.accepting() {
+        info!(
+            "skip firing of schedule {}, job pool does not accept jobs",
+            id
+        );
+        skip(schedules, id).await;
+        return;
+    }
+    match (previous, overlap) {
+        (Some(job), OverlapPolicy::Skip) => {
+            info!("skip firing of schedule {}, job {} still running", id, job);
+            skip(schedules, id).await;
+            return;
+        }
+        (Some(job), OverlapPolicy::Queue) => spec.dependencies.push(Dependency {
+            job,
+            condition: Condition::Always,
+        }),
+        _ => {}
+    }
+    match job_pool.submit_scheduled(spec, id).await {
+        Ok(job) => {
+            info!("schedule {} submitted job {}", id, job);
+            // the job is kept if the schedule was deleted meanwhile
+            if let Some(schedule) = schedules.lock().await.get_mut(&id) {
+                schedule.jobs.push(job);
+            }
+        }
+        Err(err) => error!("schedule {} could not submit its job: {}", id, err),
+    }
+}
+
+/// counts a skipped firing of the schedule, if it still exists
+async fn skip(schedules: &Mutex<HashMap<u64, Schedule>>, id: u64) {
+    if let Some(schedule) = schedules.lock().await.get_mut(&id) {
+        schedule.skipped += 1;
+    }
+}
+
+/// whether a job with the given status did not finish yet, a deleted one has none
+fn is_active(status: Option<JobStatus>) -> bool {
+    matches!(
+        status,
+        Some(
+            JobStatus::Waiting { .. }
+                | JobStatus::Retrying { .. }
+                | JobStatus::Queued { .. }
+                | JobStatus::Running
+                | JobStatus::Stopped
+        )
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::Arc,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use lazy_static::lazy_static;
+
+    use rcmd_data::{JobSpec, JobStatus};
+    use tokio::{runtime::Runtime, time::sleep};
+
+    use super::{fire, OverlapPolicy, SchedulePool, ScheduleSpec, Trigger};
+    use crate::{cron::CronSchedule, job_pool::JobPool};
+
+    lazy_static! {
+        static ref RUNTIME: Runtime = Runtime::new().unwrap();
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    // testing the next times of cron expressions, 1704067200 is monday 2024-01-01 00:00 UTC
+    #[test]
+    fn test_cron() {
+        let cron: CronSchedule = "*/15 8-18 * * mon-fri".parse().unwrap();
+        assert_eq!(
+            Some(at(1704067200 + 8 * 3600)),
+            cron.next_after(at(1704067200))
+        );
+        let next = cron.next_after(at(1704067200 + 8 * 3600)).unwrap();
+        assert_eq!(at(1704067200 + 8 * 3600 + 15 * 60), next);
+        // the day matches by day of month or by day of week
+        let cron: CronSchedule = "0 12 1 * mon".parse().unwrap();
+        assert_eq!(Some(at(1704110400)), cron.next_after(at(1704067200)));
+        assert_eq!(Some(at(1704715200)), cron.next_after(at(1704153600)));
+        let cron: CronSchedule = "0 0 29 feb *".parse().unwrap();
+        assert_eq!(Some(at(1835395200)), cron.next_after(at(1709251200)));
+        let cron: CronSchedule = "0 0 30 2 *".parse().unwrap();
+        assert_eq!(None, cron.next_after(at(1709251200)));
+        let daily: CronSchedule = "@daily".parse().unwrap();
+        assert_eq!(Ok(daily), "0 0 * * *".parse());
+        for invalid in &[
+            "60 * * * *",
+            "* * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "* * * foo *",
+        ] {
+            assert!(invalid.parse::<CronSchedule>().is_err());
+        }
+    }
+
+    // testing a schedule firing once, pausing and deleting schedules
+    #[test]
+    fn test_schedule_at() {
+        let job_pool = Arc::new(JobPool::new());
+        let pool = SchedulePool::new(job_pool.clone());
+        RUNTIME.block_on(async {
+            let spec = ScheduleSpec {
+                job: JobSpec::new("echo", &["scheduled"]),
+                trigger: Trigger::At(SystemTime::now() + Duration::from_millis(200)),
+                overlap: OverlapPolicy::Skip,
+            };
+            let id = pool.create(spec.clone()).await.unwrap();
+            let paused = pool.create(spec).await.unwrap();
+            assert_eq!(Some(()), pool.pause(paused).await);
+            let info = pool.info(id).await.unwrap();
+            assert!(info.jobs.is_empty() && info.next_run.is_some());
+
+            sleep(Duration::from_millis(500)).await;
+            let info = pool.info(id).await.unwrap();
+            assert_eq!(1, info.jobs.len());
+            assert!(info.last_run.is_some() && info.next_run.is_none());
+            let job = job_pool.info(info.jobs[0]).await.unwrap();
+            assert_eq!(Some(id), job.schedule);
+            assert_eq!(
+                "scheduled\n",
+                &job_pool.output(job.id).await.unwrap().stdout()
+            );
+            let info = pool.info(paused).await.unwrap();
+            assert!(info.paused && info.jobs.is_empty());
+
+            // a resumed schedule fires once it is due
+            assert_eq!(Some(()), pool.resume(paused).await);
+            sleep(Duration::from_millis(100)).await;
+            assert_eq!(1, pool.info(paused).await.unwrap().jobs.len());
+            assert_eq!(Some(()), pool.delete(paused).await);
+            assert_eq!(None, pool.delete(paused).await);
+            assert_eq!(1, pool.list().await.len());
+
+            let spec = ScheduleSpec {
+                job: JobSpec::new("echo", &[]),
+                trigger: Trigger::Cron("* * *".to_string()),
+                overlap: OverlapPolicy::Skip,
+            };
+            assert!(pool.create(spec).await.is_err());
+        });
+    }
+
+    // testing the overlap policies with firings while the previous job is still running
+    #[test]
+    fn test_overlap() {
+        let job_pool = Arc::new(JobPool::new());
+        let pool = SchedulePool::new(job_pool.clone());
+        RUNTIME.block_on(async {
+            for overlap in &[
+                OverlapPolicy::Skip,
+                OverlapPolicy::Queue,
+                OverlapPolicy::Allow,
+            ] {
+                let spec = ScheduleSpec {
+                    job: JobSpec::new("sleep", &["5"]),
+                    // yearly, so it only fires when triggered here
+                    trigger: Trigger::Cron("@yearly".to_string()),
+                    overlap: *overlap,
+                };
+                let id = pool.create(spec).await.unwrap();
+                fire(&pool.schedules, &job_pool, id).await;
+                fire(&pool.schedules, &job_pool, id).await;
+                let info = pool.info(id).await.unwrap();
+                assert!(info.last_run.is_some() && info.next_run.is_some());
+                let statuses = {
+                    let mut statuses = Vec::new();
+                    for job in &info.jobs {
+                        statuses.push(job_pool.status(*job).await.unwrap());
+                    }
+                    statuses
+                };
+                match overlap {
+                    OverlapPolicy::Skip => {
+                        assert_eq!(vec![JobStatus::Running], statuses);
+                        assert_eq!(1, info.skipped);
+                    }
+                    OverlapPolicy::Queue => {
+                        let waiting = JobStatus::Waiting {
+                            dependencies: vec![info.jobs[0]],
+                        };
+                        assert_eq!(vec![JobStatus::Running, waiting], statuses);
+                    }
+                    OverlapPolicy::Allow => {
+                        assert_eq!(vec![JobStatus::Running, JobStatus::Running], statuses);
+                    }
+                }
+                for job in &info.jobs {
+                    job_pool.delete(*job).await;
+                }
+            }
+        });
+    }
+
+    // testing that schedules fire no jobs once the job pool stopped accepting them
+    #[test]
+    fn test_not_accepting() {
+        let job_pool = Arc::new(JobPool::new());
+        let pool = SchedulePool::new(job_pool.clone());
+        RUNTIME.block_on(async {
+            let spec = ScheduleSpec {
+                job: JobSpec::new("echo", &["scheduled"]),
+                trigger: Trigger::At(SystemTime::now() + Duration::from_millis(200)),
+                overlap: OverlapPolicy::Allow,
+            };
+            let at = pool.create(spec.clone()).await.unwrap();
+            let cron = pool
+                .create(ScheduleSpec {
+                    trigger: Trigger::Cron("@yearly".to_string()),
+                    ..spec.clone()
+                })
+                .await
+                .unwrap();
+            job_pool.stop_accepting();
+            fire(&pool.schedules, &job_pool, cron).await;
+            sleep(Duration::from_millis(500)).await;
+            for id in &[at, cron] {
+                let info = pool.info(*id).await.unwrap();
+                assert!(info.last_run.is_some() && info.jobs.is_empty());
+                assert_eq!(1, info.skipped);
+            }
+            assert!(job_pool.list().await.is_empty());
+            assert!(job_pool.submit_spec(spec.job).await.is_err());
+        });
+    }
+}
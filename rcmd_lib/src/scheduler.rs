@@ -0,0 +1,229 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use rcmd_data::Priority;
+use tokio::sync::oneshot;
+
+/// hands out run slots to the queued jobs of the pools sharing it
+/// the next slot goes to the pool with the fewest running jobs relative to its weight,
+/// on a tie to the one that started a job least recently (round-robin for equal weights)
+/// within a pool jobs start by priority, equal ones in submission order
+#[derive(Clone, Debug, Default)]
+pub struct Scheduler(Arc<Mutex<State>>);
+
+#[derive(Debug, Default)]
+struct State {
+    // limit of running jobs across all pools
+    max_running: Option<usize>,
+    running: usize,
+    pools: HashMap<String, Share>,
+    waiting: Vec<Waiting>,
+    // increased with every queued and every started job, orders them in time
+    counter: u64,
+}
+
+#[derive(Debug)]
+struct Share {
+    max_running: Option<usize>,
+    weight: u32,
+    running: usize,
+    last_started: u64,
+}
+
+#[derive(Debug)]
+struct Waiting {
+    pool: String,
+    id: u64,
+    priority: Priority,
+    queued: u64,
+    start: oneshot::Sender<RunSlot>,
+}
+
+/// slot of a running job, passed on to the next queued job when dropped
+#[derive(Debug)]
+pub struct RunSlot {
+    scheduler: Scheduler,
+    pool: String,
+}
+
+impl Scheduler {
+    /// scheduler running at most <max_running> jobs at once, unlimited if None
+    pub fn new(max_running: Option<usize>) -> Self {
+        Self(Arc::new(Mutex::new(State {
+            max_running,
+            ..Default::default()
+        })))
+    }
+
+    /// registers a pool running at most <max_running> jobs at once
+    /// <weight> is its share of the slots relative to the other pools
+    pub fn add_pool(&self, pool: &str, max_running: Option<usize>, weight: u32) {
+        let mut state = self.0.lock().unwrap();
+        // keeps the running jobs of a pool registered before
+        let share = state.pools.entry(pool.to_string()).or_insert(Share {
+            max_running,
+            weight,
+            running: 0,
+            last_started: 0,
+        });
+        share.max_running = max_running;
+        share.weight = weight.max(1);
+    }
+
+    /// queues job <id> of <pool>, its slot is sent once it may start (right away if one is free)
+    /// dropping the receiver cancels waiting
+    pub fn enqueue(&self, pool: &str, id: u64, priority: Priority) -> oneshot::Receiver<RunSlot> {
+        let (start, started) = oneshot::channel();
+        {
+            let mut state = self.0.lock().unwrap();
+            state.counter += 1;
+            let waiting = Waiting {
+                pool: pool.to_string(),
+                id,
+                priority,
+                queued: state.counter,
+                start,
+            };
+            state.waiting.push(waiting);
+        }
+        self.dispatch();
+        started
+    }
+
+    /// slot for a job of <pool> that is running already, e.g. one reattached after a restart
+    /// taken even if it exceeds a limit, further jobs are then queued until enough slots are free
+    pub fn claim(&self, pool: &str) -> RunSlot {
+        let mut state = self.0.lock().unwrap();
+        state.running += 1;
+        state.counter += 1;
+        let counter = state.counter;
+        if let Some(share) = state.pools.get_mut(pool) {
+            share.running += 1;
+            share.last_started = counter;
+        }
+        RunSlot {
+            scheduler: self.clone(),
+            pool: pool.to_string(),
+        }
+    }
+
+    /// changes the priority of a queued job, returns false if it is not queued
+    pub fn set_priority(&self, pool: &str, id: u64, priority: Priority) -> bool {
+        let mut state = self.0.lock().unwrap();
+        match state
+            .waiting
+            .iter_mut()
+            .find(|waiting| waiting.pool == pool && waiting.id == id)
+        {
+            Some(waiting) => {
+                waiting.priority = priority;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// hands out free slots to the queued jobs that are next
+    fn dispatch(&self) {
+        let mut starts = Vec::new();
+        {
+            let mut state = self.0.lock().unwrap();
+            state.waiting.retain(|waiting| !waiting.start.is_closed());
+            while let Some(index) = state.next() {
+                let waiting = state.waiting.remove(index);
+                state.running += 1;
+                state.counter += 1;
+                let counter = state.counter;
+                if let Some(share) = state.pools.get_mut(&waiting.pool) {
+                    share.running += 1;
+                    share.last_started = counter;
+                }
+                let slot = RunSlot {
+                    scheduler: self.clone(),
+                    pool: waiting.pool,
+                };
+                starts.push((waiting.start, slot));
+            }
+        }
+        // sent without holding the lock, the slot of a job cancelled meanwhile is dropped and passed on
+        for (start, slot) in starts {
+            let _ = start.send(slot);
+        }
+    }
+}
+
+impl State {
+    /// index of the queued job to start next, None if no slot is free for any of them
+    /// jobs of pools that are not registered (anymore) are skipped until their pool is added again
+    fn next(&self) -> Option<usize> {
+        if let Some(max_running) = self.max_running {
+            if self.running >= max_running {
+                return None;
+            }
+        }
+        self.waiting
+            .iter()
+            .enumerate()
+            .filter_map(|(index, waiting)| {
+                let share = self.pools.get(&waiting.pool)?;
+                match share.max_running {
+                    Some(max) if share.running >= max => None,
+                    _ => Some((index, waiting, share)),
+                }
+            })
+            .min_by(|(_, a, share_a), (_, b, share_b)| {
+                // running / weight, compared without division
+                let load_a = share_a.running as u64 * share_b.weight as u64;
+                let load_b = share_b.running as u64 * share_a.weight as u64;
+                load_a
+                    .cmp(&load_b)
+                    .then(share_a.last_started.cmp(&share_b.last_started))
+                    .then(b.priority.cmp(&a.priority))
+                    .then(a.queued.cmp(&b.queued))
+            })
+            .map(|(index, _, _)| index)
+    }
+}
+
+impl Drop for RunSlot {
+    fn drop(&mut self) {
+        {
+            let mut state = self.scheduler.0.lock().unwrap();
+            state.running -= 1;
+            if let Some(share) = state.pools.get_mut(&self.pool) {
+                share.running -= 1;
+            }
+        }
+        self.scheduler.dispatch();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rcmd_data::Priority;
+
+    use super::Scheduler;
+
+    // testing that jobs of a pool that is not registered wait instead of breaking dispatch
+    #[test]
+    fn test_unregistered_pool() {
+        let scheduler = Scheduler::new(Some(1));
+        scheduler.add_pool("known", None, 1);
+        let mut unknown = scheduler.enqueue("unknown", 0, Priority::High);
+        let mut known = scheduler.enqueue("known", 0, Priority::Normal);
+        assert!(unknown.try_recv().is_err());
+        let slot = known.try_recv().unwrap();
+
+        let mut next = scheduler.enqueue("known", 1, Priority::Normal);
+        drop(slot);
+        let slot = next.try_recv().unwrap();
+        assert!(unknown.try_recv().is_err());
+
+        // started once its pool is registered and a slot is free
+        scheduler.add_pool("unknown", None, 1);
+        drop(slot);
+        assert!(unknown.try_recv().is_ok());
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::{io::AsRawFd, process::ExitStatusExt},
+    path::{Path, PathBuf},
+    process::{self, ExitStatus, Stdio},
+    sync::Arc,
+    time::Duration,
+};
+
+use rcmd_data::{JobSpec, OutputStream, ResourceUsage, StdinMode};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::{Child, Command},
+    sync::{mpsc, watch},
+    time::sleep,
+};
+use tracing::info;
+
+use crate::{
+    cgroup::Cgroup,
+    spool::{JobSpool, SpoolLimits},
+    util,
+};
+
+/// how often the server checks for output and the exit of a job run by a shim
+const SHIM_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// everything a shim needs to run a job, passed to it as a file next to the job's spool files
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShimJob {
+    pub spec: JobSpec,
+    pub spool_dir: PathBuf,
+    pub spool_name: String,
+    pub max_job_output: Option<u64>,
+    /// cgroup the job's process joins, if it has resource limits
+    pub cgroup: Option<PathBuf>,
+}
+
+/// how the job's process exited, recorded by the shim once all of its output was stored
+#[derive(Debug, Serialize, Deserialize)]
+struct ShimExit {
+    /// raw wait status, or why the process could not be waited for
+    status: Result<i32, String>,
+    usage: Option<ResourceUsage>,
+}
+
+/// job process run by a shim, as seen from the server
+/// the shim runs in its own session, so it outlives the server and can be reattached to
+pub struct Shim {
+    pid: u32,
+    // set if this server process started the shim, so it has to reap it
+    process: Option<Child>,
+    spool: Arc<JobSpool>,
+    exit: Option<ShimExit>,
+}
+
+impl Shim {
+    /// starts the shim binary at <path> running <job>, returns it along with the pid of the job's process
+    /// the shim writes the output to <spool>, which the job was created with
+    pub async fn start(
+        path: &Path,
+        job: &ShimJob,
+        spool: Arc<JobSpool>,
+    ) -> Result<(Self, Option<u32>), String> {
+        let job_file = spool.shim_file("job");
+        let json = serde_json::to_vec(job).map_err(|err| err.to_string())?;
+        fs::write(&job_file, json).map_err(|err| format!("could not write job file: {}", err))?;
+        let mut command = Command::new(path);
+        command
+            .arg(&job_file)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        // not stopped along with the server, e.g. by signals sent to the terminal's process group
+        unsafe {
+            command.pre_exec(|| match libc::setsid() {
+                -1 => Err(io::Error::last_os_error()),
+                _ => Ok(()),
+            });
+        }
+        let mut process = command
+            .spawn()
+            .map_err(|err| format!("could not start shim {:?}: {}", path, err))?;
+        let pid = process.id().expect("process was not waited for yet");
+        // the shim reports the pid of the job's process, or why it could not be started
+        let mut line = String::new();
+        let mut stdout = BufReader::new(process.stdout.take().unwrap());
+        if let Err(err) = stdout.read_line(&mut line).await {
+            return Err(format!("could not read from shim: {}", err));
+        }
+        let line = line.trim();
+        if let Some(err) = line.strip_prefix("error: ") {
+            return Err(err.to_string());
+        }
+        let job_pid = line
+            .parse()
+            .map_err(|_| "shim exited without starting the job".to_string())?;
+        info!("shim {} started job process {}", pid, job_pid);
+        let shim = Self {
+            pid,
+            process: Some(process),
+            spool,
+            exit: None,
+        };
+        Ok((shim, Some(job_pid)))
+    }
+
+    /// shim with the given pid started by an earlier server process, writing to <spool>
+    pub fn reattach(pid: u32, spool: Arc<JobSpool>) -> Self {
+        Self {
+            pid,
+            process: None,
+            spool,
+            exit: None,
+        }
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// waits until the shim recorded the exit of the job, notifies about output in the meantime
+    /// returns the same status once it exited
+    pub async fn wait(&mut self, output_changed: &watch::Sender<()>) -> io::Result<ExitStatus> {
+        loop {
+            if let Some(exit) = &self.exit {
+                return match &exit.status {
+                    Ok(status) => Ok(ExitStatus::from_raw(*status)),
+                    Err(msg) => Err(io::Error::other(msg.clone())),
+                };
+            }
+            // checked before looking for the exit, which is recorded before the shim exits
+            let alive = self.alive();
+            if self.spool.refresh() {
+                let _ = output_changed.send(());
+            }
+            match fs::read(self.spool.shim_file("exit")) {
+                Ok(json) => {
+                    self.exit = Some(serde_json::from_slice(&json)?);
+                    continue;
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+            if !alive {
+                return Err(io::Error::other(format!(
+                    "shim {} exited without recording the exit of the job",
+                    self.pid
+                )));
+            }
+            sleep(SHIM_POLL_INTERVAL).await;
+        }
+    }
+
+    /// resource usage of the job's process, once it exited
+    pub fn usage(&self) -> Option<ResourceUsage> {
+        self.exit.as_ref()?.usage.clone()
+    }
+
+    fn alive(&mut self) -> bool {
+        if let Some(process) = &mut self.process {
+            return matches!(process.try_wait(), Ok(None));
+        }
+        // the pid may belong to another process by now, the lock is released only once the shim exited
+        match File::open(self.spool.shim_file("lock")) {
+            Ok(lock) => {
+                let locked =
+                    unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_SH | libc::LOCK_NB) };
+                locked != 0 && io::Error::last_os_error().raw_os_error() == Some(libc::EWOULDBLOCK)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => false,
+            // signal 0 only checks for existence (and permission)
+            Err(_) => unsafe { libc::kill(self.pid as libc::pid_t, 0) == 0 },
+        }
+    }
+}
+
+/// creates and locks the lock file at <path>, the lock is held until the file is closed,
+/// at the latest when the process exits
+fn lock(path: &Path) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+/// reads the job file of the shim writing to <spool>
+pub fn read_job(spool: &JobSpool) -> io::Result<ShimJob> {
+    let json = fs::read(spool.shim_file("job"))?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+/// entry point of the rcmd_shim binary, runs the job described in the file given as only argument
+/// reports the pid of the job's process on stdout (or a line starting with "error: "),
+/// then writes its output to the spool files and records its exit next to them
+pub fn main() {
+    let job_file = match env::args_os().nth(1) {
+        Some(job_file) => PathBuf::from(job_file),
+        None => {
+            eprintln!("usage: rcmd_shim <job file>");
+            process::exit(2);
+        }
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("could not create runtime");
+    if let Err(err) = runtime.block_on(run(&job_file)) {
+        report(&format!("error: {}", err));
+        process::exit(1);
+    }
+}
+
+/// writes a line to the server, which stops reading once the job started or could not be started
+fn report(line: &str) {
+    let _ = writeln!(io::stdout(), "{}", line);
+}
+
+async fn run(job_file: &Path) -> Result<(), String> {
+    let json = fs::read(job_file).map_err(|err| format!("could not read job file: {}", err))?;
+    let job: ShimJob =
+        serde_json::from_slice(&json).map_err(|err| format!("invalid job file: {}", err))?;
+    // other jobs' output is not known here, so the cap for all spool files is not applied
+    let limits = SpoolLimits {
+        max_job_output: job.max_job_output,
+        max_spool_size: None,
+        usage: Default::default(),
+    };
+    let spool = JobSpool::append(&job.spool_dir, &job.spool_name, limits)
+        .map_err(|err| format!("could not open spool files: {}", err))?;
+    let exit_file = spool.shim_file("exit");
+    // held until the shim exits, tells a restarted server whether the shim still runs
+    let _lock = lock(&spool.shim_file("lock"))
+        .map_err(|err| format!("could not lock spool files: {}", err))?;
+    let stdout = spool
+        .writer(OutputStream::Stdout)
+        .map_err(|e| e.to_string())?;
+    let stderr = spool
+        .writer(OutputStream::Stderr)
+        .map_err(|e| e.to_string())?;
+    let cgroup_procs = match &job.cgroup {
+        Some(path) => {
+            let cgroup = Cgroup::open(path)
+                .map_err(|err| format!("could not set up resource limits: {}", err))?;
+            Some(cgroup.procs_path().to_owned())
+        }
+        None => None,
+    };
+    let stdin = match &job.spec.stdin {
+        StdinMode::Data(data) => {
+            // sender is dropped right away, so stdin is closed after the data
+            let (stdin_tx, stdin_rx) = mpsc::unbounded_channel();
+            let _ = stdin_tx.send(data.clone());
+            Some(stdin_rx)
+        }
+        // piped input has to be written by the server, such jobs are not run by a shim
+        StdinMode::Null | StdinMode::Pipe => None,
+    };
+    let process =
+        util::spawn_job_process(&job.spec, cgroup_procs).map_err(|err| err.to_string())?;
+    report(&process.pid.to_string());
+    let (status, usage) = util::run_to_end(process, stdin, stdout, stderr).await;
+    let exit = ShimExit {
+        status: status
+            .map(|status| status.into_raw())
+            .map_err(|err| err.to_string()),
+        usage,
+    };
+    // replaced at once, so the server never reads a partial record
+    let tmp = exit_file.with_extension("exit.tmp");
+    let json = serde_json::to_vec(&exit).map_err(|err| err.to_string())?;
+    fs::write(&tmp, json)
+        .and_then(|_| fs::rename(&tmp, &exit_file))
+        .map_err(|err| format!("could not record exit: {}", err))
+}
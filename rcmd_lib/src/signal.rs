@@ -0,0 +1,58 @@
+/// signals that can be referred to by name, without the SIG prefix
+const SIGNALS: &[(&str, libc::c_int)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP),
+    ("ABRT", libc::SIGABRT),
+    ("BUS", libc::SIGBUS),
+    ("FPE", libc::SIGFPE),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("URG", libc::SIGURG),
+    ("XCPU", libc::SIGXCPU),
+    ("XFSZ", libc::SIGXFSZ),
+    ("VTALRM", libc::SIGVTALRM),
+    ("PROF", libc::SIGPROF),
+    ("WINCH", libc::SIGWINCH),
+    ("IO", libc::SIGIO),
+    ("SYS", libc::SIGSYS),
+];
+
+/// parses a signal given by name (with or without SIG prefix, case insensitive) or number
+pub fn parse(signal: &str) -> Result<libc::c_int, String> {
+    let signal = signal.trim();
+    if let Ok(number) = signal.parse::<libc::c_int>() {
+        return match SIGNALS.iter().find(|(_, n)| *n == number) {
+            Some((_, number)) => Ok(*number),
+            None => Err(format!("unknown signal number: {}", number)),
+        };
+    }
+    let upper = signal.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    SIGNALS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, number)| *number)
+        .ok_or(format!("unknown signal: {}", signal))
+}
+
+/// name of a signal including SIG prefix, e.g. SIGTERM
+pub fn name(signal: libc::c_int) -> String {
+    match SIGNALS.iter().find(|(_, n)| *n == signal) {
+        Some((name, _)) => format!("SIG{}", name),
+        None => format!("signal {}", signal),
+    }
+}
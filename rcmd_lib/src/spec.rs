@@ -0,0 +1,150 @@
+use std::{collections::HashMap, fs, time::Duration};
+
+use rcmd_data::{JobSpec, ResourceLimits, StdinMode, Workflow};
+
+use crate::job_pool::JobPoolConfig;
+
+/// longest timeout a job may set even if its pool has no maximum, longer ones are most likely mistakes
+const TIMEOUT_LIMIT: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// checks all values of a job spec that can be checked before spawning its process
+pub fn validate(spec: &JobSpec, config: &JobPoolConfig) -> Result<(), String> {
+    if spec.command.is_empty() {
+        return Err("command must not be empty".to_string());
+    }
+    if spec.command.contains('\0') || spec.arguments.iter().any(|a| a.contains('\0')) {
+        return Err("command and arguments must not contain NUL bytes".to_string());
+    }
+    for (key, value) in &spec.env {
+        if key.is_empty() || key.contains('=') || key.contains('\0') {
+            return Err(format!("invalid environment variable name: {:?}", key));
+        }
+        if value.contains('\0') {
+            return Err(format!(
+                "value of environment variable {} must not contain NUL bytes",
+                key
+            ));
+        }
+    }
+    if let Some(working_dir) = &spec.working_dir {
+        match fs::metadata(working_dir) {
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(_) => {
+                return Err(format!(
+                    "working directory {:?} is not a directory",
+                    working_dir
+                ))
+            }
+            Err(err) => {
+                return Err(format!(
+                    "working directory {:?} is not accessible: {}",
+                    working_dir, err
+                ))
+            }
+        }
+    }
+    if let Some(umask) = spec.umask {
+        if umask > 0o777 {
+            return Err(format!("invalid umask: {:o}", umask));
+        }
+    }
+    if let Some(limits) = &spec.limits {
+        validate_limits(limits, config)?;
+    }
+    if let Some(timeout) = spec.timeout {
+        if timeout.is_zero() {
+            return Err("timeout must be greater than 0".to_string());
+        }
+        if timeout > TIMEOUT_LIMIT {
+            return Err(format!(
+                "timeout of {:?} exceeds the limit of {:?}",
+                timeout, TIMEOUT_LIMIT
+            ));
+        }
+        if let Some(max_timeout) = config.max_timeout {
+            if timeout > max_timeout {
+                return Err(format!(
+                    "timeout of {:?} exceeds the maximum of {:?}",
+                    timeout, max_timeout
+                ));
+            }
+        }
+    }
+    if let Some(retry) = &spec.retry {
+        if retry.max_attempts == 0 {
+            return Err("maximum number of attempts must be at least 1".to_string());
+        }
+        // input written to one attempt could not be replayed to the next
+        if spec.stdin == StdinMode::Pipe {
+            return Err("jobs with piped stdin can not be retried".to_string());
+        }
+    }
+    Ok(())
+}
+
+fn validate_limits(limits: &ResourceLimits, config: &JobPoolConfig) -> Result<(), String> {
+    if config.cgroup_parent.is_none() {
+        return Err("resource limits requested, but no cgroup parent configured".to_string());
+    }
+    if let Some(weight) = limits.cpu_weight {
+        if !(1..=10000).contains(&weight) {
+            return Err(format!(
+                "cpu weight must be in range 1..=10000, got {}",
+                weight
+            ));
+        }
+    }
+    if limits.cpu_quota_us == Some(0) || limits.cpu_period_us == Some(0) {
+        return Err("cpu quota and period must be greater than 0".to_string());
+    }
+    if limits.pids_max == Some(0) {
+        return Err("pids max must be greater than 0".to_string());
+    }
+    Ok(())
+}
+
+/// order in which the jobs of a workflow can be submitted, each one after the jobs it depends on
+/// fails on empty workflows, duplicate or unknown job names and cyclic dependencies
+pub fn workflow_order(workflow: &Workflow) -> Result<Vec<usize>, String> {
+    if workflow.jobs.is_empty() {
+        return Err("workflow contains no jobs".to_string());
+    }
+    let mut indices = HashMap::new();
+    for (index, job) in workflow.jobs.iter().enumerate() {
+        if indices.insert(job.name.as_str(), index).is_some() {
+            return Err(format!("duplicate job name in workflow: {}", job.name));
+        }
+    }
+    let mut dependencies = Vec::new();
+    for job in &workflow.jobs {
+        let mut after = Vec::new();
+        for dependency in &job.after {
+            match indices.get(dependency.job.as_str()) {
+                Some(index) => after.push(*index),
+                None => {
+                    return Err(format!(
+                        "job {} depends on unknown job {}",
+                        job.name, dependency.job
+                    ))
+                }
+            }
+        }
+        dependencies.push(after);
+    }
+    // keeps the given order as far as the dependencies allow
+    let mut submitted = vec![false; workflow.jobs.len()];
+    let mut order = Vec::new();
+    while order.len() < workflow.jobs.len() {
+        let next = (0..workflow.jobs.len()).find(|&index| {
+            !submitted[index] && dependencies[index].iter().all(|&after| submitted[after])
+        });
+        match next {
+            Some(index) => {
+                submitted[index] = true;
+                order.push(index);
+            }
+            None => return Err("dependencies of the workflow contain a cycle".to_string()),
+        }
+    }
+    Ok(order)
+}
@@ -0,0 +1,772 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rcmd_data::{OutputChunk, OutputLine, OutputRange, OutputStream};
+use tokio::io::AsyncWriteExt;
+use tracing::{error, info};
+
+/// written once to a stream in place of the output that exceeded a disk cap
+pub const TRUNCATION_MARKER: &str = "###OUTPUT TRUNCATED###\n";
+
+/// written in place of the rest of a stream read at once that exceeds MAX_READ_SIZE
+pub const READ_LIMIT_MARKER: &str = "###OUTPUT CUT, REQUEST A RANGE FOR THE REST###\n";
+
+/// most bytes of output read into memory for a single request, the rest has to be requested as a range
+pub const MAX_READ_SIZE: u64 = 16 * 1024 * 1024;
+
+/// block size when scanning spool files for line breaks or reading them line by line
+const SCAN_BLOCK_SIZE: u64 = 64 * 1024;
+
+/// index records hold offset (u64), length (u32) and time read in microseconds since epoch (u64)
+const INDEX_RECORD_SIZE: usize = 20;
+
+/// extensions of the files a job's shim keeps next to the spool files, removed along with them
+const SHIM_EXTENSIONS: [&str; 3] = ["job", "exit", "lock"];
+
+/// makes spool file names unique among pools of the same name and processes sharing a spool directory
+static NEXT_SPOOL_ID: AtomicU64 = AtomicU64::new(0);
+
+/// disk caps for spool files
+#[derive(Clone, Debug)]
+pub struct SpoolLimits {
+    /// maximum bytes of stdout and stderr of a single job
+    pub max_job_output: Option<u64>,
+    /// maximum bytes of all spool files accounted in <usage>
+    pub max_spool_size: Option<u64>,
+    /// bytes currently used by spool files
+    pub usage: Arc<AtomicU64>,
+}
+
+/// spool file holding the output of one stream
+/// along with an index file recording when each line (or part of a line) was read
+#[derive(Debug)]
+struct SpoolFile {
+    path: PathBuf,
+    index_path: PathBuf,
+    /// bytes completely written, only these are visible to readers
+    size: AtomicU64,
+    index_size: AtomicU64,
+    truncated: AtomicBool,
+}
+
+/// stdout and stderr of a job stored in a spool directory, the files are removed when dropped unless kept
+#[derive(Debug)]
+pub struct JobSpool {
+    dir: PathBuf,
+    /// common prefix of the file names
+    name: String,
+    keep: AtomicBool,
+    stdout: SpoolFile,
+    stderr: SpoolFile,
+    /// bytes written to both files
+    used: AtomicU64,
+    limits: SpoolLimits,
+}
+
+/// appends output of one stream to a job's spool file
+pub struct SpoolWriter {
+    spool: Arc<JobSpool>,
+    stream: OutputStream,
+    file: tokio::fs::File,
+    index: tokio::fs::File,
+}
+
+impl JobSpool {
+    /// creates empty spool files <dir>/<name>-<unique suffix>.stdout/.stderr and their indexes
+    pub fn create(dir: &Path, name: &str, limits: SpoolLimits) -> io::Result<Arc<Self>> {
+        fs::create_dir_all(dir)?;
+        let unique = format!(
+            "{}-{}-{}",
+            name,
+            process::id(),
+            NEXT_SPOOL_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        let stdout = SpoolFile::create(dir, &format!("{}.stdout", unique))?;
+        let stderr = match SpoolFile::create(dir, &format!("{}.stderr", unique)) {
+            Ok(stderr) => stderr,
+            Err(err) => {
+                stdout.remove();
+                return Err(err);
+            }
+        };
+        Ok(Arc::new(Self {
+            dir: dir.to_owned(),
+            name: unique,
+            keep: AtomicBool::new(false),
+            stdout,
+            stderr,
+            used: AtomicU64::new(0),
+            limits,
+        }))
+    }
+
+    /// opens the existing spool files <dir>/<name>.stdout/.stderr for reading, e.g. after a restart
+    /// no further output can be written to them, they are kept when dropped until told otherwise
+    pub fn open(dir: &Path, name: &str, limits: SpoolLimits) -> io::Result<Arc<Self>> {
+        Self::reopen(dir, name, limits, false)
+    }
+
+    /// opens the existing spool files <dir>/<name>.stdout/.stderr for appending, e.g. by a job's shim
+    /// they are kept when dropped
+    pub fn append(dir: &Path, name: &str, limits: SpoolLimits) -> io::Result<Arc<Self>> {
+        Self::reopen(dir, name, limits, true)
+    }
+
+    fn reopen(
+        dir: &Path,
+        name: &str,
+        limits: SpoolLimits,
+        writable: bool,
+    ) -> io::Result<Arc<Self>> {
+        let stdout = SpoolFile::open(dir, &format!("{}.stdout", name), writable)?;
+        let stderr = SpoolFile::open(dir, &format!("{}.stderr", name), writable)?;
+        let used = stdout.size.load(Ordering::Relaxed) + stderr.size.load(Ordering::Relaxed);
+        limits.usage.fetch_add(used, Ordering::Relaxed);
+        Ok(Arc::new(Self {
+            dir: dir.to_owned(),
+            name: name.to_string(),
+            keep: AtomicBool::new(true),
+            stdout,
+            stderr,
+            used: AtomicU64::new(used),
+            limits,
+        }))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// name to open the spool files with again
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// whether the files are kept when the spool is dropped, so they can be opened again
+    pub fn keep_files(&self, keep: bool) {
+        self.keep.store(keep, Ordering::Relaxed);
+    }
+
+    /// path of the shim file with the given extension, named like the spool files
+    pub fn shim_file(&self, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", self.name, extension))
+    }
+
+    /// makes output another process appended to the files visible, e.g. the shim of the job
+    /// returns whether there was any
+    pub fn refresh(&self) -> bool {
+        let mut grown = false;
+        for file in [&self.stdout, &self.stderr] {
+            let (size, index_size) = match file.sizes_on_disk() {
+                Ok(sizes) => sizes,
+                Err(err) => {
+                    error!("could not read size of spool file {:?}: {}", file.path, err);
+                    continue;
+                }
+            };
+            let previous = file.size.fetch_max(size, Ordering::AcqRel);
+            if size > previous {
+                self.account(size - previous);
+                grown = true;
+            }
+            file.index_size.fetch_max(index_size, Ordering::AcqRel);
+        }
+        grown
+    }
+
+    /// opens the spool file of the given stream for appending
+    /// there must be at most one writer per stream
+    pub fn writer(self: &Arc<Self>, stream: OutputStream) -> io::Result<SpoolWriter> {
+        let open = |path| OpenOptions::new().append(true).open(path);
+        let file = self.file(stream);
+        Ok(SpoolWriter {
+            spool: self.clone(),
+            stream,
+            file: tokio::fs::File::from_std(open(&file.path)?),
+            index: tokio::fs::File::from_std(open(&file.index_path)?),
+        })
+    }
+
+    /// bytes of the given stream visible to readers
+    pub fn size(&self, stream: OutputStream) -> u64 {
+        self.file(stream).size.load(Ordering::Acquire)
+    }
+
+    /// reads the given stream starting at byte offset <from>, at most MAX_READ_SIZE bytes
+    /// returns the data and the offset after it
+    pub fn read_from(&self, stream: OutputStream, from: u64) -> io::Result<(Vec<u8>, u64)> {
+        let size = self.size(stream);
+        let file = File::open(&self.file(stream).path)?;
+        let data = read_range(&file, from, size.min(from.saturating_add(MAX_READ_SIZE)))?;
+        let next = from + data.len() as u64;
+        Ok((data, next))
+    }
+
+    /// the selected part of the given stream, at most MAX_READ_SIZE bytes of it
+    pub fn chunk(&self, stream: OutputStream, range: OutputRange) -> io::Result<OutputChunk> {
+        let size = self.size(stream);
+        let (start, end) = match range {
+            OutputRange::Bytes { from, limit } => {
+                let start = from.min(size);
+                let end = limit.map_or(size, |limit| start.saturating_add(limit));
+                (start, end.min(size))
+            }
+            OutputRange::Head(n) => (0, self.head_end(stream, n, size)?),
+            OutputRange::Tail(n) => (self.tail_start(stream, n, size)?, size),
+        };
+        let end = end.min(start.saturating_add(MAX_READ_SIZE));
+        let file = File::open(&self.file(stream).path)?;
+        let data = read_range(&file, start, end)?;
+        Ok(OutputChunk {
+            offset: start,
+            next_offset: start + data.len() as u64,
+            size,
+            data,
+        })
+    }
+
+    /// output of both streams in the order it was read, split after line breaks
+    /// lines beyond MAX_READ_SIZE bytes are replaced by a single line with READ_LIMIT_MARKER
+    pub fn combined(&self) -> io::Result<Vec<OutputLine>> {
+        let mut stdout = self.indexed_lines(OutputStream::Stdout)?;
+        let mut stderr = self.indexed_lines(OutputStream::Stderr)?;
+        let (mut next_out, mut next_err) = (stdout.next()?, stderr.next()?);
+        let mut lines = Vec::new();
+        let mut read = 0;
+        // both are ordered by time already
+        loop {
+            let (reader, next) = match (&next_out, &next_err) {
+                (Some(out), Some(err)) if err.timestamp < out.timestamp => {
+                    (&mut stderr, &mut next_err)
+                }
+                (Some(_), _) => (&mut stdout, &mut next_out),
+                (None, Some(_)) => (&mut stderr, &mut next_err),
+                (None, None) => return Ok(lines),
+            };
+            let mut line = next.take().unwrap();
+            read += line.len;
+            if read > MAX_READ_SIZE {
+                line.data = READ_LIMIT_MARKER.as_bytes().to_vec();
+                lines.push(line.into());
+                return Ok(lines);
+            }
+            line.data = reader.data(line.offset, line.len)?;
+            lines.push(line.into());
+            *next = reader.next()?;
+        }
+    }
+
+    /// lines of the given stream as recorded in its index, read block by block
+    fn indexed_lines(&self, stream: OutputStream) -> io::Result<IndexedLines> {
+        let file = self.file(stream);
+        Ok(IndexedLines {
+            stream,
+            index: File::open(&file.index_path)?,
+            // the index is written after the data, so all indexed data can be read
+            index_end: file.index_size.load(Ordering::Acquire),
+            index_pos: 0,
+            records: Vec::new(),
+            record: 0,
+            data: File::open(&file.path)?,
+            size: self.size(stream),
+            block: Vec::new(),
+            block_start: 0,
+        })
+    }
+
+    /// offset after the first n lines
+    fn head_end(&self, stream: OutputStream, n: usize, size: u64) -> io::Result<u64> {
+        if n == 0 {
+            return Ok(0);
+        }
+        let file = File::open(&self.file(stream).path)?;
+        let mut found = 0;
+        let mut pos = 0;
+        while pos < size {
+            let block = read_range(&file, pos, (pos + SCAN_BLOCK_SIZE).min(size))?;
+            for (i, _) in block.iter().enumerate().filter(|(_, b)| **b == b'\n') {
+                found += 1;
+                if found == n {
+                    return Ok(pos + i as u64 + 1);
+                }
+            }
+            pos += block.len() as u64;
+        }
+        Ok(size)
+    }
+
+    /// offset of the first of the last n lines, a last line without line break counts as well
+    fn tail_start(&self, stream: OutputStream, n: usize, size: u64) -> io::Result<u64> {
+        if n == 0 {
+            return Ok(size);
+        }
+        let file = File::open(&self.file(stream).path)?;
+        let mut found = 0;
+        // the line break ending the stream does not start another line
+        let mut end = size.saturating_sub(1);
+        while end > 0 {
+            let start = end.saturating_sub(SCAN_BLOCK_SIZE);
+            let block = read_range(&file, start, end)?;
+            for (i, _) in block.iter().enumerate().rev().filter(|(_, b)| **b == b'\n') {
+                found += 1;
+                if found == n {
+                    return Ok(start + i as u64 + 1);
+                }
+            }
+            end = start;
+        }
+        Ok(0)
+    }
+
+    fn file(&self, stream: OutputStream) -> &SpoolFile {
+        match stream {
+            OutputStream::Stdout => &self.stdout,
+            OutputStream::Stderr => &self.stderr,
+        }
+    }
+
+    /// reserves space for <len> more bytes, fails if a cap would be exceeded
+    fn reserve(&self, len: u64) -> bool {
+        let limits = &self.limits;
+        let used = self.used.load(Ordering::Relaxed);
+        if matches!(limits.max_job_output, Some(max) if used + len > max) {
+            return false;
+        }
+        let total = limits.usage.fetch_add(len, Ordering::Relaxed);
+        if matches!(limits.max_spool_size, Some(max) if total + len > max) {
+            limits.usage.fetch_sub(len, Ordering::Relaxed);
+            return false;
+        }
+        self.used.fetch_add(len, Ordering::Relaxed);
+        true
+    }
+
+    /// accounts <len> more bytes regardless of the caps
+    fn account(&self, len: u64) {
+        self.used.fetch_add(len, Ordering::Relaxed);
+        self.limits.usage.fetch_add(len, Ordering::Relaxed);
+    }
+
+    fn release(&self, len: u64) {
+        self.used.fetch_sub(len, Ordering::Relaxed);
+        self.limits.usage.fetch_sub(len, Ordering::Relaxed);
+    }
+}
+
+/// reads the lines of a stream along with the index recording them
+struct IndexedLines {
+    stream: OutputStream,
+    index: File,
+    index_end: u64,
+    index_pos: u64,
+    // block of index records read last and the next one to return
+    records: Vec<u8>,
+    record: usize,
+    data: File,
+    size: u64,
+    // block of data read last, starting at <block_start>
+    block: Vec<u8>,
+    block_start: u64,
+}
+
+/// line as recorded in the index, its data is read separately
+struct IndexedLine {
+    stream: OutputStream,
+    offset: u64,
+    len: u64,
+    timestamp: SystemTime,
+    data: Vec<u8>,
+}
+
+impl IndexedLines {
+    /// next line of the index without its data, None after the last one
+    fn next(&mut self) -> io::Result<Option<IndexedLine>> {
+        if self.record + INDEX_RECORD_SIZE > self.records.len() {
+            // whole records only, the index size excludes an incomplete one at the end
+            let block_size = SCAN_BLOCK_SIZE - SCAN_BLOCK_SIZE % INDEX_RECORD_SIZE as u64;
+            let end = self.index_end.min(self.index_pos + block_size);
+            self.records = read_range(&self.index, self.index_pos, end)?;
+            self.index_pos += self.records.len() as u64;
+            self.record = 0;
+            if self.records.len() < INDEX_RECORD_SIZE {
+                return Ok(None);
+            }
+        }
+        let record = &self.records[self.record..self.record + INDEX_RECORD_SIZE];
+        self.record += INDEX_RECORD_SIZE;
+        let field = |from: usize, to: usize| {
+            let mut bytes = [0; 8];
+            bytes[..to - from].copy_from_slice(&record[from..to]);
+            u64::from_le_bytes(bytes)
+        };
+        Ok(Some(IndexedLine {
+            stream: self.stream,
+            offset: field(0, 8),
+            len: field(8, 12),
+            timestamp: UNIX_EPOCH + Duration::from_micros(field(12, 20)),
+            data: Vec::new(),
+        }))
+    }
+
+    /// data of the line at <offset>, lines are read in order so whole blocks are read at once
+    fn data(&mut self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        let end = offset.saturating_add(len).min(self.size);
+        let block_end = self.block_start + self.block.len() as u64;
+        if offset < self.block_start || end > block_end {
+            self.block_start = offset;
+            let block_end = end
+                .max(offset.saturating_add(SCAN_BLOCK_SIZE))
+                .min(self.size);
+            self.block = read_range(&self.data, offset, block_end)?;
+        }
+        let from = (offset - self.block_start) as usize;
+        let to = (end.max(offset) - self.block_start) as usize;
+        Ok(self.block.get(from..to).unwrap_or_default().to_vec())
+    }
+}
+
+impl From<IndexedLine> for OutputLine {
+    fn from(line: IndexedLine) -> Self {
+        OutputLine {
+            stream: line.stream,
+            timestamp: line.timestamp,
+            data: line.data,
+        }
+    }
+}
+
+impl Drop for JobSpool {
+    fn drop(&mut self) {
+        if !self.keep.load(Ordering::Relaxed) {
+            self.stdout.remove();
+            self.stderr.remove();
+            for extension in SHIM_EXTENSIONS {
+                let path = self.shim_file(extension);
+                match fs::remove_file(&path) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                        error!("could not remove shim file {:?}: {}", path, err)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.limits
+            .usage
+            .fetch_sub(self.used.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+}
+
+impl SpoolFile {
+    fn create(dir: &Path, name: &str) -> io::Result<Self> {
+        let path = dir.join(name);
+        let index_path = dir.join(format!("{}.index", name));
+        File::create(&path)?;
+        if let Err(err) = File::create(&index_path) {
+            let _ = fs::remove_file(&path);
+            return Err(err);
+        }
+        Ok(Self {
+            path,
+            index_path,
+            size: AtomicU64::new(0),
+            index_size: AtomicU64::new(0),
+            truncated: AtomicBool::new(false),
+        })
+    }
+
+    /// opens existing files, an incomplete index record at the end (written when the server died) is ignored
+    fn open(dir: &Path, name: &str, writable: bool) -> io::Result<Self> {
+        let mut file = Self {
+            path: dir.join(name),
+            index_path: dir.join(format!("{}.index", name)),
+            size: AtomicU64::new(0),
+            index_size: AtomicU64::new(0),
+            truncated: AtomicBool::new(!writable),
+        };
+        let (size, index_size) = file.sizes_on_disk()?;
+        file.size = AtomicU64::new(size);
+        file.index_size = AtomicU64::new(index_size);
+        Ok(file)
+    }
+
+    /// sizes of the data and index file, without an incomplete index record at the end
+    fn sizes_on_disk(&self) -> io::Result<(u64, u64)> {
+        // the index is written after the data, so read first it never refers to data beyond the size
+        let index_size = fs::metadata(&self.index_path)?.len();
+        let size = fs::metadata(&self.path)?.len();
+        Ok((size, index_size - index_size % INDEX_RECORD_SIZE as u64))
+    }
+
+    fn remove(&self) {
+        for path in [&self.path, &self.index_path] {
+            if let Err(err) = fs::remove_file(path) {
+                error!("could not remove spool file {:?}: {}", path, err);
+            }
+        }
+    }
+}
+
+impl SpoolWriter {
+    /// appends data read just now, which becomes visible to readers once completely written
+    /// once a disk cap is hit all further data is dropped and the truncation marker is written instead
+    pub async fn write(&mut self, data: &[u8]) {
+        let timestamp = SystemTime::now();
+        let file = self.spool.file(self.stream);
+        if file.truncated.load(Ordering::Relaxed) {
+            return;
+        }
+        let data = if self.spool.reserve(data.len() as u64) {
+            data
+        } else {
+            info!("disk cap for output reached, truncating {:?}", file.path);
+            file.truncated.store(true, Ordering::Relaxed);
+            // the marker is written even if it exceeds the cap
+            let marker = TRUNCATION_MARKER.as_bytes();
+            self.spool.account(marker.len() as u64);
+            marker
+        };
+        if let Err(err) = write_through(&mut self.file, data).await {
+            error!("could not write to spool file {:?}: {}", file.path, err);
+            // the file may contain part of the data, which is never made visible
+            self.spool.release(data.len() as u64);
+            file.truncated.store(true, Ordering::Relaxed);
+            return;
+        }
+        let offset = file.size.fetch_add(data.len() as u64, Ordering::Release);
+        self.write_index(offset, data, timestamp).await;
+    }
+
+    /// records offset, length and time for each line of the data written at <offset>
+    async fn write_index(&mut self, mut offset: u64, data: &[u8], timestamp: SystemTime) {
+        let file = self.spool.file(self.stream);
+        let micros = timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_micros() as u64);
+        let mut records = Vec::new();
+        for line in data.split_inclusive(|b| *b == b'\n') {
+            records.extend_from_slice(&offset.to_le_bytes());
+            records.extend_from_slice(&(line.len() as u32).to_le_bytes());
+            records.extend_from_slice(&micros.to_le_bytes());
+            offset += line.len() as u64;
+        }
+        // the index is metadata, the disk caps only apply to the output itself
+        if let Err(err) = write_through(&mut self.index, &records).await {
+            error!(
+                "could not write to index file {:?}: {}",
+                file.index_path, err
+            );
+            // a partially written record would garble the index, stop writing
+            file.truncated.store(true, Ordering::Relaxed);
+            return;
+        }
+        file.index_size
+            .fetch_add(records.len() as u64, Ordering::Release);
+    }
+}
+
+/// writes all data and waits until the file was written to
+/// without the flush tokio completes the write in the background, readers could see a short file
+async fn write_through(file: &mut tokio::fs::File, data: &[u8]) -> io::Result<()> {
+    file.write_all(data).await?;
+    file.flush().await
+}
+
+/// reads bytes [from, to) of the file, less if the file is shorter
+fn read_range(file: &File, from: u64, to: u64) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; to.saturating_sub(from) as usize];
+    let mut read = 0;
+    while read < buf.len() {
+        match file.read_at(&mut buf[read..], from + read as u64) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    buf.truncate(read);
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, path::PathBuf, process, sync::atomic::Ordering};
+
+    use lazy_static::lazy_static;
+    use rcmd_data::{OutputRange, OutputStream};
+    use tokio::runtime::Runtime;
+
+    use super::{
+        JobSpool, SpoolLimits, MAX_READ_SIZE, READ_LIMIT_MARKER, SCAN_BLOCK_SIZE, TRUNCATION_MARKER,
+    };
+
+    lazy_static! {
+        static ref RUNTIME: Runtime = Runtime::new().unwrap();
+    }
+
+    fn dir(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("rcmd-test-spool-{}-{}", name, process::id()))
+    }
+
+    fn limits(max_job_output: Option<u64>, max_spool_size: Option<u64>) -> SpoolLimits {
+        SpoolLimits {
+            max_job_output,
+            max_spool_size,
+            usage: Default::default(),
+        }
+    }
+
+    fn read(spool: &JobSpool, stream: OutputStream) -> String {
+        String::from_utf8(spool.read_from(stream, 0).unwrap().0).unwrap()
+    }
+
+    // testing that output beyond the cap of a job is replaced by a single truncation marker
+    #[test]
+    fn test_job_cap() {
+        let dir = dir("job-cap");
+        let spool = JobSpool::create(&dir, "job", limits(Some(10), None)).unwrap();
+        RUNTIME.block_on(async {
+            let mut stdout = spool.writer(OutputStream::Stdout).unwrap();
+            let mut stderr = spool.writer(OutputStream::Stderr).unwrap();
+            stdout.write(b"12345678\n").await;
+            stdout.write(b"abc\n").await;
+            stdout.write(b"more\n").await;
+            // the cap covers both streams
+            stderr.write(b"x").await;
+        });
+        let expected = format!("12345678\n{}", TRUNCATION_MARKER);
+        assert_eq!(expected, read(&spool, OutputStream::Stdout));
+        assert_eq!(TRUNCATION_MARKER, read(&spool, OutputStream::Stderr));
+        let lines = spool.combined().unwrap();
+        assert_eq!(3, lines.len());
+        assert_eq!(TRUNCATION_MARKER.as_bytes(), &lines[2].data[..]);
+        drop(spool);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // testing that the total cap applies to all spools sharing the usage, which drops with a spool
+    #[test]
+    fn test_total_cap() {
+        let dir = dir("total-cap");
+        let limits = limits(None, Some(10));
+        let first = JobSpool::create(&dir, "first", limits.clone()).unwrap();
+        let second = JobSpool::create(&dir, "second", limits.clone()).unwrap();
+        RUNTIME.block_on(async {
+            first
+                .writer(OutputStream::Stdout)
+                .unwrap()
+                .write(b"123456")
+                .await;
+            second
+                .writer(OutputStream::Stdout)
+                .unwrap()
+                .write(b"123456")
+                .await;
+        });
+        assert_eq!("123456", read(&first, OutputStream::Stdout));
+        assert_eq!(TRUNCATION_MARKER, read(&second, OutputStream::Stdout));
+        let marker = TRUNCATION_MARKER.len() as u64;
+        assert_eq!(6 + marker, limits.usage.load(Ordering::Relaxed));
+        drop(first);
+        assert_eq!(marker, limits.usage.load(Ordering::Relaxed));
+        assert!(!dir.join(format!("{}.stdout", "first")).exists());
+        drop(second);
+        assert_eq!(0, limits.usage.load(Ordering::Relaxed));
+        assert_eq!(0, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // testing head and tail with lines spanning the blocks scanned for line breaks
+    #[test]
+    fn test_head_tail() {
+        let dir = dir("head-tail");
+        let spool = JobSpool::create(&dir, "job", limits(None, None)).unwrap();
+        let long = "a".repeat(SCAN_BLOCK_SIZE as usize * 2 + 10);
+        RUNTIME.block_on(async {
+            let mut stdout = spool.writer(OutputStream::Stdout).unwrap();
+            stdout.write(format!("{}\nb\n", long).as_bytes()).await;
+            stdout.write(b"c").await;
+        });
+        let chunk = |range| {
+            let chunk = spool.chunk(OutputStream::Stdout, range).unwrap();
+            String::from_utf8(chunk.data).unwrap()
+        };
+        assert_eq!("", chunk(OutputRange::Head(0)));
+        assert_eq!(format!("{}\n", long), chunk(OutputRange::Head(1)));
+        assert_eq!(format!("{}\nb\nc", long), chunk(OutputRange::Head(5)));
+        assert_eq!("", chunk(OutputRange::Tail(0)));
+        assert_eq!("c", chunk(OutputRange::Tail(1)));
+        assert_eq!("b\nc", chunk(OutputRange::Tail(2)));
+        assert_eq!(format!("{}\nb\nc", long), chunk(OutputRange::Tail(3)));
+
+        // the line break ending the stream does not start another line
+        RUNTIME.block_on(async {
+            let mut stdout = spool.writer(OutputStream::Stdout).unwrap();
+            stdout.write(b"\n").await;
+        });
+        assert_eq!("c\n", chunk(OutputRange::Tail(1)));
+        let range = OutputRange::Bytes {
+            from: long.len() as u64 + 1,
+            limit: Some(3),
+        };
+        assert_eq!("b\nc", chunk(range));
+        drop(spool);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // testing that a single read returns at most MAX_READ_SIZE bytes, the rest is read with further ones
+    #[test]
+    fn test_read_limit() {
+        let dir = dir("read-limit");
+        let spool = JobSpool::create(&dir, "job", limits(None, None)).unwrap();
+        let block = vec![b'x'; 1024 * 1024];
+        let blocks = MAX_READ_SIZE / block.len() as u64;
+        RUNTIME.block_on(async {
+            let mut stdout = spool.writer(OutputStream::Stdout).unwrap();
+            let mut stderr = spool.writer(OutputStream::Stderr).unwrap();
+            stderr.write(b"first\n").await;
+            for _ in 0..blocks {
+                stdout.write(&block).await;
+            }
+            stderr.write(b"last\n").await;
+        });
+        let (data, next) = spool.read_from(OutputStream::Stdout, 0).unwrap();
+        assert_eq!((MAX_READ_SIZE, MAX_READ_SIZE), (data.len() as u64, next));
+        let (data, next) = spool.read_from(OutputStream::Stdout, 10).unwrap();
+        assert_eq!(MAX_READ_SIZE - 10, data.len() as u64);
+        assert_eq!(MAX_READ_SIZE, next);
+        let range = OutputRange::Bytes {
+            from: 0,
+            limit: None,
+        };
+        let chunk = spool.chunk(OutputStream::Stdout, range).unwrap();
+        assert_eq!(MAX_READ_SIZE, chunk.next_offset);
+
+        // in the order written, the line passing the limit is replaced by the marker
+        let lines = spool.combined().unwrap();
+        assert_eq!(blocks as usize + 1, lines.len());
+        assert_eq!(
+            (OutputStream::Stderr, &b"first\n"[..]),
+            (lines[0].stream, &lines[0].data[..])
+        );
+        assert!(lines[1..blocks as usize]
+            .iter()
+            .all(|line| line.data == block));
+        let last = lines.last().unwrap();
+        assert_eq!(
+            (OutputStream::Stdout, READ_LIMIT_MARKER.as_bytes()),
+            (last.stream, &last.data[..])
+        );
+        drop(spool);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use rcmd_data::{Attempt, JobSpec, JobStatus, ResourceUsage};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+/// file holding the id of the next job of a pool
+const NEXT_ID_FILE: &str = "next_id";
+/// file holding why the most recently evicted jobs of a pool were evicted, by id
+const EVICTED_FILE: &str = "evicted";
+
+/// durable records of the jobs of one pool, one JSON file per job in <dir>/<encoded pool name>
+#[derive(Debug)]
+pub struct JobStore {
+    dir: PathBuf,
+}
+
+/// everything needed to restore a job after a restart, its output is referenced by spool name
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub id: u64,
+    pub spec: JobSpec,
+    pub status: JobStatus,
+    pub pid: Option<u32>,
+    pub submitted_at: SystemTime,
+    pub started_at: Option<SystemTime>,
+    pub ended_at: Option<SystemTime>,
+    pub usage: Option<ResourceUsage>,
+    pub spool: Option<String>,
+    pub attempt: u32,
+    pub attempts: Vec<AttemptRecord>,
+    pub schedule: Option<u64>,
+    /// set while the job is running in a shim
+    #[serde(default)]
+    pub shim_pid: Option<u32>,
+}
+
+/// finished attempt of a retried job along with the name of its spool
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AttemptRecord {
+    pub info: Attempt,
+    pub spool: Option<String>,
+}
+
+impl JobStore {
+    /// opens the store of <pool> in <dir>, creating its directory if needed
+    pub fn open(dir: &Path, pool: &str) -> io::Result<Self> {
+        let dir = dir.join(encode_name(pool));
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// names of the pools with a store in <dir>, none if it does not exist yet
+    pub fn pools(dir: &Path) -> io::Result<Vec<String>> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut pools = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            match entry.file_name().to_str().and_then(decode_name) {
+                Some(pool) => pools.push(pool),
+                None => error!(
+                    "ignoring unknown directory {:?} in state directory",
+                    entry.path()
+                ),
+            }
+        }
+        Ok(pools)
+    }
+
+    /// writes the record of a job, replacing its previous one
+    pub fn save(&self, record: &JobRecord) -> io::Result<()> {
+        let json = serde_json::to_vec(record)?;
+        write_atomically(&self.dir.join(format!("{}.json", record.id)), &json)
+    }
+
+    pub fn remove(&self, id: u64) -> io::Result<()> {
+        fs::remove_file(self.dir.join(format!("{}.json", id)))
+    }
+
+    /// all records of the pool, unreadable ones are logged and skipped
+    pub fn load(&self) -> io::Result<Vec<JobRecord>> {
+        let mut records = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension() != Some(OsStr::new("json")) {
+                continue;
+            }
+            let record = fs::read(&path).and_then(|json| Ok(serde_json::from_slice(&json)?));
+            match record {
+                Ok(record) => records.push(record),
+                Err(err) => error!("could not read job record {:?}: {}", path, err),
+            }
+        }
+        Ok(records)
+    }
+
+    /// id of the next job, the ids below it were handed out before
+    pub fn next_id(&self) -> io::Result<u64> {
+        match fs::read_to_string(self.dir.join(NEXT_ID_FILE)) {
+            Ok(id) => id
+                .trim()
+                .parse()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid next job id")),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn save_next_id(&self, id: u64) -> io::Result<()> {
+        write_atomically(&self.dir.join(NEXT_ID_FILE), id.to_string().as_bytes())
+    }
+
+    /// reasons of the evicted jobs, replacing the ones saved before
+    pub fn save_evicted(&self, evicted: &BTreeMap<u64, String>) -> io::Result<()> {
+        let json = serde_json::to_vec(evicted)?;
+        write_atomically(&self.dir.join(EVICTED_FILE), &json)
+    }
+
+    pub fn load_evicted(&self) -> io::Result<BTreeMap<u64, String>> {
+        match fs::read(self.dir.join(EVICTED_FILE)) {
+            Ok(json) => Ok(serde_json::from_slice(&json)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// replaces the file at once, so a crash leaves either the old or the new content
+fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(tmp, path)
+}
+
+/// pool name usable as directory name, alphanumeric characters are kept, all others hex encoded
+fn encode_name(pool: &str) -> String {
+    pool.bytes()
+        .map(|b| match b {
+            b'0'..=b'9' | b'a'..=b'z' | b'A'..=b'Z' => (b as char).to_string(),
+            _ => format!("_{:02x}", b),
+        })
+        .collect()
+}
+
+fn decode_name(name: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '_' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+            }
+            c if c.is_ascii_alphanumeric() => bytes.push(c as u8),
+            _ => return None,
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
@@ -1,121 +1,585 @@
-use std::process::ExitStatus;
+use std::{
+    ffi::CString,
+    mem,
+    os::unix::process::{CommandExt, ExitStatusExt},
+    process::{Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
 
+use rcmd_data::{JobSpec, ResourceUsage, StdinMode, UsageSummary};
 use tokio::{
-    io::{self, AsyncBufReadExt, AsyncRead, BufReader},
-    process::Child,
-    sync::{mpsc, oneshot},
-    time::Instant,
+    io::{self, AsyncRead, AsyncReadExt, AsyncWriteExt},
+    process::{ChildStderr, ChildStdin, ChildStdout},
+    signal::unix::{signal, Signal, SignalKind},
+    sync::{mpsc, oneshot, watch},
+    time::{sleep, sleep_until, timeout, Instant},
 };
 use tracing::{debug, error, info, instrument};
 
-/// setup tasks to forward stdout/stderr to given channels
-/// waits for process exiting or kill signal before sending exit status on given channel
-#[instrument(skip(process, stdout_channel, stderr_channel, exit_channel, kill_signal))]
+use crate::{
+    cgroup::{self, Cgroup},
+    shim::Shim,
+    spool::SpoolWriter,
+};
+
+/// how a managed process ended
+pub struct ProcessExit {
+    pub status: io::Result<ExitStatus>,
+    pub oom_killed: bool,
+    /// stop request the process was stopped with, if any
+    pub stop: Option<StopRequest>,
+    /// process did not exit within the grace period of the stop request and was killed
+    pub escalated: bool,
+    /// timeout after which the process was stopped, if it ran too long
+    pub timed_out: Option<Duration>,
+    /// signals sent to the process group through control commands
+    pub signalled: Vec<libc::c_int>,
+    /// None if the process could not be waited for
+    pub usage: Option<ResourceUsage>,
+    /// when the process (and its group, if it was stopped) was found to be gone
+    pub ended_at: SystemTime,
+}
+
+/// request to stop a process by sending <signal> to its process group
+/// if it did not exit after <grace>, the whole group is killed
+#[derive(Clone, Copy, Debug)]
+pub struct StopRequest {
+    pub signal: libc::c_int,
+    pub grace: Duration,
+}
+
+/// commands for a process manager task
+pub enum Control {
+    /// send signal to the process group, reply with the result
+    Signal {
+        signal: libc::c_int,
+        reply: oneshot::Sender<io::Result<()>>,
+    },
+    Stop(StopRequest),
+}
+
+/// stops a process with <stop> once it ran for longer than <after> since <started_at>
+#[derive(Clone, Copy, Debug)]
+pub struct Timeout {
+    pub after: Duration,
+    pub stop: StopRequest,
+    pub started_at: SystemTime,
+}
+
+impl StopRequest {
+    pub fn kill() -> Self {
+        Self {
+            signal: libc::SIGKILL,
+            grace: Duration::ZERO,
+        }
+    }
+}
+
+/// how often to check whether all processes of a killed process group are gone
+const GROUP_EXIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// how long to wait for all processes of a killed process group to be gone
+const GROUP_EXIT_TIMEOUT: Duration = Duration::from_secs(5);
+/// maximum bytes of output read at once
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+/// how often to check whether a process exited in case its SIGCHLD was missed
+const REAP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// child process of a job, spawned without tokio so only its manager waits for it
+/// tokio would otherwise reap the pid once its handle dropped, which may belong to another process by then
+pub struct JobChild {
+    pub pid: u32,
+    pub stdin: Option<ChildStdin>,
+    pub stdout: Option<ChildStdout>,
+    pub stderr: Option<ChildStderr>,
+}
+
+/// process of a job handed to its manager
+// moved once into the manager, boxing would gain nothing
+#[allow(clippy::large_enum_variant)]
+pub enum JobProcess {
+    /// child of the server, the manager writes its output to the spool files
+    Child {
+        process: JobChild,
+        /// input for stdin, stdin is closed once all senders dropped
+        stdin: Option<mpsc::UnboundedReceiver<Vec<u8>>>,
+        stdout: SpoolWriter,
+        stderr: SpoolWriter,
+    },
+    /// run by a shim, which writes its output and records its exit
+    /// the manager leaves the process running if the job pool is dropped
+    Shim { shim: Shim, pid: Option<u32> },
+}
+
+/// channels connecting a process manager task with the job pool
+pub struct ManagerChannels {
+    pub exit: oneshot::Sender<ProcessExit>,
+    pub control: mpsc::UnboundedReceiver<Control>,
+    /// notified after each line of output and after the exit was sent, closed once the manager finished
+    pub output_changed: watch::Sender<()>,
+    /// resource usage of the process is added to this once it exited
+    pub usage_summary: Arc<Mutex<UsageSummary>>,
+}
+
+/// what a manager waits for, the exit of its own child or the one recorded by a shim
+enum Waiter {
+    Reaper(Reaper),
+    Shim(Shim, Arc<watch::Sender<()>>),
+}
+
+impl Waiter {
+    async fn wait(&mut self) -> io::Result<ExitStatus> {
+        match self {
+            Waiter::Reaper(reaper) => reaper.wait().await,
+            Waiter::Shim(shim, output_changed) => shim.wait(output_changed).await,
+        }
+    }
+
+    fn usage(&self) -> Option<ResourceUsage> {
+        match self {
+            Waiter::Reaper(reaper) => reaper.usage(),
+            Waiter::Shim(shim, _) => shim.usage(),
+        }
+    }
+}
+
+/// waits for a child process with wait4, which unlike tokio's wait provides its resource usage
+/// the process must not be waited for by anybody else, see JobChild
+struct Reaper {
+    pid: libc::pid_t,
+    sigchld: io::Result<Signal>,
+    exit: Option<(ExitStatus, libc::rusage)>,
+}
+
+impl Reaper {
+    fn new(process: &JobChild) -> Self {
+        Self {
+            pid: process.pid as libc::pid_t,
+            sigchld: signal(SignalKind::child()),
+            exit: None,
+        }
+    }
+
+    /// waits for the process to exit, returns the same status once it exited
+    async fn wait(&mut self) -> io::Result<ExitStatus> {
+        loop {
+            if let Some((status, _)) = &self.exit {
+                return Ok(*status);
+            }
+            let mut status = 0;
+            let mut rusage: libc::rusage = unsafe { mem::zeroed() };
+            match unsafe { libc::wait4(self.pid, &mut status, libc::WNOHANG, &mut rusage) } {
+                0 => {
+                    // any child exiting wakes up every reaper, polling covers a failed signal setup
+                    match &mut self.sigchld {
+                        Ok(sigchld) => {
+                            let _ = timeout(REAP_POLL_INTERVAL, sigchld.recv()).await;
+                        }
+                        Err(_) => sleep(REAP_POLL_INTERVAL).await,
+                    }
+                }
+                -1 => {
+                    let wait_error = io::Error::last_os_error();
+                    if wait_error.kind() != io::ErrorKind::Interrupted {
+                        return Err(wait_error);
+                    }
+                }
+                _ => self.exit = Some((ExitStatus::from_raw(status), rusage)),
+            }
+        }
+    }
+
+    /// resource usage of the process and its waited for descendants, once it exited
+    fn usage(&self) -> Option<ResourceUsage> {
+        let (_, rusage) = self.exit.as_ref()?;
+        let time = |tv: libc::timeval| {
+            Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+        };
+        Some(ResourceUsage {
+            user_time: time(rusage.ru_utime),
+            system_time: time(rusage.ru_stime),
+            // reported in kilobytes
+            max_rss: rusage.ru_maxrss as u64 * 1024,
+            block_input: rusage.ru_inblock as u64,
+            block_output: rusage.ru_oublock as u64,
+            voluntary_context_switches: rusage.ru_nvcsw as u64,
+            involuntary_context_switches: rusage.ru_nivcsw as u64,
+        })
+    }
+}
+
+/// setup tasks to write stdout/stderr to given spool files and given input to stdin, unless a shim does
+/// waits for process exiting, stop request or timeout before sending exit status on given channel
+/// signals received on the control channel in the meantime are forwarded to the process group
+/// the process is expected to lead its own process group, which is stopped as a whole
+/// the job's cgroup (if any) is removed once the process exited
+#[instrument(skip(process, cgroup, channels))]
 pub async fn manage_process(
     job_id: u64,
-    mut process: Child,
-    stdout_channel: mpsc::UnboundedSender<(String, Instant)>,
-    stderr_channel: mpsc::UnboundedSender<(String, Instant)>,
-    exit_channel: oneshot::Sender<io::Result<ExitStatus>>,
-    kill_signal: oneshot::Receiver<()>,
+    process: JobProcess,
+    cgroup: Option<Cgroup>,
+    time_limit: Option<Timeout>,
+    channels: ManagerChannels,
 ) {
-    info!("start managing process with pid: {:?}", process.id());
-    let stdout = process.stdout.take().unwrap();
-    let stderr = process.stderr.take().unwrap();
-
-    // continously read from stdout/stderr in background
-    let stdout_handle = tokio::spawn(read_to_end(stdout, stdout_channel));
-    let stderr_handle = tokio::spawn(read_to_end(stderr, stderr_channel));
-
-    // wait for either process to finish or receival of terminate command
-    tokio::select! {
-        _ = process.wait() => info!("process exited"),
-        recv_res = kill_signal => {
-            info!("received kill signal");
-            if let Err(_recv_err) = recv_res {
-                // this would happen when job pool was dropped
-                debug!("kill channel receive error, sender dropped, pid: {:?}", process.id());
+    let ManagerChannels {
+        exit: exit_channel,
+        control: mut control_channel,
+        output_changed,
+        usage_summary,
+    } = channels;
+    let output_changed = Arc::new(output_changed);
+    let (pid, mut waiter, handles) = match process {
+        JobProcess::Child {
+            mut process,
+            stdin,
+            stdout,
+            stderr,
+        } => {
+            let reaper = Reaper::new(&process);
+            let stdout_stream = process.stdout.take().unwrap();
+            let stderr_stream = process.stderr.take().unwrap();
+            // continously read from stdout/stderr in background
+            let stdout_handle =
+                tokio::spawn(read_to_end(stdout_stream, stdout, output_changed.clone()));
+            let stderr_handle =
+                tokio::spawn(read_to_end(stderr_stream, stderr, output_changed.clone()));
+            let stdin_handle = match (process.stdin.take(), stdin) {
+                (Some(stdin_stream), Some(stdin)) => {
+                    Some(tokio::spawn(write_all(stdin_stream, stdin)))
+                }
+                _ => None,
+            };
+            let handles = Some((stdout_handle, stderr_handle, stdin_handle));
+            (Some(process.pid), Waiter::Reaper(reaper), handles)
+        }
+        JobProcess::Shim { shim, pid } => {
+            let waiter = Waiter::Shim(shim, output_changed.clone());
+            (pid, waiter, None)
+        }
+    };
+    info!("start managing process with pid: {:?}", pid);
+    let pgid = pid.map(|pid| pid as libc::pid_t);
+
+    // wait for either process to finish, receival of stop request or the timeout
+    let mut signalled = Vec::new();
+    // a deadline too far in the future to be represented is never reached
+    let deadline = time_limit.and_then(|time_limit| {
+        let elapsed = time_limit.started_at.elapsed().unwrap_or_default();
+        Instant::now().checked_add(time_limit.after.saturating_sub(elapsed))
+    });
+    let mut timed_out = None;
+    let stop = loop {
+        tokio::select! {
+            _ = waiter.wait() => {
+                info!("process exited");
+                break None;
             }
-            // kill process no matter if channel was closed or signal was sent
-            if let Err(kill_error) = process.kill().await {
-                error!("unexpected error when killing process, pid: {:?}, err: {}", process.id(), kill_error);
+            control = control_channel.recv() => match control {
+                Some(Control::Signal { signal, reply }) => {
+                    info!("received signal {} for process group", signal);
+                    let result = signal_process_group(pgid, signal);
+                    if result.is_ok() {
+                        signalled.push(signal);
+                    }
+                    let _ = reply.send(result);
+                }
+                Some(Control::Stop(request)) => break Some(request),
+                // the shim keeps running the job, a new job pool can reattach to it
+                None if matches!(waiter, Waiter::Shim(..)) => {
+                    info!("control channel closed, leaving process to its shim");
+                    return;
+                }
+                None => {
+                    // this would happen when job pool was dropped
+                    debug!("control channel closed, sender dropped, pid: {:?}", pid);
+                    break Some(StopRequest::kill());
+                }
+            },
+            // the deadline is only used if there is a time limit
+            _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                let time_limit = time_limit.unwrap();
+                info!("process timed out after {:?}", time_limit.after);
+                timed_out = Some(time_limit.after);
+                break Some(time_limit.stop);
+            }
+        }
+    };
+
+    let mut kill_result = Ok(());
+    let mut escalated = false;
+    if let Some(request) = stop {
+        info!("stopping process: {:?}", request);
+        if request.signal != libc::SIGKILL {
+            // continue the group afterwards, a paused process could not act on the signal
+            let signal_result = signal_process_group(pgid, request.signal)
+                .and_then(|_| signal_process_group(pgid, libc::SIGCONT));
+            if let Err(signal_error) = signal_result {
+                error!(
+                    "unexpected error when signalling process group, err: {}",
+                    signal_error
+                );
+            }
+            if timeout(request.grace, waiter.wait()).await.is_err() {
+                info!("process did not exit within grace period, killing it");
+                escalated = true;
             }
         }
+        // other processes of the job would keep the cgroup (and possibly the output pipes) alive
+        if let Some(cgroup) = &cgroup {
+            cgroup.kill();
+        }
+        // kill what is left of the process group, no matter if channel was closed or request was sent
+        kill_result = kill_process_group(pgid, &mut waiter).await;
+        if let Err(kill_error) = &kill_result {
+            error!(
+                "unexpected error when killing process group, err: {}",
+                kill_error
+            );
+        }
     }
 
-    // wait for process to finish, send exit status / error on exit channel
-    if let Err(join_error) = stdout_handle.await {
-        error!(
-            "unexpected error when joining stdout, pid: {:?}, err: {}",
-            process.id(),
-            join_error
-        );
+    let ended_at = SystemTime::now();
+
+    if let Some((stdout_handle, stderr_handle, stdin_handle)) = handles {
+        // nobody is reading anymore, stop writing and close stdin
+        if let Some(stdin_handle) = stdin_handle {
+            stdin_handle.abort();
+        }
+
+        // wait for process to finish, send exit status / error on exit channel
+        if let Err(join_error) = stdout_handle.await {
+            error!(
+                "unexpected error when joining stdout, pid: {:?}, err: {}",
+                pid, join_error
+            );
+        }
+        if let Err(join_error) = stderr_handle.await {
+            error!(
+                "unexpected error when joining stderr, pid: {:?}, err: {}",
+                pid, join_error
+            );
+        }
     }
-    if let Err(join_error) = stderr_handle.await {
-        error!(
-            "unexpected error when joining stderr, pid: {:?}, err: {}",
-            process.id(),
-            join_error
-        );
+    let status = match kill_result {
+        Ok(_) => waiter.wait().await,
+        Err(kill_error) => Err(kill_error),
+    };
+    let oom_killed = matches!(&cgroup, Some(cgroup) if cgroup.oom_killed());
+    let mut usage = waiter.usage();
+    if let Some(cgroup) = cgroup {
+        // the cgroup also accounts processes the job's process did not wait for
+        if let (Some(usage), Some((user_time, system_time))) = (&mut usage, cgroup.cpu_times()) {
+            usage.user_time = user_time;
+            usage.system_time = system_time;
+        }
+        cgroup.remove().await;
+    }
+    if let Some(usage) = &usage {
+        let mut summary = usage_summary.lock().unwrap();
+        summary.jobs += 1;
+        summary.usage.add(usage);
     }
-    if let Err(_unsent) = exit_channel.send(process.wait().await) {
+    let exit = ProcessExit {
+        status,
+        oom_killed,
+        stop,
+        escalated,
+        timed_out,
+        signalled,
+        usage,
+        ended_at,
+    };
+    if let Err(_unsent) = exit_channel.send(exit) {
         // this would happen when job pool was dropped
-        debug!(
-            "closed channel when sending exit result, pid: {:?}",
-            process.id()
-        );
+        debug!("closed channel when sending exit result, pid: {:?}", pid);
+    }
+    let _ = output_changed.send(());
+}
+
+/// sends signal to all processes of the given group, a group without processes is not an error
+fn signal_process_group(pgid: Option<libc::pid_t>, signal: libc::c_int) -> io::Result<()> {
+    let pgid = match pgid {
+        Some(pgid) => pgid,
+        None => return Ok(()),
+    };
+    if unsafe { libc::killpg(pgid, signal) } != 0 {
+        let signal_error = io::Error::last_os_error();
+        if signal_error.raw_os_error() != Some(libc::ESRCH) {
+            return Err(signal_error);
+        }
+    }
+    Ok(())
+}
+
+/// sends SIGKILL to the process group led by the given process
+/// waits until the leader is reaped and every other member of the group is gone
+async fn kill_process_group(pgid: Option<libc::pid_t>, waiter: &mut Waiter) -> io::Result<()> {
+    signal_process_group(pgid, libc::SIGKILL)?;
+    waiter.wait().await?;
+    let pgid = match pgid {
+        Some(pgid) => pgid,
+        None => return Ok(()),
+    };
+    // remaining members were reparented and get reaped by init (or a subreaper)
+    let deadline = Instant::now() + GROUP_EXIT_TIMEOUT;
+    while process_group_exists(pgid) {
+        if Instant::now() > deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("processes of group {} still alive after kill", pgid),
+            ));
+        }
+        sleep(GROUP_EXIT_POLL_INTERVAL).await;
     }
+    debug!("all processes of group {} are gone", pgid);
+    Ok(())
+}
+
+fn process_group_exists(pgid: libc::pid_t) -> bool {
+    // signal 0 only checks for existence (and permission)
+    unsafe { libc::killpg(pgid, 0) == 0 }
 }
 
-/// get all lines from channel up to timestamp <until>
-/// actually receives one more line with timestamp after <until>
-pub async fn receive_lines_until(
-    lines_receiver: &mut mpsc::UnboundedReceiver<(String, Instant)>,
-    until: &Instant,
-) -> Vec<String> {
-    let mut lines = Vec::new();
-    while let Ok((line, timestamp)) = lines_receiver.try_recv() {
-        lines.push(line);
-        if timestamp > *until {
-            break;
+/// spawns the process of a job, which leads its own session and joins the cgroup with
+/// the given cgroup.procs file before exec
+pub fn spawn_job_process(spec: &JobSpec, cgroup_procs: Option<CString>) -> io::Result<JobChild> {
+    let mut process = job_command(spec, cgroup_procs).spawn()?;
+    let pipes = (|| -> io::Result<_> {
+        Ok((
+            process.stdin.take().map(ChildStdin::from_std).transpose()?,
+            ChildStdout::from_std(process.stdout.take().unwrap())?,
+            ChildStderr::from_std(process.stderr.take().unwrap())?,
+        ))
+    })();
+    match pipes {
+        Ok((stdin, stdout, stderr)) => Ok(JobChild {
+            pid: process.id(),
+            stdin,
+            stdout: Some(stdout),
+            stderr: Some(stderr),
+        }),
+        Err(err) => {
+            // nobody would manage the process
+            let _ = process.kill();
+            let _ = process.wait();
+            Err(err)
         }
     }
-    lines
 }
 
-/// get all lines from channel until all senders have dropped
-pub async fn receive_all_lines(
-    lines_receiver: &mut mpsc::UnboundedReceiver<(String, Instant)>,
-) -> Vec<String> {
-    let mut lines = Vec::new();
-    while let Some((line, _timestamp)) = lines_receiver.recv().await {
-        lines.push(line);
+fn job_command(spec: &JobSpec, cgroup_procs: Option<CString>) -> Command {
+    // pipe stdout/stderr, and stdin unless there is no input
+    let stdin = match spec.stdin {
+        StdinMode::Null => Stdio::null(),
+        StdinMode::Data(_) | StdinMode::Pipe => Stdio::piped(),
+    };
+    let mut command = Command::new(&spec.command);
+    command
+        .args(&spec.arguments)
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if spec.clear_env {
+        command.env_clear();
+    }
+    command.envs(&spec.env);
+    if let Some(working_dir) = &spec.working_dir {
+        command.current_dir(working_dir);
+    }
+    if let Some(umask) = spec.umask {
+        unsafe {
+            command.pre_exec(move || {
+                libc::umask(umask as libc::mode_t);
+                Ok(())
+            });
+        }
+    }
+    // own session and thereby process group, so all descendants can be killed together
+    unsafe {
+        command.pre_exec(|| match libc::setsid() {
+            -1 => Err(std::io::Error::last_os_error()),
+            _ => Ok(()),
+        });
+    }
+    if let Some(procs) = cgroup_procs {
+        // join cgroup before exec, so even the first instruction of the job is accounted
+        unsafe {
+            command.pre_exec(move || cgroup::join(&procs));
+        }
     }
-    lines
+    command
 }
 
-/// reads from stream and sends to channel line by line until EOF
-/// when encountering invalid utf8 a marker is added to the line
+/// writes the output of a process to the spool files and the given input to its stdin
+/// until it exited and its output was written completely, used by the shim of a job
+/// returns its exit status and resource usage
+pub async fn run_to_end(
+    mut process: JobChild,
+    stdin: Option<mpsc::UnboundedReceiver<Vec<u8>>>,
+    stdout: SpoolWriter,
+    stderr: SpoolWriter,
+) -> (io::Result<ExitStatus>, Option<ResourceUsage>) {
+    let mut reaper = Reaper::new(&process);
+    // nobody follows the output of the shim's process
+    let output_changed = Arc::new(watch::channel(()).0);
+    let stdout_handle = tokio::spawn(read_to_end(
+        process.stdout.take().unwrap(),
+        stdout,
+        output_changed.clone(),
+    ));
+    let stderr_handle = tokio::spawn(read_to_end(
+        process.stderr.take().unwrap(),
+        stderr,
+        output_changed,
+    ));
+    let stdin_handle = match (process.stdin.take(), stdin) {
+        (Some(stdin_stream), Some(stdin)) => Some(tokio::spawn(write_all(stdin_stream, stdin))),
+        _ => None,
+    };
+    let status = reaper.wait().await;
+    if let Some(stdin_handle) = stdin_handle {
+        stdin_handle.abort();
+    }
+    for handle in [stdout_handle, stderr_handle] {
+        if let Err(join_error) = handle.await {
+            error!("unexpected error when joining output: {}", join_error);
+        }
+    }
+    (status, reaper.usage())
+}
+
+/// writes data received from channel to stdin until all senders have dropped, then closes stdin
+async fn write_all(mut stdin: ChildStdin, mut data_receiver: mpsc::UnboundedReceiver<Vec<u8>>) {
+    while let Some(data) = data_receiver.recv().await {
+        if let Err(io_error) = stdin.write_all(&data).await {
+            // most likely the process closed its stdin, remaining input is discarded
+            info!("could not write to stdin: {}", io_error);
+            return;
+        }
+    }
+    debug!("all stdin senders dropped, closing stdin");
+}
+
+/// reads from stream and writes to spool file until EOF, notifying about each chunk read
+/// the output is stored exactly as read, writing blocks reading,
+/// so a job producing output faster than it can be stored is slowed down
 async fn read_to_end<A: AsyncRead + std::marker::Unpin>(
-    stream: A,
-    lines_sender: mpsc::UnboundedSender<(String, Instant)>,
+    mut stream: A,
+    mut writer: SpoolWriter,
+    output_changed: Arc<watch::Sender<()>>,
 ) {
-    let mut reader = BufReader::new(stream);
+    let mut buf = vec![0; READ_BUFFER_SIZE];
     loop {
-        let mut buf = String::new();
-        match reader.read_line(&mut buf).await {
-            Ok(n) if n == 0 => break,
-            Ok(_) => {}
-            Err(io_error) => match io_error.kind() {
-                io::ErrorKind::InvalidData => buf.push_str("###INVALID UTF8###"),
-                _ => error!("unexpected io error when reading from stream: {}", io_error),
-            },
-        }
-        if let Err(send_error) = lines_sender.send((buf, Instant::now())) {
-            error!(
-                "unexpected error when sending stream output line : {}",
-                send_error
-            )
+        match stream.read(&mut buf).await {
+            Ok(0) => break,
+            Ok(n) => writer.write(&buf[..n]).await,
+            Err(io_error) if io_error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(io_error) => {
+                error!("unexpected io error when reading from stream: {}", io_error);
+                break;
+            }
         }
+        // nobody following the output is not an error
+        let _ = output_changed.send(());
     }
 }
@@ -0,0 +1,193 @@
+use std::{
+    env, fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+
+use lazy_static::lazy_static;
+use rcmd_lib::job_pool::{JobPool, JobPoolConfig, JobStatus};
+use tokio::{
+    runtime::Runtime,
+    time::{sleep, timeout},
+};
+
+lazy_static! {
+    static ref RUNTIME: Runtime = Runtime::new().unwrap();
+}
+
+fn config(dir: &Path) -> JobPoolConfig {
+    JobPoolConfig {
+        spool_dir: Some(dir.join("spool")),
+        state_dir: Some(dir.join("state")),
+        shim: Some(PathBuf::from(env!("CARGO_BIN_EXE_rcmd_shim"))),
+        ..Default::default()
+    }
+}
+
+fn process_exists(pid: u32) -> bool {
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+// testing that jobs run by a shim keep running without their pool and are reattached by the next one
+#[test]
+fn test_reattach() {
+    let dir = env::temp_dir().join(format!("rcmd-test-shim-{}", process::id()));
+    let config = config(&dir);
+    RUNTIME.block_on(async {
+        let pool = JobPool::with_config("shim", config.clone());
+        let script = "echo before; sleep 1; echo after";
+        let finishing = pool.submit("sh", &["-c", script]).await;
+        let stopped = pool.submit("sleep", &["30"]).await;
+        sleep(Duration::from_millis(200)).await;
+        assert_eq!(Some(JobStatus::Running), pool.status(finishing).await);
+        let pid = pool.info(stopped).await.unwrap().pid.unwrap();
+        drop(pool);
+        sleep(Duration::from_millis(200)).await;
+        assert!(process_exists(pid));
+
+        let pool = JobPool::with_config("shim", config.clone());
+        assert_eq!(Some(JobStatus::Running), pool.status(finishing).await);
+        assert_eq!(Some(pid), pool.info(stopped).await.unwrap().pid);
+        sleep(Duration::from_millis(1500)).await;
+        assert_eq!(
+            Some(JobStatus::Completed { exit_code: 0 }),
+            pool.status(finishing).await
+        );
+        assert_eq!(
+            "before\nafter\n",
+            pool.output(finishing).await.unwrap().stdout()
+        );
+        match pool.stop(stopped, None, None).await {
+            Some(JobStatus::Halted { .. }) => {}
+            status => panic!("expected halted job, got: {:?}", status),
+        }
+        assert!(!process_exists(pid));
+        assert!(pool.info(finishing).await.unwrap().usage.is_some());
+    });
+    fs::remove_dir_all(dir).unwrap();
+}
+
+// testing that a job the shim can not start fails like one run by the server itself
+#[test]
+fn test_shim_invalid_command() {
+    let dir = env::temp_dir().join(format!("rcmd-test-shim-invalid-{}", process::id()));
+    let config = config(&dir);
+    RUNTIME.block_on(async {
+        let pool = JobPool::with_config("shim", config);
+        let id = pool.submit("abcdfg", &[]).await;
+        match pool.status(id).await {
+            Some(JobStatus::Error { .. }) => {}
+            status => panic!("expected error job status, got: {:?}", status),
+        }
+    });
+    fs::remove_dir_all(dir).unwrap();
+}
+
+// testing that shutdown leaves jobs run by a shim running, so the next pool can reattach to them
+#[test]
+fn test_shutdown_leaves_shim_jobs() {
+    let dir = env::temp_dir().join(format!("rcmd-test-shim-shutdown-{}", process::id()));
+    let config = config(&dir);
+    RUNTIME.block_on(async {
+        let pool = JobPool::with_config("shim", config.clone());
+        let id = pool.submit("sleep", &["30"]).await;
+        let pid = pool.info(id).await.unwrap().pid.unwrap();
+        pool.shutdown(Some(Duration::from_millis(200))).await;
+        assert!(process_exists(pid));
+        assert_eq!(None, pool.status(id).await);
+        drop(pool);
+
+        let pool = JobPool::with_config("shim", config);
+        assert_eq!(Some(JobStatus::Running), pool.status(id).await);
+        assert_eq!(Some(Ok(())), pool.delete(id).await);
+        assert!(!process_exists(pid));
+    });
+    fs::remove_dir_all(dir).unwrap();
+}
+
+// testing that a job whose shim died is not considered running if another process got the shim's pid
+#[test]
+fn test_reattach_dead_shim() {
+    let dir = env::temp_dir().join(format!("rcmd-test-shim-dead-{}", process::id()));
+    let config = config(&dir);
+    RUNTIME.block_on(async {
+        let pool = JobPool::with_config("shim", config.clone());
+        let id = pool.submit("sleep", &["30"]).await;
+        let pid = pool.info(id).await.unwrap().pid.unwrap();
+        drop(pool);
+        sleep(Duration::from_millis(200)).await;
+
+        let record_file = dir.join("state").join("shim").join(format!("{}.json", id));
+        let mut record: serde_json::Value =
+            serde_json::from_slice(&fs::read(&record_file).unwrap()).unwrap();
+        let shim_pid = record["shim_pid"].as_u64().unwrap() as libc::pid_t;
+        unsafe {
+            libc::kill(shim_pid, libc::SIGKILL);
+            libc::kill(pid as libc::pid_t, libc::SIGKILL);
+        }
+        sleep(Duration::from_millis(200)).await;
+        // the test process stands in for an unrelated process reusing the pid
+        record["shim_pid"] = process::id().into();
+        fs::write(&record_file, serde_json::to_vec(&record).unwrap()).unwrap();
+
+        let pool = JobPool::with_config("shim", config);
+        sleep(Duration::from_millis(500)).await;
+        match pool.status(id).await {
+            Some(JobStatus::Error { .. }) | Some(JobStatus::Lost { .. }) => {}
+            status => panic!("expected error or lost job status, got: {:?}", status),
+        }
+    });
+    fs::remove_dir_all(dir).unwrap();
+}
+
+// testing that a queued job whose shim is slow to start does not block requests for other jobs,
+// and that a job deleted while its shim starts does not keep running
+#[test]
+fn test_slow_shim() {
+    let dir = env::temp_dir().join(format!("rcmd-test-shim-slow-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let shim = dir.join("slow-shim");
+    let script = format!(
+        "#!/bin/sh\nsleep 1\nexec {} \"$@\"\n",
+        env!("CARGO_BIN_EXE_rcmd_shim")
+    );
+    fs::write(&shim, script).unwrap();
+    fs::set_permissions(&shim, fs::Permissions::from_mode(0o755)).unwrap();
+    let config = JobPoolConfig {
+        shim: Some(shim),
+        max_running_jobs: Some(1),
+        ..config(&dir)
+    };
+    RUNTIME.block_on(async {
+        let pool = JobPool::with_config("slow", config);
+        pool.submit("sleep", &["0.2"]).await;
+        let queued = pool.submit("true", &[]).await;
+        sleep(Duration::from_millis(400)).await;
+        // the first job finished, the shim of the queued one is starting
+        let status = timeout(Duration::from_millis(200), pool.status(queued)).await;
+        assert!(matches!(status, Ok(Some(JobStatus::Queued { .. }))));
+        sleep(Duration::from_millis(1500)).await;
+        assert_eq!(
+            Some(JobStatus::Completed { exit_code: 0 }),
+            pool.status(queued).await
+        );
+
+        pool.submit("sleep", &["0.2"]).await;
+        let pid_file = dir.join("pid");
+        let script = format!("echo $$ > {}; exec sleep 30", pid_file.display());
+        let deleted = pool.submit("sh", &["-c", &script]).await;
+        sleep(Duration::from_millis(400)).await;
+        let result = timeout(Duration::from_millis(200), pool.delete(deleted)).await;
+        assert_eq!(Ok(Some(Ok(()))), result);
+        sleep(Duration::from_millis(1500)).await;
+        let pid = fs::read_to_string(&pid_file)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        assert!(!process_exists(pid));
+    });
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,135 @@
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use rcmd_data::parse_duration;
+use rcmd_lib::job_pool::{JobPoolConfig, Retention, Scheduler};
+use rocket::{
+    figment::{
+        providers::{Env, Format, Toml},
+        Figment,
+    },
+    serde::{de::Error, Deserialize, Deserializer},
+};
+
+/// server settings that are not covered by Rocket's config
+/// read from Rcmd.toml in the working directory, overridden by RCMD_ prefixed env vars
+#[derive(Debug, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ServerConfig {
+    /// cgroup (v2) under which jobs with resource limits are placed
+    pub cgroup_parent: Option<PathBuf>,
+    /// directory for files holding the jobs' output
+    pub spool_dir: Option<PathBuf>,
+    /// maximum bytes of output stored per job
+    pub max_job_output: Option<u64>,
+    /// maximum bytes of output stored for all jobs
+    pub max_spool_size: Option<u64>,
+    /// directory for the records of the jobs, finished jobs are restored after a restart if set
+    pub state_dir: Option<PathBuf>,
+    /// path of the rcmd_shim binary, jobs then keep running across restarts (needs state_dir)
+    pub shim: Option<PathBuf>,
+    /// timeout of jobs that did not set one, e.g. "30m"
+    #[serde(default, deserialize_with = "duration")]
+    pub default_timeout: Option<Duration>,
+    /// longest timeout a job may set
+    #[serde(default, deserialize_with = "duration")]
+    pub max_timeout: Option<Duration>,
+    /// grace period of the jobs stopped when the server shuts down, defaults to their stop grace
+    #[serde(default, deserialize_with = "duration")]
+    pub shutdown_grace: Option<Duration>,
+    /// time after which finished jobs are evicted along with their output, e.g. "24h"
+    #[serde(default, deserialize_with = "duration")]
+    pub max_finished_age: Option<Duration>,
+    /// maximum number of finished jobs kept per client, the oldest ones are evicted beyond it
+    pub max_finished_jobs: Option<usize>,
+    /// maximum bytes of output of the finished jobs kept per client
+    pub max_finished_output: Option<u64>,
+    /// maximum number of jobs running at once across all clients, further jobs are queued
+    pub max_running_jobs: Option<usize>,
+    /// maximum number of jobs of a single client running at once
+    pub max_running_jobs_per_client: Option<usize>,
+    /// time after which a drain stops the remaining jobs, unless the drain request sets one
+    /// without it a drain waits for all jobs to finish
+    #[serde(default, deserialize_with = "duration")]
+    pub drain_timeout: Option<Duration>,
+    /// names of the clients allowed to use the admin endpoints, e.g. to drain the server
+    #[serde(default)]
+    pub admins: Vec<String>,
+    /// settings overriding the ones above for single clients, by client name
+    #[serde(default)]
+    pub clients: HashMap<String, ClientConfig>,
+}
+
+/// settings for the jobs of a single client, unset ones are taken from the server settings
+#[derive(Debug, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ClientConfig {
+    #[serde(default, deserialize_with = "duration")]
+    pub default_timeout: Option<Duration>,
+    #[serde(default, deserialize_with = "duration")]
+    pub max_timeout: Option<Duration>,
+    pub max_running_jobs: Option<usize>,
+    pub max_finished_jobs: Option<usize>,
+    /// share of the run slots relative to other clients (default 1) while jobs are queued
+    pub weight: Option<u32>,
+}
+
+impl ServerConfig {
+    pub fn load() -> Self {
+        Figment::new()
+            .merge(Toml::file("Rcmd.toml"))
+            .merge(Env::prefixed("RCMD_"))
+            .extract()
+            .expect("invalid server configuration")
+    }
+
+    /// config of the job pools, clones of it share the spool usage and the scheduler
+    pub fn job_pool_config(&self) -> JobPoolConfig {
+        JobPoolConfig {
+            cgroup_parent: self.cgroup_parent.clone(),
+            spool_dir: self.spool_dir.clone(),
+            max_job_output: self.max_job_output,
+            max_spool_size: self.max_spool_size,
+            default_timeout: self.default_timeout,
+            max_timeout: self.max_timeout,
+            max_running_jobs: self.max_running_jobs_per_client,
+            scheduler: Scheduler::new(self.max_running_jobs),
+            state_dir: self.state_dir.clone(),
+            shim: self.shim.clone(),
+            retention: Retention {
+                max_age: self.max_finished_age,
+                max_jobs: self.max_finished_jobs,
+                max_output: self.max_finished_output,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// pool configs of the clients with their own settings, derived from the given server wide one
+    pub fn client_job_pool_configs(&self, base: &JobPoolConfig) -> HashMap<String, JobPoolConfig> {
+        self.clients
+            .iter()
+            .map(|(client, client_config)| {
+                let config = JobPoolConfig {
+                    default_timeout: client_config.default_timeout.or(base.default_timeout),
+                    max_timeout: client_config.max_timeout.or(base.max_timeout),
+                    max_running_jobs: client_config.max_running_jobs.or(base.max_running_jobs),
+                    weight: client_config.weight,
+                    retention: Retention {
+                        max_jobs: client_config.max_finished_jobs.or(base.retention.max_jobs),
+                        ..base.retention.clone()
+                    },
+                    ..base.clone()
+                };
+                (client.clone(), config)
+            })
+            .collect()
+    }
+}
+
+/// parses a duration given like "10s" or "30m"
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    let duration: Option<String> = Option::deserialize(deserializer)?;
+    duration
+        .map(|duration| parse_duration(&duration).map_err(D::Error::custom))
+        .transpose()
+}
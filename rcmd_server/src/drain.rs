@@ -0,0 +1,173 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, SystemTime},
+};
+
+use rcmd_data::DrainStatus;
+use rcmd_lib::job_pool::JobPool;
+use rocket::{
+    fairing::AdHoc,
+    http::Header,
+    tokio::{
+        signal::unix::{signal, SignalKind},
+        time::sleep,
+    },
+    Shutdown,
+};
+
+use crate::state::JobPools;
+
+/// how long clients are asked to wait before submitting again if the drain has no deadline
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// how often a draining server checks whether all jobs finished
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// drain mode of the server, once started new jobs are rejected
+/// and the server shuts down as soon as all jobs finished or the deadline passed
+#[derive(Clone)]
+pub struct Drain {
+    job_pools: Arc<RwLock<HashMap<String, Arc<JobPool>>>>,
+    // of the job pools, for pools created while draining
+    accepting: Arc<AtomicBool>,
+    // of drains started without a timeout
+    default_timeout: Option<Duration>,
+    // set once draining started
+    draining: Arc<Mutex<Option<Draining>>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Draining {
+    // None if the server waits for all jobs to finish
+    deadline: Option<SystemTime>,
+}
+
+/// rejection of a submission while the server drains, to be retried against the restarted server
+#[derive(Responder)]
+#[response(status = 503)]
+pub struct Unavailable {
+    message: &'static str,
+    retry_after: Header<'static>,
+}
+
+impl Drain {
+    /// drain of the server with the given job pools, not started yet
+    pub fn new(job_pools: &JobPools, default_timeout: Option<Duration>) -> Self {
+        Self {
+            job_pools: job_pools.job_pools.clone(),
+            accepting: job_pools.accepting.clone(),
+            default_timeout,
+            draining: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// starts draining, the remaining jobs are stopped through <shutdown> after <timeout>
+    /// (default from the config, without one the server waits for all jobs)
+    /// does nothing if the server drains already
+    pub fn start(&self, timeout: Option<Duration>, shutdown: Shutdown) {
+        let draining = {
+            let mut draining = self.draining.lock().unwrap();
+            if draining.is_some() {
+                return;
+            }
+            // a deadline too far in the future to be represented is never reached
+            let started = Draining {
+                deadline: timeout
+                    .or(self.default_timeout)
+                    .and_then(|timeout| SystemTime::now().checked_add(timeout)),
+            };
+            *draining = Some(started);
+            started
+        };
+        info!("draining server, deadline: {:?}", draining.deadline);
+        // schedules would otherwise keep submitting jobs, so the drain might never end
+        self.accepting.store(false, Ordering::SeqCst);
+        for pool in self.job_pools.read().unwrap().values() {
+            pool.stop_accepting();
+        }
+        let job_pools = self.job_pools.clone();
+        rocket::tokio::spawn(async move {
+            loop {
+                let remaining = remaining_jobs(&job_pools).await;
+                if remaining == 0 {
+                    info!("all jobs finished, shutting down");
+                    break;
+                }
+                if matches!(draining.deadline, Some(deadline) if SystemTime::now() >= deadline) {
+                    info!(
+                        "drain deadline passed, stopping {} remaining jobs",
+                        remaining
+                    );
+                    break;
+                }
+                sleep(DRAIN_POLL_INTERVAL).await;
+            }
+            shutdown.notify();
+        });
+    }
+
+    /// rejection with a Retry-After until the deadline, if the server drains
+    pub fn check(&self) -> Result<(), Unavailable> {
+        let draining = match *self.draining.lock().unwrap() {
+            Some(draining) => draining,
+            None => return Ok(()),
+        };
+        let retry_after = match draining.deadline {
+            Some(deadline) => deadline
+                .duration_since(SystemTime::now())
+                .unwrap_or_default()
+                .max(Duration::from_secs(1)),
+            None => DEFAULT_RETRY_AFTER,
+        };
+        Err(Unavailable {
+            message: "server is draining, submit again later",
+            retry_after: Header::new("Retry-After", retry_after.as_secs().to_string()),
+        })
+    }
+
+    pub async fn status(&self) -> DrainStatus {
+        let draining = *self.draining.lock().unwrap();
+        DrainStatus {
+            draining: draining.is_some(),
+            remaining_jobs: remaining_jobs(&self.job_pools).await,
+            deadline: draining.and_then(|draining| draining.deadline),
+        }
+    }
+}
+
+/// fairing starting the drain once the server receives SIGUSR1
+pub fn on_sigusr1() -> AdHoc {
+    AdHoc::on_liftoff("Drain on SIGUSR1", |rocket| {
+        Box::pin(async move {
+            let drain = rocket.state::<Drain>().unwrap().clone();
+            let shutdown = rocket.shutdown();
+            let mut signals = match signal(SignalKind::user_defined1()) {
+                Ok(signals) => signals,
+                Err(err) => {
+                    error!("could not listen for SIGUSR1: {}", err);
+                    return;
+                }
+            };
+            rocket::tokio::spawn(async move {
+                if signals.recv().await.is_some() {
+                    info!("received SIGUSR1");
+                    drain.start(None, shutdown);
+                }
+            });
+        })
+    })
+}
+
+/// number of unfinished jobs of all clients
+async fn remaining_jobs(job_pools: &RwLock<HashMap<String, Arc<JobPool>>>) -> usize {
+    let pools: Vec<Arc<JobPool>> = job_pools.read().unwrap().values().cloned().collect();
+    let mut remaining = 0;
+    for pool in pools {
+        remaining += pool.unfinished_jobs().await;
+    }
+    remaining
+}
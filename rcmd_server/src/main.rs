@@ -1,21 +1,40 @@
-use std::{collections::HashMap, env, net::IpAddr, str::FromStr};
+use std::{collections::HashMap, env, io::Cursor, net::IpAddr, str::FromStr};
 
-use rcmd_lib::job_pool::{JobOutput, JobSpec, JobStatus};
+use rcmd_data::{parse_duration, DrainStatus};
+use rcmd_lib::{
+    job_pool::{
+        JobInfo, JobOutput, JobSpec, JobStatus, OutputChunk, OutputLine, OutputRange, OutputStream,
+        Priority, UsageSummary, Workflow,
+    },
+    schedule_pool::{ScheduleInfo, ScheduleSpec},
+    signal,
+};
 use rocket::{
     config::{CipherSuite, MutualTls, TlsConfig},
-    http::Status,
-    response::status,
+    data::{Data, ToByteUnit},
+    fairing::AdHoc,
+    http::{ContentType, Header, Status},
+    response::{
+        status,
+        stream::{Event, EventStream},
+    },
     serde::json::Json,
-    Config,
+    Build, Config, Rocket, Shutdown, State,
 };
 use state::JobPools;
 
-use crate::auth::ClientJobPool;
+use crate::{
+    auth::{Admin, Admins, ClientJobPool, ClientSchedulePool},
+    config::ServerConfig,
+    drain::{Drain, Unavailable},
+};
 
 #[macro_use]
 extern crate rocket;
 
 mod auth;
+mod config;
+mod drain;
 mod state;
 
 #[get("/")]
@@ -23,26 +42,293 @@ fn index(client: ClientJobPool) -> String {
     format!("Hello, {}!", client.client.name)
 }
 
+/// rejection of a submission that is invalid, or made while the server drains
+#[derive(Responder)]
+enum SubmitError {
+    Invalid(status::Custom<String>),
+    Unavailable(Unavailable),
+}
+
+impl From<Unavailable> for SubmitError {
+    fn from(unavailable: Unavailable) -> Self {
+        Self::Unavailable(unavailable)
+    }
+}
+
 #[post("/jobs", format = "json", data = "<job_spec>")]
-async fn start_job(client_job_pool: ClientJobPool, job_spec: Json<JobSpec>) -> Json<u64> {
-    let command = &job_spec.command;
-    let args: Vec<&str> = job_spec.arguments.iter().map(|arg| arg.as_str()).collect();
-    Json(client_job_pool.job_pool.submit(command, &args).await)
+async fn start_job(
+    client_job_pool: ClientJobPool,
+    drain: &State<Drain>,
+    job_spec: Json<JobSpec>,
+) -> Result<Json<u64>, SubmitError> {
+    drain.check()?;
+    match client_job_pool
+        .job_pool
+        .submit_spec(job_spec.into_inner())
+        .await
+    {
+        Ok(id) => Ok(Json(id)),
+        Err(err) => Err(SubmitError::Invalid(status::Custom(
+            Status::BadRequest,
+            err,
+        ))),
+    }
 }
 
-#[get("/jobs")]
-async fn get_jobs(client_job_pool: ClientJobPool) -> Json<HashMap<u64, JobSpec>> {
-    Json(client_job_pool.job_pool.list().await)
+/// submits jobs depending on each other by name, returns their ids by name
+#[post("/workflows", format = "json", data = "<workflow>")]
+async fn start_workflow(
+    client_job_pool: ClientJobPool,
+    drain: &State<Drain>,
+    workflow: Json<Workflow>,
+) -> Result<Json<HashMap<String, u64>>, SubmitError> {
+    drain.check()?;
+    match client_job_pool
+        .job_pool
+        .submit_workflow(workflow.into_inner())
+        .await
+    {
+        Ok(ids) => Ok(Json(ids)),
+        Err(err) => Err(SubmitError::Invalid(status::Custom(
+            Status::BadRequest,
+            err,
+        ))),
+    }
+}
+
+/// creates a schedule submitting a job when its cron expression matches or once at the given time
+#[post("/schedules", format = "json", data = "<schedule_spec>")]
+async fn create_schedule(
+    client_schedule_pool: ClientSchedulePool,
+    drain: &State<Drain>,
+    schedule_spec: Json<ScheduleSpec>,
+) -> Result<Json<u64>, SubmitError> {
+    drain.check()?;
+    match client_schedule_pool
+        .schedule_pool
+        .create(schedule_spec.into_inner())
+        .await
+    {
+        Ok(id) => Ok(Json(id)),
+        Err(err) => Err(SubmitError::Invalid(status::Custom(
+            Status::BadRequest,
+            err,
+        ))),
+    }
 }
 
+#[get("/schedules")]
+async fn get_schedules(
+    client_schedule_pool: ClientSchedulePool,
+) -> Json<HashMap<u64, ScheduleInfo>> {
+    Json(client_schedule_pool.schedule_pool.list().await)
+}
+
+/// state of the schedule along with the jobs it submitted
+#[get("/schedules/<id>")]
+async fn get_schedule(
+    client_schedule_pool: ClientSchedulePool,
+    id: u64,
+) -> Option<Json<ScheduleInfo>> {
+    client_schedule_pool.schedule_pool.info(id).await.map(Json)
+}
+
+#[post("/schedules/<id>/pause")]
+async fn pause_schedule(client_schedule_pool: ClientSchedulePool, id: u64) -> Option<()> {
+    client_schedule_pool.schedule_pool.pause(id).await
+}
+
+#[post("/schedules/<id>/resume")]
+async fn resume_schedule(client_schedule_pool: ClientSchedulePool, id: u64) -> Option<()> {
+    client_schedule_pool.schedule_pool.resume(id).await
+}
+
+/// deletes the schedule, the jobs it submitted are kept
+#[delete("/schedules/<id>")]
+async fn delete_schedule(client_schedule_pool: ClientSchedulePool, id: u64) -> Option<()> {
+    client_schedule_pool.schedule_pool.delete(id).await
+}
+
+/// specs of all jobs, or their full status documents if detailed
+#[derive(Responder)]
+enum JobList {
+    Specs(Json<HashMap<u64, JobSpec>>),
+    Detailed(Json<HashMap<u64, JobInfo>>),
+}
+
+#[get("/jobs?<detailed>")]
+async fn get_jobs(client_job_pool: ClientJobPool, detailed: Option<bool>) -> JobList {
+    let job_pool = client_job_pool.job_pool;
+    if detailed.unwrap_or(false) {
+        JobList::Detailed(Json(job_pool.list_info().await))
+    } else {
+        JobList::Specs(Json(job_pool.list().await))
+    }
+}
+
+/// status of the job along with its pid, timestamps and submitting client
 #[get("/jobs/<id>/status")]
-async fn get_status(client_job_pool: ClientJobPool, id: u64) -> Option<Json<JobStatus>> {
-    client_job_pool.job_pool.status(id).await.map(Json)
+async fn get_status(client_job_pool: ClientJobPool, id: u64) -> Option<Json<JobInfo>> {
+    client_job_pool.job_pool.info(id).await.map(Json)
+}
+
+/// resources used by all finished jobs of the client, including deleted ones
+#[get("/usage")]
+fn get_usage(client_job_pool: ClientJobPool) -> Json<UsageSummary> {
+    Json(client_job_pool.job_pool.usage_summary())
+}
+
+/// whole output of a job, or part of one stream if any range parameter is given
+#[derive(Responder)]
+enum OutputResponse {
+    Full(Json<JobOutput>),
+    Range(Json<OutputChunk>),
+}
+
+/// part of one stream as is, for piping it into other programs
+#[derive(Responder)]
+#[response(content_type = "binary")]
+struct RawOutput {
+    data: Vec<u8>,
+    next_offset: Header<'static>,
+    size: Header<'static>,
+}
+
+/// selects stream (default stdout) and range from query parameters, None if none was given
+/// <from> and <limit> select a byte range, <head> and <tail> a number of lines
+fn parse_output_range(
+    stream: Option<&str>,
+    from: Option<u64>,
+    limit: Option<u64>,
+    head: Option<usize>,
+    tail: Option<usize>,
+) -> Result<Option<(OutputStream, OutputRange)>, String> {
+    let range = match (from, limit, head, tail) {
+        (None, None, None, None) if stream.is_none() => return Ok(None),
+        (_, _, None, None) => OutputRange::Bytes {
+            from: from.unwrap_or(0),
+            limit,
+        },
+        (None, None, Some(head), None) => OutputRange::Head(head),
+        (None, None, None, Some(tail)) => OutputRange::Tail(tail),
+        _ => {
+            return Err(
+                "only one of byte range (from/limit), head and tail can be given".to_string(),
+            )
+        }
+    };
+    let stream = stream.map(str::parse).transpose()?;
+    Ok(Some((stream.unwrap_or(OutputStream::Stdout), range)))
+}
+
+#[get("/jobs/<id>/output?<stream>&<from>&<limit>&<head>&<tail>")]
+async fn get_output(
+    client_job_pool: ClientJobPool,
+    id: u64,
+    stream: Option<&str>,
+    from: Option<u64>,
+    limit: Option<u64>,
+    head: Option<usize>,
+    tail: Option<usize>,
+) -> Option<Result<OutputResponse, status::Custom<String>>> {
+    let job_pool = client_job_pool.job_pool;
+    let (stream, range) = match parse_output_range(stream, from, limit, head, tail) {
+        Ok(Some(range)) => range,
+        Ok(None) => {
+            return job_pool
+                .output(id)
+                .await
+                .map(|output| Ok(OutputResponse::Full(Json(output))))
+        }
+        Err(err) => return Some(Err(status::Custom(Status::BadRequest, err))),
+    };
+    match job_pool.output_range(id, stream, range).await? {
+        Ok(chunk) => Some(Ok(OutputResponse::Range(Json(chunk)))),
+        Err(err) => Some(Err(status::Custom(Status::InternalServerError, err))),
+    }
 }
 
-#[get("/jobs/<id>/output")]
-async fn get_output(client_job_pool: ClientJobPool, id: u64) -> Option<Json<JobOutput>> {
-    client_job_pool.job_pool.output(id).await.map(Json)
+/// same parameters as for the output, but returns the raw bytes of one stream (default stdout)
+/// the offset to continue from and the stream's size are returned as headers
+#[get("/jobs/<id>/output/raw?<stream>&<from>&<limit>&<head>&<tail>")]
+async fn get_raw_output(
+    client_job_pool: ClientJobPool,
+    id: u64,
+    stream: Option<&str>,
+    from: Option<u64>,
+    limit: Option<u64>,
+    head: Option<usize>,
+    tail: Option<usize>,
+) -> Option<Result<RawOutput, status::Custom<String>>> {
+    let (stream, range) = match parse_output_range(stream, from, limit, head, tail) {
+        Ok(range) => range.unwrap_or((
+            OutputStream::Stdout,
+            OutputRange::Bytes {
+                from: 0,
+                limit: None,
+            },
+        )),
+        Err(err) => return Some(Err(status::Custom(Status::BadRequest, err))),
+    };
+    match client_job_pool
+        .job_pool
+        .output_range(id, stream, range)
+        .await?
+    {
+        Ok(chunk) => Some(Ok(RawOutput {
+            data: chunk.data,
+            next_offset: Header::new("Rcmd-Next-Offset", chunk.next_offset.to_string()),
+            size: Header::new("Rcmd-Size", chunk.size.to_string()),
+        })),
+        Err(err) => Some(Err(status::Custom(Status::InternalServerError, err))),
+    }
+}
+
+/// output of an attempt of a retried job, the current attempt included
+#[get("/jobs/<id>/attempts/<attempt>/output")]
+async fn get_attempt_output(
+    client_job_pool: ClientJobPool,
+    id: u64,
+    attempt: u32,
+) -> Option<Result<Json<JobOutput>, status::Custom<String>>> {
+    match client_job_pool.job_pool.attempt_output(id, attempt).await? {
+        Ok(output) => Some(Ok(Json(output))),
+        Err(err) => Some(Err(status::Custom(Status::NotFound, err))),
+    }
+}
+
+/// stdout and stderr interleaved in the order they were produced, each line with its time and stream
+#[get("/jobs/<id>/output/combined")]
+async fn get_combined_output(
+    client_job_pool: ClientJobPool,
+    id: u64,
+) -> Option<Result<Json<Vec<OutputLine>>, status::Custom<String>>> {
+    match client_job_pool.job_pool.combined_output(id).await? {
+        Ok(lines) => Some(Ok(Json(lines))),
+        Err(err) => Some(Err(status::Custom(Status::InternalServerError, err))),
+    }
+}
+
+/// replays the output produced so far, then streams new output as server-sent events
+/// output is sent base64 encoded as "stdout" or "stderr" events, the final status as "exit" event
+#[get("/jobs/<id>/output/follow")]
+async fn follow_output(client_job_pool: ClientJobPool, id: u64) -> Option<EventStream![]> {
+    let mut follower = client_job_pool.job_pool.follow(id).await?;
+    Some(EventStream! {
+        while let Some(output) = follower.next().await {
+            for (stream, data) in output {
+                let name = match stream {
+                    OutputStream::Stdout => "stdout",
+                    OutputStream::Stderr => "stderr",
+                };
+                yield Event::data(base64::encode(data)).event(name);
+            }
+        }
+        // a job deleted while being followed has no final status
+        if let Some(status) = follower.status().await {
+            yield Event::json(&status).event("exit");
+        }
+    })
 }
 
 #[delete("/jobs/<id>")]
@@ -57,6 +343,145 @@ async fn delete_job(
     }
 }
 
+#[post("/jobs/<id>/stop?<signal>&<grace>")]
+async fn stop_job(
+    client_job_pool: ClientJobPool,
+    id: u64,
+    signal: Option<&str>,
+    grace: Option<&str>,
+) -> Option<Result<Json<JobStatus>, status::Custom<String>>> {
+    let signal = match signal.map(signal::parse).transpose() {
+        Ok(signal) => signal,
+        Err(err) => return Some(Err(status::Custom(Status::BadRequest, err))),
+    };
+    let grace = match grace.map(parse_duration).transpose() {
+        Ok(grace) => grace,
+        Err(err) => return Some(Err(status::Custom(Status::BadRequest, err))),
+    };
+    client_job_pool
+        .job_pool
+        .stop(id, signal, grace)
+        .await
+        .map(|status| Ok(Json(status)))
+}
+
+#[post("/jobs/<id>/signal?<signal>")]
+async fn signal_job(
+    client_job_pool: ClientJobPool,
+    id: u64,
+    signal: &str,
+) -> Option<Result<(), status::Custom<String>>> {
+    let signal = match signal::parse(signal) {
+        Ok(signal) => signal,
+        Err(err) => return Some(Err(status::Custom(Status::BadRequest, err))),
+    };
+    match client_job_pool.job_pool.signal(id, signal).await {
+        Some(Ok(_)) => Some(Ok(())),
+        Some(Err(err)) => Some(Err(status::Custom(Status::Conflict, err))),
+        None => None,
+    }
+}
+
+/// changes the priority of a queued job
+#[post("/jobs/<id>/priority?<priority>")]
+async fn set_priority(
+    client_job_pool: ClientJobPool,
+    id: u64,
+    priority: &str,
+) -> Option<Result<(), status::Custom<String>>> {
+    let priority = match Priority::from_str(priority) {
+        Ok(priority) => priority,
+        Err(err) => return Some(Err(status::Custom(Status::BadRequest, err))),
+    };
+    match client_job_pool.job_pool.set_priority(id, priority).await {
+        Some(Ok(_)) => Some(Ok(())),
+        Some(Err(err)) => Some(Err(status::Custom(Status::Conflict, err))),
+        None => None,
+    }
+}
+
+#[post("/jobs/<id>/stdin", data = "<data>")]
+async fn write_stdin(
+    client_job_pool: ClientJobPool,
+    id: u64,
+    data: Data<'_>,
+) -> Option<Result<(), status::Custom<String>>> {
+    let data = match data.open(1.mebibytes()).into_bytes().await {
+        Ok(data) if data.is_complete() => data.into_inner(),
+        Ok(_) => {
+            let msg = "stdin data is limited to 1 MiB per request".to_string();
+            return Some(Err(status::Custom(Status::PayloadTooLarge, msg)));
+        }
+        Err(err) => return Some(Err(status::Custom(Status::BadRequest, err.to_string()))),
+    };
+    match client_job_pool.job_pool.write_stdin(id, data).await {
+        Some(Ok(_)) => Some(Ok(())),
+        Some(Err(err)) => Some(Err(status::Custom(Status::Conflict, err))),
+        None => None,
+    }
+}
+
+#[post("/jobs/<id>/stdin/close")]
+async fn close_stdin(
+    client_job_pool: ClientJobPool,
+    id: u64,
+) -> Option<Result<(), status::Custom<String>>> {
+    match client_job_pool.job_pool.close_stdin(id).await {
+        Some(Ok(_)) => Some(Ok(())),
+        Some(Err(err)) => Some(Err(status::Custom(Status::Conflict, err))),
+        None => None,
+    }
+}
+
+/// starts draining: new jobs are rejected and the server shuts down once all jobs finished,
+/// jobs still running after <timeout> (default from the config) are stopped
+#[post("/admin/drain?<timeout>")]
+async fn start_drain(
+    admin: Admin,
+    drain: &State<Drain>,
+    shutdown: Shutdown,
+    timeout: Option<&str>,
+) -> Result<Json<DrainStatus>, status::Custom<String>> {
+    let timeout = match timeout.map(parse_duration).transpose() {
+        Ok(timeout) => timeout,
+        Err(err) => return Err(status::Custom(Status::BadRequest, err)),
+    };
+    info!("drain requested by {}", admin.client.name);
+    drain.start(timeout, shutdown);
+    Ok(Json(drain.status().await))
+}
+
+/// whether the server drains and how many jobs remain
+#[get("/admin/drain")]
+async fn get_drain(_admin: Admin, drain: &State<Drain>) -> Json<DrainStatus> {
+    Json(drain.status().await)
+}
+
+/// fairing answering requests for jobs evicted by the retention policy with 410 instead of 404
+fn evicted_jobs() -> AdHoc {
+    AdHoc::on_response("Evicted jobs", |request, response| {
+        Box::pin(async move {
+            if response.status() != Status::NotFound || request.routed_segment(0) != Some("jobs") {
+                return;
+            }
+            let id = match request.param::<u64>(1) {
+                Some(Ok(id)) => id,
+                _ => return,
+            };
+            let client_job_pool = match request.guard::<ClientJobPool>().await.succeeded() {
+                Some(client_job_pool) => client_job_pool,
+                None => return,
+            };
+            if let Some(reason) = client_job_pool.job_pool.evicted(id) {
+                let body = format!("job {} was evicted: {}", id, reason);
+                response.set_status(Status::Gone);
+                response.set_header(ContentType::Plain);
+                response.set_sized_body(body.len(), Cursor::new(body));
+            }
+        })
+    })
+}
+
 #[launch]
 fn rocket() -> _ {
     let args: Vec<String> = env::args().collect();
@@ -77,9 +502,234 @@ fn rocket() -> _ {
         address: IpAddr::from_str("0.0.0.0").unwrap(),
         ..Default::default()
     };
+    server(rocket::custom(config), ServerConfig::load())
+}
 
-    rocket::custom(config).manage(JobPools::new()).mount(
-        "/",
-        routes![index, start_job, get_jobs, get_status, get_output, delete_job],
-    )
+/// mounts the routes on <rocket> and sets up the job pools, drain and fairings for them
+fn server(rocket: Rocket<Build>, server_config: ServerConfig) -> Rocket<Build> {
+    let pool_config = server_config.job_pool_config();
+    let client_pool_configs = server_config.client_job_pool_configs(&pool_config);
+    let job_pools = JobPools::new(pool_config, client_pool_configs);
+    job_pools.restore_pools();
+    let drain = Drain::new(&job_pools, server_config.drain_timeout);
+    let admins = Admins(server_config.admins.iter().cloned().collect());
+    let shutdown_grace = server_config.shutdown_grace;
+    // e.g. on Ctrl-C, the jobs' processes would otherwise outlive the server
+    let stop_jobs = AdHoc::on_shutdown("Stop jobs", move |rocket| {
+        Box::pin(async move {
+            if let Some(job_pools) = rocket.state::<JobPools>() {
+                job_pools.shutdown(shutdown_grace).await;
+            }
+        })
+    });
+
+    rocket
+        .manage(job_pools)
+        .manage(drain)
+        .manage(admins)
+        .attach(stop_jobs)
+        .attach(drain::on_sigusr1())
+        .attach(evicted_jobs())
+        .mount(
+            "/",
+            routes![
+                index,
+                start_job,
+                start_workflow,
+                get_jobs,
+                get_status,
+                get_usage,
+                get_output,
+                get_raw_output,
+                get_attempt_output,
+                get_combined_output,
+                follow_output,
+                delete_job,
+                stop_job,
+                signal_job,
+                set_priority,
+                write_stdin,
+                close_stdin,
+                create_schedule,
+                get_schedules,
+                get_schedule,
+                pause_schedule,
+                resume_schedule,
+                delete_schedule,
+                start_drain,
+                get_drain
+            ],
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use rcmd_data::{DrainStatus, JobInfo, JobSpec, JobStatus, Workflow};
+    use rocket::{
+        http::{Header, Status},
+        local::asynchronous::{Client, LocalRequest},
+        tokio::time::{sleep, timeout},
+    };
+
+    use crate::{config::ServerConfig, server, state::JobPools};
+
+    /// local client of a server with the given config, "admin" being its only admin
+    async fn client(config: ServerConfig) -> Client {
+        let config = ServerConfig {
+            admins: vec!["admin".to_string()],
+            ..config
+        };
+        Client::tracked(server(rocket::build(), config))
+            .await
+            .unwrap()
+    }
+
+    /// <request> made by the client with the given name
+    fn by<'c>(name: &str, request: LocalRequest<'c>) -> LocalRequest<'c> {
+        request.header(Header::new("Rcmd-Test-Client", name.to_string()))
+    }
+
+    /// status and id of the job submitted by the client with the given name
+    async fn submit(client: &Client, name: &str, command: &str, args: &[&str]) -> (Status, u64) {
+        let response = by(name, client.post("/jobs"))
+            .json(&JobSpec::new(command, args))
+            .dispatch()
+            .await;
+        let status = response.status();
+        (status, response.into_json().await.unwrap_or_default())
+    }
+
+    async fn drain_status(client: &Client) -> DrainStatus {
+        let response = by("admin", client.get("/admin/drain")).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        response.into_json().await.unwrap()
+    }
+
+    /// whether the server got shut down within <wait>
+    async fn shut_down(client: &Client, wait: Duration) -> bool {
+        timeout(wait, client.rocket().shutdown()).await.is_ok()
+    }
+
+    // testing that a draining server rejects submissions but keeps answering reads and exits after the last job
+    #[rocket::async_test]
+    async fn test_drain() {
+        let client = client(ServerConfig::default()).await;
+        let (status, id) = submit(&client, "drain", "sleep", &["1"]).await;
+        assert_eq!(Status::Ok, status);
+        let response = by("admin", client.post("/admin/drain")).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        let expected = DrainStatus {
+            draining: true,
+            remaining_jobs: 1,
+            deadline: None,
+        };
+        assert_eq!(Some(expected), response.into_json().await);
+
+        let response = by("drain", client.post("/jobs"))
+            .json(&JobSpec::new("echo", &["rejected"]))
+            .dispatch()
+            .await;
+        assert_eq!(Status::ServiceUnavailable, response.status());
+        assert_eq!(Some("60"), response.headers().get_one("Retry-After"));
+        let response = by("drain", client.get(format!("/jobs/{}/status", id)))
+            .dispatch()
+            .await;
+        assert_eq!(Status::Ok, response.status());
+        let info: JobInfo = response.into_json().await.unwrap();
+        assert_eq!(JobStatus::Running, info.status);
+        assert_eq!(1, drain_status(&client).await.remaining_jobs);
+
+        // a client showing up while draining gets a pool that does not accept jobs either
+        let response = by("late", client.get("/jobs")).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        let job_pools = client.rocket().state::<JobPools>().unwrap();
+        assert!(!job_pools.get_pool("late").unwrap().accepting());
+        assert!(!job_pools.get_pool("drain").unwrap().accepting());
+
+        assert!(!shut_down(&client, Duration::from_millis(100)).await);
+        assert!(shut_down(&client, Duration::from_secs(3)).await);
+        assert_eq!(0, drain_status(&client).await.remaining_jobs);
+    }
+
+    // testing that a drain with a timeout shuts the server down at its deadline
+    #[rocket::async_test]
+    async fn test_drain_deadline() {
+        let client = client(ServerConfig::default()).await;
+        let (_, id) = submit(&client, "deadline", "sleep", &["30"]).await;
+        let response = by("admin", client.post("/admin/drain?timeout=1s"))
+            .dispatch()
+            .await;
+        assert_eq!(Status::Ok, response.status());
+        assert!(drain_status(&client).await.deadline.is_some());
+        let (status, _) = submit(&client, "deadline", "echo", &["rejected"]).await;
+        assert_eq!(Status::ServiceUnavailable, status);
+        let response = by("deadline", client.post("/workflows"))
+            .json(&Workflow { jobs: Vec::new() })
+            .dispatch()
+            .await;
+        assert_eq!(Status::ServiceUnavailable, response.status());
+        assert_eq!(Some("1"), response.headers().get_one("Retry-After"));
+
+        assert!(shut_down(&client, Duration::from_secs(3)).await);
+        assert_eq!(1, drain_status(&client).await.remaining_jobs);
+        let response = by("deadline", client.delete(format!("/jobs/{}", id)))
+            .dispatch()
+            .await;
+        assert_eq!(Status::Ok, response.status());
+    }
+
+    // testing that requests for evicted jobs are answered with 410 and the reason, others still with 404
+    #[rocket::async_test]
+    async fn test_evicted_jobs() {
+        let config = ServerConfig {
+            max_finished_jobs: Some(1),
+            ..Default::default()
+        };
+        let client = client(config).await;
+        let (_, evicted) = submit(&client, "evict", "true", &[]).await;
+        let (_, kept) = submit(&client, "evict", "true", &[]).await;
+        sleep(Duration::from_millis(300)).await;
+        let job_pools = client.rocket().state::<JobPools>().unwrap();
+        assert_eq!(
+            1,
+            job_pools.get_pool("evict").unwrap().evict_finished().await
+        );
+
+        for path in &["status", "output"] {
+            let response = by("evict", client.get(format!("/jobs/{}/{}", evicted, path)))
+                .dispatch()
+                .await;
+            assert_eq!(Status::Gone, response.status());
+            let body = response.into_string().await.unwrap();
+            assert!(body.starts_with(&format!("job {} was evicted: ", evicted)));
+        }
+        let response = by("evict", client.get(format!("/jobs/{}/status", kept)))
+            .dispatch()
+            .await;
+        assert_eq!(Status::Ok, response.status());
+        // unknown jobs, other clients' jobs and other resources with the same id are still not found
+        for (name, path) in [
+            ("evict", "/jobs/99/status".to_string()),
+            ("other", format!("/jobs/{}/status", evicted)),
+            ("evict", format!("/schedules/{}", evicted)),
+        ] {
+            let response = by(name, client.get(path)).dispatch().await;
+            assert_eq!(Status::NotFound, response.status());
+        }
+    }
+
+    // testing that only admins can drain the server or see the drain's status
+    #[rocket::async_test]
+    async fn test_drain_admin_only() {
+        let client = client(ServerConfig::default()).await;
+        let response = by("someone", client.post("/admin/drain")).dispatch().await;
+        assert_eq!(Status::Forbidden, response.status());
+        let response = by("someone", client.get("/admin/drain")).dispatch().await;
+        assert_eq!(Status::Forbidden, response.status());
+        assert!(!drain_status(&client).await.draining);
+        let (status, _) = submit(&client, "someone", "true", &[]).await;
+        assert_eq!(Status::Ok, status);
+    }
 }
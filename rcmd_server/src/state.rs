@@ -1,41 +1,106 @@
 use std::{
     collections::HashMap,
-    sync::{Arc, RwLock},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
 };
 
-use rcmd_lib::job_pool::JobPool;
+use rcmd_lib::{
+    job_pool::{JobPool, JobPoolConfig},
+    schedule_pool::SchedulePool,
+};
 
 pub struct JobPools {
     pub job_pools: Arc<RwLock<HashMap<String, Arc<JobPool>>>>,
+    // cleared once the server drains, pools created afterwards do not accept jobs either
+    pub accepting: Arc<AtomicBool>,
+    // created on the first request concerning schedules, submitting to the client's job pool
+    schedule_pools: Arc<RwLock<HashMap<String, Arc<SchedulePool>>>>,
+    pool_config: JobPoolConfig,
+    // for clients with their own settings
+    client_pool_configs: HashMap<String, JobPoolConfig>,
 }
 
 impl JobPools {
-    pub fn new() -> Self {
+    pub fn new(
+        pool_config: JobPoolConfig,
+        client_pool_configs: HashMap<String, JobPoolConfig>,
+    ) -> Self {
         Self {
             job_pools: Arc::new(RwLock::new(HashMap::new())),
+            accepting: Arc::new(AtomicBool::new(true)),
+            schedule_pools: Arc::new(RwLock::new(HashMap::new())),
+            pool_config,
+            client_pool_configs,
         }
     }
 
-    pub fn has_pool(&self, client: &str) -> bool {
-        self.job_pools.read().unwrap().contains_key(client)
-    }
-
     pub fn get_pool(&self, client: &str) -> Option<Arc<JobPool>> {
         self.job_pools.read().unwrap().get(client).cloned()
     }
 
-    pub fn create_pool(&self, client: &str) -> Arc<JobPool> {
-        let pool = JobPool::new();
+    /// gets the pool of a client, creating it on its first use
+    /// created under the write lock, as two pools restoring the same stored jobs would reuse their ids
+    pub fn pool(&self, client: &str) -> Arc<JobPool> {
+        if let Some(pool) = self.get_pool(client) {
+            return pool;
+        }
         self.job_pools
             .write()
             .unwrap()
-            .insert(client.to_string(), Arc::new(pool));
-        self.get_pool(client).unwrap()
+            .entry(client.to_string())
+            .or_insert_with(|| {
+                let config = self
+                    .client_pool_configs
+                    .get(client)
+                    .unwrap_or(&self.pool_config);
+                let pool = JobPool::with_config(client, config.clone());
+                // checked under the write lock, so a drain stops this pool or the pool sees the drain
+                if !self.accepting.load(Ordering::SeqCst) {
+                    pool.stop_accepting();
+                }
+                Arc::new(pool)
+            })
+            .clone()
+    }
+
+    /// creates the pools of all clients with stored jobs, so jobs lost by a restart are marked right away
+    pub fn restore_pools(&self) {
+        for client in JobPool::stored_pools(&self.pool_config) {
+            self.pool(&client);
+        }
+    }
+
+    /// stops the jobs of all clients with the given grace period and waits until their processes are gone
+    pub async fn shutdown(&self, grace: Option<Duration>) {
+        let pools: Vec<Arc<JobPool>> = self.job_pools.read().unwrap().values().cloned().collect();
+        // shut down concurrently, so it takes at most one grace period
+        let handles: Vec<_> = pools
+            .into_iter()
+            .map(|pool| rocket::tokio::spawn(async move { pool.shutdown(grace).await }))
+            .collect();
+        for handle in handles {
+            if let Err(err) = handle.await {
+                error!("could not shut down job pool: {}", err);
+            }
+        }
+    }
+
+    /// gets the schedule pool of a client, creating it on top of the client's <job_pool> if needed
+    pub fn schedule_pool(&self, client: &str, job_pool: Arc<JobPool>) -> Arc<SchedulePool> {
+        self.schedule_pools
+            .write()
+            .unwrap()
+            .entry(client.to_string())
+            .or_insert_with(|| Arc::new(SchedulePool::new(job_pool)))
+            .clone()
     }
 }
 
 impl Default for JobPools {
     fn default() -> Self {
-        Self::new()
+        Self::new(JobPoolConfig::default(), HashMap::new())
     }
 }